@@ -1,9 +1,32 @@
+use std::process::Command;
+
 use vergen::EmitBuilder;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     EmitBuilder::builder()
         .git_sha(true)
         .cargo_opt_level()
+        .cargo_target_triple()
         .emit()?;
+    println!("cargo:rustc-env=MCDL_RUSTC_CHANNEL={}", rustc_channel());
     Ok(())
 }
+
+/// The release channel of the compiler, read from its version, e.g. `rustc 1.75.0-nightly (...)`
+fn rustc_channel() -> &'static str {
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .unwrap_or_default();
+
+    match version.split_whitespace().nth(1) {
+        Some(release) if release.contains("-nightly") => "nightly",
+        Some(release) if release.contains("-beta") => "beta",
+        Some(release) if release.ends_with("-dev") => "dev",
+        Some(_) => "stable",
+        None => "unknown",
+    }
+}
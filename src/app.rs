@@ -1,33 +1,64 @@
-use std::borrow::Cow;
-use std::ffi::OsString;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::Infallible;
+use std::ffi::{OsStr, OsString};
+use std::future::Future;
+use std::io::IsTerminal;
+use std::net::IpAddr;
+use std::path::{Component, Path, PathBuf};
+use std::process::Stdio;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use color_eyre::eyre::{self, eyre, Result, WrapErr};
-use dialoguer::Confirm;
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use derive_more::derive::Display;
+use dialoguer::{Confirm, Input, Select};
+use directories::BaseDirs;
 use itertools::Itertools;
 use lazy_static::lazy_static;
+use parking_lot::Mutex;
+use regex::Regex;
+use serde::Serialize;
 use tokio::fs;
-use tokio::process::Command;
-use tokio::task::JoinSet;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, BufReader};
+use tokio::process::{ChildStdin, Command};
+use tokio::sync::Semaphore;
+use tokio::task::{self, JoinSet};
 use tracing::{debug, error, info, instrument, warn};
 
-use crate::common::{LOG_BASE_DIR, META, PROJ_DIRS, REQWEST_CLIENT};
-use crate::types::meta::{InstanceMeta, InstanceSettings};
-use crate::types::version::{GameVersion, VersionMetadata, VersionNumber};
-use crate::utils::net::{download_jre, get_version_metadata};
+use crate::common::{CONFIG, CONFIG_PATH, LOG_BASE_DIR, META, PROJ_DIRS, REQWEST_CLIENT};
+use crate::types::adoptium::Platform;
+use crate::types::checksum::{verify, Checksum};
+use crate::types::config::Config;
+use crate::types::mappings::{Mappings, MappingsFormat};
+use crate::types::meta::{
+    AppMeta, InstanceManifest, InstanceMeta, InstanceServerSettings, InstanceSettings,
+    PREFER_IPV6_ARG,
+};
+use crate::types::properties::ServerProperties;
+use crate::types::run_state::RunState;
+use crate::types::service::{ServiceDefinition, ServiceKind};
+use crate::types::version::{
+    AssetIndexContents, AssetObject, GameVersion, GameVersionList, Library, VersionDownload,
+    VersionMetadata, VersionNumber,
+};
+use crate::utils::fs::{copy_dir_all, copy_dir_except, dir_size, symlink_dir, temp_path};
+use crate::utils::net::{
+    download_jre, download_jre_for, get_version_metadata, https_proxy, probe_services,
+};
+use crate::utils::progress::{progress_group, Progress};
 
 lazy_static! {
     static ref INSTANCE_BASE_DIR: PathBuf = PROJ_DIRS.data_local_dir().join("instance");
     static ref JRE_BASE_DIR: PathBuf = PROJ_DIRS.data_local_dir().join("jre");
+    /// Shared by all client instances, since most assets are the same between versions
+    static ref ASSET_OBJECTS_DIR: PathBuf =
+        PROJ_DIRS.data_local_dir().join("assets").join("objects");
     static ref INSTANCE_SETTINGS_BASE_DIR: PathBuf = PROJ_DIRS.config_local_dir().join("instance");
-    static ref PB_STYLE: ProgressStyle = ProgressStyle::with_template(
-        "{prefix:.bold.blue.bright} {spinner:.green.bright} {wide_msg}",
-    )
-    .unwrap()
-    .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏-");
+    /// Holds a run state for each instance whose server is running
+    static ref RUN_STATE_DIR: PathBuf = PROJ_DIRS.data_local_dir().join("run");
 }
 
 macro_rules! META {
@@ -36,51 +67,353 @@ macro_rules! META {
     };
 }
 
+/// Options that apply to every version in an install
+#[derive(Debug, Clone, Default)]
+pub(crate) struct InstallOptions {
+    /// The maximum number of versions to install at the same time
+    pub concurrency: usize,
+    /// Whether to write `eula=true` to `eula.txt`
+    pub accept_eula: bool,
+    /// Whether to write `eula.txt` at all, which servers such as proxies don't use
+    pub eula_file: bool,
+    /// An existing world directory to use as the instance's world
+    pub world: Option<PathBuf>,
+    /// Whether to symlink the world instead of copying it
+    pub link_world: bool,
+    /// Whether to install the JRE inside the instance directory instead of sharing it
+    pub bundled_jre: bool,
+    /// Whether to use the system Java instead of installing a JRE
+    pub system_java: bool,
+    /// Whether to continue installing after a failure, reporting all failures at the end
+    pub keep_going: bool,
+    /// Whether to check for enough free disk space before downloading anything
+    pub space_check: bool,
+    /// A server jar to use instead of the one from the manifest
+    pub server_jar: Option<ServerJar>,
+    /// The expected checksum of `server_jar`, if known
+    pub server_jar_checksum: Option<Checksum>,
+    /// The heap size to give the server, overriding `-Xms`/`-Xmx` in the default JVM args
+    pub memory: Option<String>,
+    /// A shell command to run in the instance directory after the install
+    pub after_install: Option<String>,
+    /// Whether a failing `after_install` command should only be logged
+    pub ignore_hook_errors: bool,
+    /// The format to store the server mappings in, if they should be downloaded
+    pub mappings: Option<MappingsFormat>,
+    /// A directory to create the instance in, instead of the instance base directory
+    pub output_dir: Option<PathBuf>,
+    /// A template for instance names, instead of naming them by version ID
+    pub name: Option<String>,
+    /// Tags to give each installed instance
+    pub tags: Vec<String>,
+    /// Whether to install the client jar instead of the server
+    pub client: bool,
+    /// Whether to also download the client's libraries and asset index
+    pub client_libraries: bool,
+    /// Whether to also download the client's assets into the shared asset store
+    pub assets: bool,
+    /// The address the server listens on
+    pub bind: Option<IpAddr>,
+    /// Whether the server accepts IPv6 connections as well as IPv4
+    pub ipv6: bool,
+}
+
+impl InstallOptions {
+    /// The directory the instance `name` will be installed to
+    pub fn instance_dir(&self, name: &str) -> PathBuf {
+        self.output_dir
+            .clone()
+            .unwrap_or_else(|| INSTANCE_BASE_DIR.join(name))
+    }
+}
+
+/// A server jar from somewhere other than Mojang, e.g. a patched build
+#[derive(Debug, Clone, Display)]
+pub(crate) enum ServerJar {
+    #[display("{}", _0.display())]
+    File(PathBuf),
+    #[display("{_0}")]
+    Url(String),
+}
+
+impl FromStr for ServerJar {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.starts_with("http://") || s.starts_with("https://") {
+            Ok(ServerJar::Url(s.to_string()))
+        } else {
+            Ok(ServerJar::File(PathBuf::from(s)))
+        }
+    }
+}
+
+impl ServerJar {
+    /// Checks that the jar is a readable file or a reachable URL
+    ///
+    /// File paths are made absolute, so they can be recorded in the metadata
+    #[instrument(err, ret(level = "debug"))]
+    async fn validate(self) -> Result<Self> {
+        match self {
+            ServerJar::File(path) => {
+                let path = fs::canonicalize(&path)
+                    .await
+                    .wrap_err(format!("Server jar {} does not exist", path.display()))?;
+                if !fs::metadata(&path).await?.is_file() {
+                    return Err(eyre!("Server jar {} is not a file", path.display()));
+                }
+                fs::File::open(&path)
+                    .await
+                    .wrap_err(format!("Server jar {} is not readable", path.display()))?;
+
+                Ok(ServerJar::File(path))
+            }
+            ServerJar::Url(url) => {
+                REQWEST_CLIENT
+                    .head(&url)
+                    .send()
+                    .await
+                    .and_then(reqwest::Response::error_for_status)
+                    .wrap_err(format!("Server jar {url} is not reachable"))?;
+
+                Ok(ServerJar::Url(url))
+            }
+        }
+    }
+
+    async fn fetch(&self) -> Result<Bytes> {
+        match self {
+            ServerJar::File(path) => Ok(fs::read(path)
+                .await
+                .wrap_err(format!("Failed to read server jar {}", path.display()))?
+                .into()),
+            ServerJar::Url(url) => REQWEST_CLIENT
+                .get(url)
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .wrap_err(format!("Failed to download server jar {url}"))?
+                .bytes()
+                .await
+                .wrap_err("Failed to read server jar to bytes"),
+        }
+    }
+}
+
+/// Returned when an operation is cancelled with Ctrl-C, after cleaning up
+#[derive(Debug, Display)]
+#[display("Interrupted")]
+pub(crate) struct Interrupted;
+
+impl std::error::Error for Interrupted {}
+
+/// Something an install is creating, along with the files to remove if it never finishes
+#[derive(Debug)]
+enum PartialInstall {
+    Instance(String, Vec<PathBuf>),
+    Jre(u8, PathBuf),
+}
+
+impl PartialInstall {
+    /// Removes the files of installs that didn't make it into the metadata
+    fn remove_all(installs: &[PartialInstall], meta: &AppMeta) {
+        for install in installs {
+            let paths = match install {
+                PartialInstall::Instance(id, _) if meta.instance_installed(id) => continue,
+                PartialInstall::Jre(jre, _) if meta.jre_installed(jre) => continue,
+                PartialInstall::Instance(_, paths) => paths.clone(),
+                PartialInstall::Jre(_, path) => vec![path.clone(), partial_jre_dir(path)],
+            };
+
+            for path in paths.iter().filter(|p| p.exists()) {
+                debug!(path = %path.display(), "Removing partial install");
+                let result = if path.is_dir() {
+                    std::fs::remove_dir_all(path)
+                } else {
+                    std::fs::remove_file(path)
+                };
+                if let Err(e) = result {
+                    warn!(path = %path.display(), "Failed to remove partial install: {e}");
+                }
+            }
+        }
+    }
+}
+
 // ideally there is one public function for each subcommand
 
+/// Names the instances `versions` will be installed as, from the name template or by default
+///
+/// A version given more than once is one instance, so duplicates are dropped before naming
+pub(crate) fn plan_installs<'a>(
+    versions: Vec<&'a GameVersion>,
+    options: &InstallOptions,
+) -> Result<Vec<(&'a GameVersion, String)>> {
+    let versions = versions.into_iter().unique_by(|v| &v.id).collect_vec();
+
+    // version IDs could be anything for unusual versions, so they are validated like templates
+    let names = match &options.name {
+        Some(template) => versions
+            .iter()
+            .enumerate()
+            .map(|(i, version)| expand_name_template(template, version, i + 1))
+            .collect::<Result<Vec<_>>>()?,
+        // so a client doesn't take the name of the server for the same version
+        None if options.client => versions
+            .iter()
+            .map(|v| format!("{}-client", v.id))
+            .collect(),
+        None => versions.iter().map(|v| v.id.to_string()).collect(),
+    };
+    for name in &names {
+        validate_instance_name(name)?;
+    }
+
+    let installs = versions.into_iter().zip(names).collect_vec();
+
+    if let Some(name) = installs.iter().map(|(_, name)| name).duplicates().next() {
+        return Err(eyre!(
+            "More than one instance would be named `{name}`; use {{version}} or {{n}} in --name to tell them apart"
+        ));
+    }
+
+    Ok(installs)
+}
+
 #[instrument(err, ret(level = "debug"), skip(versions))]
-pub(crate) async fn install_versions(versions: Vec<&GameVersion>) -> Result<()> {
+pub(crate) async fn install_versions(
+    versions: Vec<&GameVersion>,
+    mut options: InstallOptions,
+) -> Result<()> {
     info!("Installing {} versions", versions.len());
+    let total = versions.len();
+
+    if let Some(world) = &options.world {
+        if !world.join("level.dat").is_file() {
+            return Err(eyre!(
+                "{} is not a world directory (missing level.dat)",
+                world.display()
+            ));
+        }
+
+        // links must not depend on the working directory
+        options.world = Some(
+            world
+                .canonicalize()
+                .wrap_err(format!("Failed to resolve world path {}", world.display()))?,
+        );
+    }
+
+    if let Some(jar) = options.server_jar.take() {
+        options.server_jar = Some(jar.validate().await?);
+    }
+
+    let (versions, names): (Vec<_>, Vec<_>) =
+        plan_installs(versions, &options)?.into_iter().unzip();
+    let dropped = total - versions.len();
+    if dropped > 0 {
+        warn!(dropped, "Ignoring duplicate versions");
+        eprintln!(
+            "Ignoring {dropped} duplicate version{}\n",
+            if dropped == 1 { "" } else { "s" }
+        );
+    }
+
+    if let Some(dir) = options.output_dir.take() {
+        options.output_dir = Some(validate_output_dir(&dir, &META.lock())?);
+    }
+
+    // fetched up front, so the space needed is known before anything is downloaded
+    let mut failed = vec![];
+    let mut fetched = Vec::with_capacity(versions.len());
+    for (version, name) in versions.into_iter().zip(names) {
+        match get_version_metadata(version).await {
+            Ok(version_meta) => fetched.push((version, version_meta, name)),
+            Err(e) if options.keep_going => {
+                let e = e.wrap_err(format!("Failed to install {}", version.id));
+                error!(name, "{e:?}");
+                failed.push((version.id.to_string(), e));
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    let (versions, version_metas, names): (Vec<_>, Vec<_>, Vec<_>) =
+        fetched.into_iter().multiunzip();
+
+    if options.space_check {
+        check_disk_space(space_needed(&version_metas, &names, &options)?)?;
+    }
 
     let mut install_threads = JoinSet::new();
-    let bars = MultiProgress::new();
+    let bars = progress_group();
+    // limits the number of downloads running at the same time
+    let permits = Arc::new(Semaphore::new(options.concurrency));
 
     let mut jres_installed: Vec<u8> = Vec::new();
+    // used to report which install each finished task belongs to
+    let mut task_names: HashMap<task::Id, String> = HashMap::new();
+    // cleaned up if the install is interrupted
+    let mut partial = vec![];
 
-    for version in versions {
+    for ((version, version_meta), name) in versions.into_iter().zip(version_metas).zip(names) {
         let version_display = version.id.to_string();
-        debug!(version = version_display, version.url, "Entering loop");
+        debug!(
+            version = version_display,
+            name, version.url, "Entering loop"
+        );
 
         let cloned_meta = META.clone();
-        let pb_server = bars.add(
-            ProgressBar::new_spinner()
-                .with_style(PB_STYLE.clone())
-                .with_prefix(version.id.to_string()),
-        );
-        pb_server.enable_steady_tick(Duration::from_millis(100));
+        let label = if name == version_display {
+            name.clone()
+        } else {
+            format!("{name} ({version_display})")
+        };
+        let pb_server = Progress::in_group(&bars, label);
 
-        pb_server.set_message("Getting version metadata...");
-        let version_meta: VersionMetadata = get_version_metadata(version).await?;
         let jre_version = version_meta.java_version.major_version;
 
+        // anything that already exists isn't ours to clean up
+        let instance_dir = options.instance_dir(&name);
+        let settings_path = INSTANCE_SETTINGS_BASE_DIR.join(format!("{name}.toml"));
+        let created: Vec<_> = [instance_dir.clone(), settings_path.clone()]
+            .into_iter()
+            .filter(|p| !p.exists())
+            .collect();
+        partial.push(PartialInstall::Instance(name.clone(), created.clone()));
+
         // spawn a thread to install the version
         let thread_version_display = version_meta.id.to_string();
-        install_threads.spawn(async move {
+        let thread_kind = version.kind().to_string();
+        let thread_permits = permits.clone();
+        let thread_options = options.clone();
+        let handle = install_threads.spawn(async move {
+            pb_server.set_message("Waiting...");
+            let _permit = thread_permits.acquire_owned().await?;
             debug!(version = thread_version_display, "Entering install thread");
 
-            if !version_meta.downloads.contains_key("server") {
-                pb_server.finish_with_message("Cancelled (no server jar)");
+            let jar_kind = if thread_options.client {
+                "client"
+            } else {
+                "server"
+            };
+            if let (None, Some(reason)) = (
+                &thread_options.server_jar,
+                version_meta.missing_jar_reason(jar_kind),
+            ) {
+                pb_server.finish_with_message(format!("Cancelled (no {jar_kind} jar)"));
                 debug!(
                     version = thread_version_display,
-                    "Exiting install thread (no server jar)"
+                    "Exiting install thread (no {jar_kind} jar)"
                 );
-                return Ok::<(), eyre::Report>(());
+                if thread_options.client {
+                    return Err(eyre!("Can't install {thread_version_display}: {reason}"));
+                }
+                return Err(eyre!(
+                    "Can't install {thread_version_display}: {reason}. Use --server-jar to install a jar from elsewhere"
+                ));
             }
 
-            let instance_dir = INSTANCE_BASE_DIR.join(version_meta.id.to_string());
-
-            // only necessary while there is one instance per version
-            if META.lock().instance_installed(&version_meta.id.to_string()) {
+            if META.lock().instance_installed(&name) {
                 pb_server.finish_with_message("Cancelled (already installed)");
                 debug!(
                     version = thread_version_display,
@@ -89,70 +422,200 @@ pub(crate) async fn install_versions(versions: Vec<&GameVersion>) -> Result<()>
                 return Ok::<(), eyre::Report>(());
             }
 
-            let url = version_meta
-                .downloads
-                .get("server")
-                .expect("infallible")
-                .url
-                .clone();
-
-            pb_server.set_message("Downloading server jar...");
-            let server_jar = REQWEST_CLIENT
-                .get(url)
-                .send()
-                .await
-                .wrap_err("Failed to download server jar")?
-                .bytes()
-                .await
-                .wrap_err("Failed to read server jar to bytes")?;
+            let (server_jar, checksum) = match &thread_options.server_jar {
+                Some(jar) => {
+                    pb_server.set_message("Getting custom server jar...");
+                    (
+                        jar.fetch().await?,
+                        thread_options.server_jar_checksum.clone(),
+                    )
+                }
+                None => {
+                    let download = version_meta.downloads.get(jar_kind).expect("infallible");
+
+                    pb_server.set_message(format!("Downloading {jar_kind} jar..."));
+                    let jar = REQWEST_CLIENT
+                        .get(&download.url)
+                        .send()
+                        .await
+                        .wrap_err(format!("Failed to download {jar_kind} jar"))?
+                        .bytes()
+                        .await
+                        .wrap_err(format!("Failed to read {jar_kind} jar to bytes"))?;
+                    (jar, Some(download.checksum()?))
+                }
+            };
+
+            if let Some(checksum) = &checksum {
+                pb_server.set_message(format!("Verifying {jar_kind} jar..."));
+                verify(&server_jar, checksum)
+                    .wrap_err(format!("Failed to verify {jar_kind} jar"))?;
+            }
 
             // write to disk
-            pb_server.set_message("Writing server jar to disk...");
+            pb_server.set_message(format!("Writing {jar_kind} jar to disk..."));
             fs::create_dir_all(&instance_dir).await.wrap_err(format!(
                 "Failed to create instance directory for {}",
                 version_meta.id
             ))?;
 
-            fs::write(instance_dir.join("server.jar"), server_jar)
+            let jar_path = PathBuf::from(format!("{jar_kind}.jar"));
+            fs::write(instance_dir.join(&jar_path), server_jar)
                 .await
                 .wrap_err(format!(
-                    "Failed to write server jar for {}",
+                    "Failed to write {jar_kind} jar for {}",
                     version_meta.id
                 ))?;
 
+            if thread_options.client_libraries {
+                install_client_libraries(&version_meta, &instance_dir, &pb_server)
+                    .await
+                    .wrap_err("Failed to install client libraries")?;
+            }
+            if thread_options.assets {
+                install_assets(&version_meta, &instance_dir, &pb_server)
+                    .await
+                    .wrap_err("Failed to install assets")?;
+            }
+
+            if let Some(format) = thread_options.mappings {
+                match version_meta.downloads.get("server_mappings") {
+                    Some(download) => {
+                        pb_server.set_message("Downloading mappings...");
+                        install_mappings(download, &instance_dir, format)
+                            .await
+                            .wrap_err("Failed to install server mappings")?;
+                    }
+                    None => warn!(
+                        version = thread_version_display,
+                        "No server mappings are published for this version"
+                    ),
+                }
+            }
+
             // write eula
-            pb_server.set_message("Writing eula.txt...");
-            fs::write(instance_dir.join("eula.txt"), "eula=true")
+            if thread_options.eula_file {
+                pb_server.set_message("Writing eula.txt...");
+                fs::write(
+                    instance_dir.join("eula.txt"),
+                    format!("eula={}", thread_options.accept_eula),
+                )
                 .await
                 .wrap_err(format!("Failed to write eula.txt for {}", version_meta.id))?;
+            }
+
+            // attach world
+            if let Some(world) = &thread_options.world {
+                pb_server.set_message("Attaching world...");
+                let target = instance_dir.join("world");
+                if thread_options.link_world {
+                    symlink_dir(world, &target)?;
+                } else {
+                    copy_dir_all(world, &target)?;
+                }
+
+                let properties_path = instance_dir.join("server.properties");
+                let mut properties = ServerProperties::read_or_default(&properties_path)?;
+                properties.set("level-name", "world");
+                properties.save(&properties_path)?;
+            }
+
+            if thread_options.bundled_jre {
+                install_bundled_jre(&jre_version, &instance_dir, &pb_server)
+                    .await
+                    .wrap_err(format!("Failed to install bundled JRE {jre_version}"))?;
+            }
 
             // write settings
             pb_server.set_message("Writing settings...");
-            let settings = InstanceSettings::new(jre_version);
-            let settings_path =
-                INSTANCE_SETTINGS_BASE_DIR.join(format!("{}.toml", version_meta.id));
-
+            let mut settings = InstanceSettings::new(jre_version);
+            settings.java.bundled = thread_options.bundled_jre;
+            settings.java.system = thread_options.system_java;
+            settings.server.jar = jar_path;
+            if let Some(memory) = &thread_options.memory {
+                settings.java.set_memory(memory);
+            }
+            settings.server.bind = thread_options.bind;
+            settings.server.ipv6 = thread_options.ipv6;
+            if !thread_options.client {
+                write_server_ip(&instance_dir, &settings.server)?;
+            }
             settings.save(&settings_path).await?;
+            InstanceManifest::new(version_meta.id.clone(), &thread_kind, jre_version)
+                .save(&instance_dir)?;
 
             // update meta
             pb_server.set_message("Updating metadata...");
             let mut instance_meta = InstanceMeta::new(version_meta.id, jre_version);
             instance_meta.add_file(&instance_dir);
             instance_meta.add_file(&settings_path);
+            instance_meta.custom_jar = thread_options.server_jar.as_ref().map(ToString::to_string);
+            instance_meta.location = thread_options.output_dir;
+            instance_meta.tags = thread_options.tags.into_iter().collect();
+            instance_meta.client = thread_options.client;
+            if thread_options.server_jar.is_none() {
+                instance_meta.download = version_meta.downloads.get(jar_kind).cloned();
+            }
 
-            let mut meta = cloned_meta.lock();
-            meta.add_instance(instance_meta);
-            meta.save()?;
+            {
+                let mut meta = AppMeta::begin_update(&cloned_meta)?;
+                meta.instances.insert(name.clone(), instance_meta);
+                meta.save()?;
+            }
 
-            pb_server.finish_with_message("Done!");
+            if let Some(hook) = &thread_options.after_install {
+                pb_server.set_message("Running after-install hook...");
+                let result = run_hook(
+                    hook,
+                    &instance_dir,
+                    &name,
+                    &thread_version_display,
+                    jre_version,
+                )
+                .await;
+                match result {
+                    Err(e) if thread_options.ignore_hook_errors => {
+                        warn!(
+                            version = thread_version_display,
+                            "Ignoring hook error: {e:?}"
+                        );
+                    }
+                    // the hook can use the instance, but it only stays installed if the hook succeeds
+                    Err(e) => {
+                        pb_server.finish_with_message("Failed (after-install hook)");
+                        let mut meta = AppMeta::begin_update(&cloned_meta)?;
+                        meta.remove_instance(&name);
+                        meta.save()?;
+                        PartialInstall::remove_all(&[PartialInstall::Instance(name, created)], &meta);
+                        return Err(e);
+                    }
+                    Ok(()) => {}
+                }
+            }
+
+            if thread_options.accept_eula || !thread_options.eula_file {
+                pb_server.finish_with_message("Done!");
+            } else {
+                pb_server.finish_with_message("Done! (EULA not accepted, edit eula.txt to run)");
+            }
 
             info!(version = thread_version_display, "Installed version");
             debug!(version = thread_version_display, "Exiting install thread");
             Ok::<(), eyre::Report>(())
         });
+        task_names.insert(handle.id(), version_display.clone());
 
-        // if the JRE is already installed, skip it
-        if META!().jre_installed(&jre_version) || jres_installed.contains(&jre_version) {
+        // if the JRE is bundled, provided by the system or already installed, skip it
+        if options.bundled_jre || options.system_java {
+            debug!(
+                jre = jre_version,
+                version = version_display,
+                bundled = options.bundled_jre,
+                "Skipping shared JRE install"
+            );
+            continue;
+        }
+        if shared_jre_installed(jre_version) || jres_installed.contains(&jre_version) {
             debug!(
                 jre = jre_version,
                 version = version_display,
@@ -163,279 +626,2998 @@ pub(crate) async fn install_versions(versions: Vec<&GameVersion>) -> Result<()>
 
         // otherwise, install it
         jres_installed.push(jre_version);
+        let jre_dir = JRE_BASE_DIR.join(jre_version.to_string());
+        if !jre_dir.exists() {
+            partial.push(PartialInstall::Jre(jre_version, jre_dir));
+        }
+
+        info!(
+            jre = jre_version,
+            version = version_display,
+            "Installing JRE"
+        );
+
+        let pb_jre = Progress::in_group(&bars, format!("JRE {jre_version} for {}", version.id));
+
+        // at the same time, spawn a thread to install the JRE
+        let thread_permits = permits.clone();
+        let handle = install_threads.spawn(async move {
+            pb_jre.set_message("Waiting...");
+            let _permit = thread_permits.acquire_owned().await?;
+            pb_jre.set_message("Installing JRE...");
+            install_jre(&jre_version, &pb_jre)
+                .await
+                .wrap_err(format!("Failed to install JRE {jre_version}"))?;
+
+            Ok::<(), eyre::Report>(())
+        });
+        task_names.insert(handle.id(), format!("JRE {jre_version}"));
+
+        debug!(version = version_display, version.url, "Exiting loop");
+    }
+
+    let mut succeeded = vec![];
+    let interrupt = tokio::signal::ctrl_c();
+    tokio::pin!(interrupt);
+    loop {
+        let result = tokio::select! {
+            result = install_threads.join_next_with_id() => match result {
+                Some(result) => result,
+                None => break,
+            },
+            Ok(()) = &mut interrupt => {
+                warn!("Interrupted, cleaning up");
+                install_threads.abort_all();
+                while install_threads.join_next().await.is_some() {}
+
+                bars.clear()?;
+                PartialInstall::remove_all(&partial, &META.lock());
+                return Err(Interrupted.into());
+            }
+        };
+
+        let (id, result) = match result {
+            Ok((id, result)) => (id, result),
+            Err(e) => (e.id(), Err(e.into())),
+        };
+        let name = task_names.remove(&id).unwrap_or_default();
+
+        match result.wrap_err(format!("Failed to install {name}")) {
+            Ok(()) => succeeded.push(name),
+            Err(e) if options.keep_going => {
+                error!(name, "{e:?}");
+                failed.push((name, e));
+            }
+            // dropping the join set aborts the remaining installs
+            Err(e) => return Err(e),
+        }
+    }
+
+    if options.keep_going {
+        println!();
+        if !succeeded.is_empty() {
+            println!("Installed: {}", succeeded.iter().sorted().join(", "));
+        }
+        if !failed.is_empty() {
+            println!("Failed:");
+            for (name, e) in &failed {
+                println!("  {name}: {e:#}");
+            }
+
+            return Err(eyre!(
+                "{} of {} installs failed",
+                failed.len(),
+                failed.len() + succeeded.len()
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// The directory of the instance `id`
+pub(crate) fn instance_dir(id: &str, instance: &InstanceMeta) -> PathBuf {
+    instance
+        .location
+        .clone()
+        .unwrap_or_else(|| INSTANCE_BASE_DIR.join(id))
+}
+
+/// The directory of an instance that runs a server, i.e. one that exists and isn't a client
+fn server_instance_dir(id: &str) -> Result<PathBuf> {
+    match META!().instances.get(id) {
+        Some(instance) if instance.client => Err(eyre!(
+            "Instance `{id}` is a client, which can only be started by a launcher"
+        )),
+        Some(instance) => Ok(instance_dir(id, instance)),
+        None => Err(eyre!("Instance `{id}` does not exist")),
+    }
+}
+
+/// Substitutes the placeholders in an instance name template for the `n`th version installed
+///
+/// The placeholders are `{version}`, `{kind}` (the release type, e.g. `snapshot`) and `{n}`,
+/// which counts from 1
+fn expand_name_template(template: &str, version: &GameVersion, n: usize) -> Result<String> {
+    let mut name = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        name.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| eyre!("Unclosed `{{` in name template `{template}`"))?
+            + start;
+
+        match &rest[start + 1..end] {
+            "version" => name.push_str(&version.id.to_string()),
+            "kind" => name.push_str(&version.release_type),
+            "n" => name.push_str(&n.to_string()),
+            other => {
+                return Err(eyre!(
+                    "Unknown placeholder `{{{other}}}` in name template `{template}`; \
+                     supported placeholders are {{version}}, {{kind}} and {{n}}"
+                ))
+            }
+        }
+        rest = &rest[end + 1..];
+    }
+    name.push_str(rest);
+
+    Ok(name)
+}
+
+/// Checks that `tag` is a valid instance tag, returning it for use as a clap value parser
+///
+/// Tags are given as comma-separated lists, so they are limited to a conservative set of characters
+pub(crate) fn validate_tag(tag: &str) -> Result<String> {
+    if tag.is_empty() {
+        return Err(eyre!("Tags must not be empty"));
+    }
+    if !tag
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(eyre!(
+            "Tag `{tag}` can only contain letters, numbers, `-`, `_` and `.`"
+        ));
+    }
+
+    Ok(tag.to_string())
+}
+
+/// Checks that `name` can be used as an instance directory and settings file name on this OS
+pub(crate) fn validate_instance_name(name: &str) -> Result<()> {
+    check_instance_name(name, cfg!(windows))
+}
+
+fn check_instance_name(name: &str, windows: bool) -> Result<()> {
+    const RESERVED: [&str; 22] = [
+        "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+        "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    ];
+
+    let unsafe_char = |c: char| {
+        c == '/'
+            || c == '\\'
+            || c.is_control()
+            || (windows && matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*'))
+    };
+    // `CON.txt` is reserved too
+    let reserved = |name: &str| {
+        let stem = name.split('.').next().unwrap_or_default().trim_end();
+        windows && RESERVED.iter().any(|r| r.eq_ignore_ascii_case(stem))
+    };
+    let bad_end = |name: &str| windows && name.ends_with(['.', ' ']);
+
+    let problem = if name.is_empty() || name.trim().is_empty() {
+        return Err(eyre!("Instance names must not be empty"));
+    } else if name == "." || name == ".." {
+        "is a relative path"
+    } else if name.contains(unsafe_char) {
+        "contains characters that aren't allowed in file names"
+    } else if reserved(name) {
+        "is a reserved device name"
+    } else if bad_end(name) {
+        "ends with a dot or space"
+    } else {
+        return Ok(());
+    };
+
+    let mut suggestion: String = name
+        .chars()
+        .map(|c| if unsafe_char(c) { '-' } else { c })
+        .collect();
+    if windows {
+        suggestion.truncate(suggestion.trim_end_matches(['.', ' ']).len());
+    }
+    if suggestion.is_empty() || suggestion == "." || suggestion == ".." || reserved(&suggestion) {
+        suggestion.insert_str(0, "instance-");
+    }
+
+    Err(eyre!(
+        "`{name}` is not a valid instance name: it {problem} (try `{suggestion}`)"
+    ))
+}
+
+/// Checks that `dir` can hold a new instance, which must be missing or empty
+///
+/// Returns the absolute path, so the instance can be found from any working directory
+#[instrument(err, skip(meta))]
+fn validate_output_dir(dir: &Path, meta: &AppMeta) -> Result<PathBuf> {
+    // uninstalling removes the whole directory, so it can't hold anything that isn't ours
+    let dir = if dir.exists() {
+        let non_empty = std::fs::read_dir(dir)
+            .wrap_err(format!("Failed to read output directory {}", dir.display()))?
+            .next()
+            .is_some();
+        if non_empty {
+            return Err(eyre!(
+                "{} is not empty, so it can't be used as an output directory",
+                dir.display()
+            ));
+        }
+        dir.canonicalize().wrap_err(format!(
+            "Failed to resolve output directory {}",
+            dir.display()
+        ))?
+    } else {
+        resolve_missing_dir(dir)?
+    };
+
+    let existing = meta
+        .instances
+        .iter()
+        .find(|(id, instance)| instance_dir(id, instance) == dir);
+    if let Some((id, _)) = existing {
+        return Err(eyre!(
+            "{} is already used by instance `{id}`",
+            dir.display()
+        ));
+    }
+
+    // creating a file is the only reliable way to check for write access
+    let writable = dir.ancestors().find(|a| a.exists()).expect("infallible");
+    let probe = writable.join(".mcdl-write-test");
+    std::fs::write(&probe, "")
+        .and_then(|()| std::fs::remove_file(&probe))
+        .wrap_err(format!(
+            "Output directory {} is not writable",
+            dir.display()
+        ))?;
+
+    Ok(dir)
+}
+
+/// Makes `dir`, which doesn't exist yet, absolute without creating it
+///
+/// The part that exists is canonicalized, and the rest is normalized by hand
+fn resolve_missing_dir(dir: &Path) -> Result<PathBuf> {
+    let dir = std::path::absolute(dir).wrap_err(format!(
+        "Failed to resolve output directory {}",
+        dir.display()
+    ))?;
+    let existing = dir.ancestors().find(|a| a.exists()).expect("infallible");
+
+    let mut resolved = existing.canonicalize().wrap_err(format!(
+        "Failed to resolve output directory {}",
+        dir.display()
+    ))?;
+    // nothing below `existing` exists, so there are no symlinks to make `..` ambiguous
+    for component in dir.strip_prefix(existing).expect("infallible").components() {
+        match component {
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::CurDir => {}
+            component => resolved.push(component),
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Interactively picks a version and heap size to install, for users who don't know version strings
+///
+/// Returns `None` if the user cancels at the final confirmation
+#[instrument(err, skip(manifest))]
+pub(crate) fn install_wizard(
+    manifest: &GameVersionList,
+) -> Result<Option<(&GameVersion, Option<String>)>> {
+    lazy_static! {
+        static ref MEMORY_RE: Regex = Regex::new(r"^[1-9]\d*[KMGkmg]?$").unwrap();
+    }
+    const SHOWN_RELEASES: usize = 15;
+
+    let releases = manifest
+        .versions
+        .iter()
+        .filter(|v| v.id.is_release())
+        .sorted()
+        .rev()
+        .take(SHOWN_RELEASES)
+        .collect_vec();
+
+    let mut items = releases
+        .iter()
+        .map(|v| {
+            if v.id == manifest.latest.release {
+                format!("{} (latest)", v.id)
+            } else {
+                v.id.to_string()
+            }
+        })
+        .collect_vec();
+    items.push("Other...".to_string());
+
+    let selected = Select::new()
+        .with_prompt("Which version would you like to install?")
+        .items(&items)
+        .default(0)
+        .interact()?;
+
+    let version = match releases.get(selected) {
+        Some(version) => *version,
+        None => {
+            let input: String = Input::new()
+                .with_prompt("Version")
+                .validate_with(|input: &String| -> Result<(), String> {
+                    input
+                        .parse::<VersionNumber>()
+                        .and_then(|v| v.resolve(manifest).map(|_| ()))
+                        .map_err(|e| e.to_string())
+                })
+                .interact_text()?;
+            input.parse::<VersionNumber>()?.resolve(manifest)?
+        }
+    };
+
+    let default_memory = CONFIG
+        .read()
+        .java_args
+        .iter()
+        .find_map(|arg| arg.strip_prefix("-Xmx"))
+        .unwrap_or("4G")
+        .to_string();
+    let memory: String = Input::new()
+        .with_prompt("Heap size (e.g. 4G)")
+        .default(default_memory.clone())
+        .validate_with(|input: &String| -> Result<(), &str> {
+            if MEMORY_RE.is_match(input) {
+                Ok(())
+            } else {
+                Err("Expected a size such as 512M or 4G")
+            }
+        })
+        .interact_text()?;
+    let memory = (memory != default_memory).then_some(memory);
+
+    let confirmed = Confirm::new()
+        .with_prompt(format!(
+            "Install {} ({}) with {} of memory?",
+            version.id,
+            version.release_type,
+            memory.as_deref().unwrap_or(&default_memory)
+        ))
+        .default(true)
+        .interact()?;
+
+    Ok(confirmed.then_some((version, memory)))
+}
+
+/// Runs a hook command through the shell in an instance directory
+///
+/// The instance is described by `MCDL_INSTANCE_DIR`, `MCDL_INSTANCE_ID` (its name),
+/// `MCDL_INSTANCE_VERSION` and `MCDL_JRE_VERSION`
+#[instrument(err, ret(level = "debug"))]
+async fn run_hook(
+    hook: &str,
+    instance_dir: &Path,
+    id: &str,
+    version: &str,
+    jre_version: u8,
+) -> Result<()> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+
+    let status = Command::new(shell)
+        .arg(flag)
+        .arg(hook)
+        .current_dir(instance_dir)
+        .env("MCDL_INSTANCE_DIR", instance_dir)
+        .env("MCDL_INSTANCE_ID", id)
+        .env("MCDL_INSTANCE_VERSION", version)
+        .env("MCDL_JRE_VERSION", jre_version.to_string())
+        .status()
+        .await
+        .wrap_err(format!("Failed to start hook `{hook}`"))?;
+
+    if !status.success() {
+        return Err(eyre!("Hook `{hook}` exited with {status}"));
+    }
+
+    Ok(())
+}
+
+/// Asks the user to accept the Minecraft EULA, optionally remembering the answer
+///
+/// Returns `false` without prompting if stdin is not a terminal
+#[instrument(err, ret(level = "debug"))]
+pub(crate) fn prompt_eula() -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        debug!("Not prompting for EULA (stdin is not a terminal)");
+        return Ok(false);
+    }
+
+    let accepted = Confirm::new()
+        .with_prompt("Do you agree to the Minecraft EULA (https://aka.ms/MinecraftEULA)?")
+        .default(false)
+        .interact()?;
+
+    if accepted
+        && Confirm::new()
+            .with_prompt("Remember this for future installs?")
+            .default(false)
+            .interact()?
+    {
+        // only persist the acceptance, not any env or CLI overrides
+        let mut config = if CONFIG_PATH.exists() {
+            Config::from_file(CONFIG_PATH.as_path())?
+        } else {
+            Config::default()
+        };
+        config.accept_eula = true;
+        config.save(CONFIG_PATH.as_path())?;
+        CONFIG.write().accept_eula = true;
+
+        println!("EULA acceptance saved to {}", CONFIG_PATH.display());
+    }
+
+    Ok(accepted)
+}
+
+// pub(crate) async fn install_version(version: &GameVersion) -> Result<()> {
+//     install_versions(vec![version]).await
+// }
+
+#[instrument(err, ret(level = "debug"), skip(pb))]
+async fn install_jre(major_version: &u8, pb: &Progress) -> Result<()> {
+    let jre_dir = JRE_BASE_DIR.join(major_version.to_string());
+
+    if shared_jre_installed(*major_version) {
+        pb.finish_with_message("Cancelled (already installed)");
+        debug!("Cancelled JRE install (this should never happen)");
+        return Ok(());
+    }
+    {
+        let mut meta = AppMeta::begin_update(&META)?;
+        if meta.remove_jre(major_version) {
+            // e.g. the directory was removed by hand, or an older mcdl was interrupted extracting it
+            warn!("JRE {major_version} is recorded as installed but is incomplete, reinstalling");
+            meta.save()?;
+        }
+    }
+
+    pb.set_message("Downloading JRE...");
+    info!("Starting JRE download");
+    let jre = download_jre(major_version, pb).await?;
+    info!("Downloaded JRE");
+
+    pb.set_message("Extracting JRE...");
+    info!("Starting JRE extraction");
+    extract_jre(jre, &jre_dir).wrap_err("Failed to extract JRE")?;
+    info!("Extracted JRE");
+
+    pb.set_message("Updating metadata...");
+    {
+        let mut meta = AppMeta::begin_update(&META)?;
+        meta.add_jre(*major_version);
+        meta.save()?;
+    }
+
+    pb.finish_with_message("Done!");
+    info!("Installed JRE");
+    Ok(())
+}
+
+/// How much space an extracted JRE takes up, roughly, since Adoptium only publishes archive sizes
+const JRE_SIZE_ESTIMATE: u64 = 200 * 1024 * 1024;
+
+/// Estimates the bytes an install will write, by the directory they will be written to
+///
+/// Sizes that aren't known before downloading, such as a custom jar from a URL, count as 0
+fn space_needed(
+    version_metas: &[VersionMetadata],
+    names: &[String],
+    options: &InstallOptions,
+) -> Result<Vec<(PathBuf, u64)>> {
+    let world_size = match &options.world {
+        Some(world) if !options.link_world => dir_size(world),
+        _ => 0,
+    };
+    let custom_jar_size = match &options.server_jar {
+        Some(ServerJar::File(path)) => std::fs::metadata(path)?.len(),
+        _ => 0,
+    };
+
+    let mut needed = vec![];
+    let mut shared_jres = BTreeSet::new();
+    // assets are shared, so each index is only counted once, and ones already stored are counted
+    // again since there's no cheap way to know which objects are missing
+    let mut asset_indexes = BTreeMap::new();
+    for (version_meta, name) in version_metas.iter().zip(names) {
+        let download_size = |key: &str| version_meta.downloads.get(key).map_or(0, |d| d.size);
+
+        let mut size = world_size;
+        size += match options.server_jar {
+            Some(_) => custom_jar_size,
+            None if options.client => download_size("client"),
+            None => download_size("server"),
+        };
+        if options.assets {
+            if let Some(index) = &version_meta.asset_index {
+                asset_indexes.insert(&index.id, index.total_size);
+            }
+        }
+        if options.client_libraries {
+            size += version_meta
+                .libraries
+                .iter()
+                .filter_map(Library::artifact)
+                .map(|artifact| artifact.download.size)
+                .sum::<u64>();
+            size += version_meta
+                .asset_index
+                .as_ref()
+                .map_or(0, |index| index.download.size);
+        }
+        if options.mappings.is_some() {
+            size += download_size("server_mappings");
+        }
+
+        let jre_version = version_meta.java_version.major_version;
+        if options.bundled_jre {
+            size += JRE_SIZE_ESTIMATE;
+        } else if !options.system_java && !shared_jre_installed(jre_version) {
+            shared_jres.insert(jre_version);
+        }
+
+        needed.push((options.instance_dir(name), size));
+    }
+    needed.push((
+        JRE_BASE_DIR.clone(),
+        shared_jres.len() as u64 * JRE_SIZE_ESTIMATE,
+    ));
+    needed.push((ASSET_OBJECTS_DIR.clone(), asset_indexes.values().sum()));
+
+    Ok(needed)
+}
+
+/// Checks that each filesystem has room for what `needed` will write to it
+#[instrument(err, ret(level = "debug"))]
+fn check_disk_space(needed: Vec<(PathBuf, u64)>) -> Result<()> {
+    // directories that don't exist yet will be created on the filesystem of their closest ancestor
+    let mut by_filesystem: BTreeMap<String, (PathBuf, u64)> = BTreeMap::new();
+    for (dir, size) in needed {
+        let Some(existing) = dir.ancestors().find(|p| p.exists()) else {
+            continue;
+        };
+        let entry = by_filesystem
+            .entry(filesystem_id(existing)?)
+            .or_insert_with(|| (existing.to_path_buf(), 0));
+        entry.1 += size;
+    }
+
+    for (dir, required) in by_filesystem.into_values() {
+        let available = fs2::available_space(&dir)
+            .wrap_err(format!("Failed to check free space in {}", dir.display()))?;
+        debug!(dir = %dir.display(), required, available, "Checked disk space");
+
+        if required > available {
+            return Err(eyre!(
+                "Not enough disk space in {}: {} needed, {} available. Use --no-space-check to install anyway",
+                dir.display(),
+                format_size(required),
+                format_size(available)
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Identifies the filesystem `path` is on, so that space needed on the same one is added up
+#[cfg(unix)]
+fn filesystem_id(path: &Path) -> Result<String> {
+    use std::os::unix::fs::MetadataExt;
+
+    Ok(std::fs::metadata(path)?.dev().to_string())
+}
+
+#[cfg(not(unix))]
+fn filesystem_id(path: &Path) -> Result<String> {
+    // the drive, e.g. `C:`
+    Ok(path
+        .canonicalize()?
+        .components()
+        .next()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .unwrap_or_default())
+}
+
+/// Downloads and verifies the ProGuard server mappings, converting them if requested
+#[instrument(err, skip(download))]
+async fn install_mappings(
+    download: &VersionDownload,
+    instance_dir: &Path,
+    format: MappingsFormat,
+) -> Result<()> {
+    let bytes = REQWEST_CLIENT
+        .get(&download.url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)?
+        .bytes()
+        .await?;
+    verify(&bytes, &download.checksum()?)?;
+
+    // parse even if not converting, so broken mappings are caught now rather than by other tools
+    let mappings: Mappings = std::str::from_utf8(&bytes)?.parse()?;
+    fs::write(instance_dir.join("server_mappings.txt"), &bytes).await?;
+
+    if format == MappingsFormat::Tiny {
+        fs::write(
+            instance_dir.join("server_mappings.tiny"),
+            mappings.to_tiny_v2(),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Downloads the client's libraries for this OS and its asset index, laid out like the
+/// launcher's `libraries` and `assets/indexes` directories
+#[instrument(err, ret(level = "debug"), skip_all, fields(version = %version_meta.id))]
+async fn install_client_libraries(
+    version_meta: &VersionMetadata,
+    instance_dir: &Path,
+    pb: &Progress,
+) -> Result<()> {
+    let artifacts = version_meta
+        .libraries
+        .iter()
+        .filter_map(Library::artifact)
+        .collect_vec();
+    let libraries_dir = instance_dir.join("libraries");
+
+    for (i, artifact) in artifacts.iter().enumerate() {
+        pb.set_message(format!(
+            "Downloading libraries ({}/{})...",
+            i + 1,
+            artifacts.len()
+        ));
+        // the metadata decides where libraries are written, so it must stay inside the instance
+        let path = &artifact.path;
+        if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(eyre!("Invalid library path {}", path.display()));
+        }
+        download_to(&artifact.download, &libraries_dir.join(path))
+            .await
+            .wrap_err(format!("Failed to download library {}", path.display()))?;
+    }
+
+    if let (Some(index), Some(id)) = (&version_meta.asset_index, version_meta.assets_id()) {
+        pb.set_message("Downloading asset index...");
+        let path = asset_index_path(instance_dir, id)?;
+        download_to(&index.download, &path)
+            .await
+            .wrap_err("Failed to download asset index")?;
+    }
+
+    Ok(())
+}
+
+/// Where the asset index `id` is saved in an instance
+fn asset_index_path(instance_dir: &Path, id: &str) -> Result<PathBuf> {
+    let file_name = format!("{id}.json");
+    if Path::new(&file_name).components().count() != 1 {
+        return Err(eyre!("Invalid asset index ID `{id}`"));
+    }
+
+    Ok(instance_dir.join("assets").join("indexes").join(file_name))
+}
+
+/// How many assets to download at the same time, since there are thousands of small ones
+const ASSET_DOWNLOADS: usize = 16;
+
+/// Downloads the assets in a client instance's asset index into the shared asset store, and links
+/// the store into the instance as `assets/objects`
+///
+/// Objects are addressed by hash, so each one is only downloaded once for all instances
+#[instrument(err, ret(level = "debug"), skip_all, fields(version = %version_meta.id))]
+async fn install_assets(
+    version_meta: &VersionMetadata,
+    instance_dir: &Path,
+    pb: &Progress,
+) -> Result<()> {
+    let Some(id) = version_meta.assets_id() else {
+        warn!("No asset index is published for this version");
+        return Ok(());
+    };
+    let index_path = asset_index_path(instance_dir, id)?;
+    let index: AssetIndexContents = serde_json::from_slice(
+        &fs::read(&index_path)
+            .await
+            .wrap_err(format!("Failed to read {}", index_path.display()))?,
+    )
+    .wrap_err(format!("Invalid asset index {}", index_path.display()))?;
+
+    // objects are verified before they are moved into the store, so a stored object of the right
+    // size is complete
+    let mut missing = vec![];
+    for (name, object) in index.objects.into_iter().unique_by(|(_, o)| o.path().ok()) {
+        let path = ASSET_OBJECTS_DIR.join(object.path()?);
+        if !path.metadata().is_ok_and(|m| m.len() == object.size) {
+            missing.push((name, object, path));
+        }
+    }
+    debug!(missing = missing.len(), "Checked asset store");
+
+    let total = missing.len();
+    let permits = Arc::new(Semaphore::new(ASSET_DOWNLOADS));
+    let mut downloads = JoinSet::new();
+    for (name, object, path) in missing {
+        let permits = permits.clone();
+        downloads.spawn(async move {
+            let _permit = permits.acquire_owned().await?;
+            download_asset(&object, &path)
+                .await
+                .wrap_err(format!("Failed to download asset {name}"))
+        });
+    }
+
+    let mut done = 0;
+    while let Some(result) = downloads.join_next().await {
+        result??;
+        done += 1;
+        pb.set_message(format!("Downloading assets ({done}/{total})..."));
+    }
+
+    let link = instance_dir.join("assets").join("objects");
+    if !link.exists() {
+        fs::create_dir_all(&*ASSET_OBJECTS_DIR).await?;
+        symlink_dir(&ASSET_OBJECTS_DIR, &link)?;
+    }
+
+    Ok(())
+}
+
+/// Downloads an asset and moves it into the store once verified
+///
+/// The store is shared, so another install may store the same object at the same time. Either
+/// copy is fine, as long as the one that ends up in the store is verified
+async fn download_asset(object: &AssetObject, path: &Path) -> Result<()> {
+    let checksum = object.checksum()?;
+    let stored = || async {
+        fs::read(path)
+            .await
+            .is_ok_and(|data| verify(&data, &checksum).is_ok())
+    };
+    if stored().await {
+        return Ok(());
+    }
+
+    let bytes = REQWEST_CLIENT
+        .get(object.url()?)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)?
+        .bytes()
+        .await?;
+    verify(&bytes, &checksum)?;
+
+    fs::create_dir_all(path.parent().expect("infallible")).await?;
+    let partial = temp_path(path);
+    let result = match fs::write(&partial, &bytes).await {
+        Ok(()) => fs::rename(&partial, path).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        let _ = fs::remove_file(&partial).await;
+        if !stored().await {
+            return Err(e.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Downloads and verifies a file from the version metadata, creating its parent directories
+async fn download_to(download: &VersionDownload, path: &Path) -> Result<()> {
+    let bytes = REQWEST_CLIENT
+        .get(&download.url)
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)?
+        .bytes()
+        .await?;
+    verify(&bytes, &download.checksum()?)?;
+
+    fs::create_dir_all(path.parent().expect("infallible")).await?;
+    fs::write(path, &bytes).await?;
+
+    Ok(())
+}
+
+/// Installs a JRE into an instance directory, rather than the shared JRE directory
+#[instrument(err, ret(level = "debug"), skip(pb))]
+async fn install_bundled_jre(major_version: &u8, instance_dir: &Path, pb: &Progress) -> Result<()> {
+    pb.set_message("Downloading bundled JRE...");
+    let jre = download_jre(major_version, pb).await?;
+
+    pb.set_message("Extracting bundled JRE...");
+    extract_jre(jre, &bundled_jre_dir(instance_dir, *major_version))
+        .wrap_err("Failed to extract JRE")?;
+
+    info!("Installed bundled JRE");
+    Ok(())
+}
+
+/// Removes an instance's files and its metadata
+///
+/// With `dry_run`, only prints what would be removed and how much space that would free
+#[instrument(err, ret(level = "debug"), skip(id))]
+pub(crate) fn uninstall_instance(id: VersionNumber, dry_run: bool) -> Result<()> {
+    let pb = Progress::new(id.to_string());
+
+    let mut instance_files = vec![];
+
+    pb.set_message("Checking if instance exists...");
+    if let Some(instance) = META!().instances.get(&id.to_string()) {
+        // the directory describes itself, so don't trust the meta if they disagree
+        let dir = instance_dir(&id.to_string(), instance);
+        if let Some(manifest) = InstanceManifest::read(&dir)? {
+            if manifest.version != instance.id {
+                return Err(eyre!(
+                    "{} contains a {} instance, not {}, so it won't be removed",
+                    dir.display(),
+                    manifest.version,
+                    instance.id
+                ));
+            }
+        }
+
+        instance_files.extend(instance.files.clone());
+    } else {
+        return Err(eyre!("Instance `{id}` does not exist"));
+    }
+    ensure_not_running(&id.to_string(), "uninstall")?;
+
+    if dry_run {
+        pb.finish_with_message("Dry run, nothing was removed");
+        print_uninstall_plan(&id.to_string(), &instance_files);
+        return Ok(());
+    }
+
+    pb.set_message("Removing files...");
+    for path in &instance_files {
+        if !path.exists() {
+            warn!(?path, "File does not exist");
+            continue;
+        }
+
+        if path.is_dir() {
+            info!(?path, "Removing directory");
+            std::fs::remove_dir_all(path)
+                .wrap_err(format!("Failed to remove directory {}", path.display()))?;
+        } else {
+            info!(?path, "Removing file");
+            std::fs::remove_file(path)
+                .wrap_err(format!("Failed to remove file {}", path.display()))?;
+        }
+
+        let mut meta = AppMeta::begin_update(&META)?;
+        if let Some(instance) = meta.instances.get_mut(&id.to_string()) {
+            instance.remove_file(path);
+        }
+        meta.save()?;
+    }
+
+    pb.set_message("Updating metadata...");
+    {
+        let mut meta = AppMeta::begin_update(&META)?;
+        meta.remove_instance(&id.to_string());
+        if meta.current.as_ref() == Some(&id.to_string()) {
+            meta.current = None;
+        }
+        meta.save()?;
+    }
+
+    // bonus: remove jre if it's not used by any other instances
+
+    pb.finish_with_message("Done!");
+    Ok(())
+}
+
+/// Prints the files and metadata that uninstalling `id` would remove
+fn print_uninstall_plan(id: &str, files: &[PathBuf]) {
+    let mut total = 0;
+    println!("Would remove:");
+    for path in files {
+        let size = if path.is_dir() {
+            dir_size(path)
+        } else {
+            path.metadata().map_or(0, |m| m.len())
+        };
+        total += size;
+
+        if path.exists() {
+            println!("  {} ({})", path.display(), format_size(size));
+        } else {
+            println!("  {} (already missing)", path.display());
+        }
+    }
+
+    println!("  the metadata for `{id}`");
+    if META.lock().current.as_deref() == Some(id) {
+        println!("  `{id}` as the current instance");
+    }
+    println!("This would free {}", format_size(total));
+}
+
+/// Renames an instance, moving its directory and settings file to match
+///
+/// If `level_name` is set, the world is also renamed and `level-name` in `server.properties` is updated
+#[instrument(err, ret(level = "debug"))]
+pub(crate) fn rename_instance(id: &String, new_name: &String, level_name: bool) -> Result<()> {
+    validate_instance_name(new_name)?;
+    if !META!().instance_installed(id) {
+        return Err(eyre!("Instance `{id}` does not exist"));
+    }
+    if META!().instance_installed(new_name) {
+        return Err(eyre!("Instance `{new_name}` already exists"));
+    }
+    ensure_not_running(id, "rename")?;
+
+    // instances outside the base directory stay where they are
+    let location = META!().instances[id].location.clone();
+    let old_dir = location
+        .clone()
+        .unwrap_or_else(|| INSTANCE_BASE_DIR.join(id));
+    let new_dir = location.unwrap_or_else(|| INSTANCE_BASE_DIR.join(new_name));
+    let old_settings = INSTANCE_SETTINGS_BASE_DIR.join(format!("{id}.toml"));
+    let new_settings = INSTANCE_SETTINGS_BASE_DIR.join(format!("{new_name}.toml"));
+
+    if (new_dir != old_dir && new_dir.exists()) || new_settings.exists() {
+        return Err(eyre!(
+            "Files for `{new_name}` already exist at {} or {}",
+            new_dir.display(),
+            new_settings.display()
+        ));
+    }
+
+    if level_name {
+        let properties_path = old_dir.join("server.properties");
+        let mut properties = ServerProperties::read_or_default(&properties_path)?;
+        let old_world = old_dir.join(properties.get("level-name").unwrap_or("world"));
+
+        if old_world.exists() {
+            std::fs::rename(&old_world, old_dir.join(new_name))
+                .wrap_err(format!("Failed to rename world {}", old_world.display()))?;
+        }
+        properties.set("level-name", new_name);
+        properties.save(&properties_path)?;
+    }
+
+    if new_dir != old_dir {
+        std::fs::rename(&old_dir, &new_dir).wrap_err(format!(
+            "Failed to move {} to {}",
+            old_dir.display(),
+            new_dir.display()
+        ))?;
+    }
+    std::fs::rename(&old_settings, &new_settings).wrap_err(format!(
+        "Failed to move {} to {}",
+        old_settings.display(),
+        new_settings.display()
+    ))?;
+
+    let mut meta = AppMeta::begin_update(&META)?;
+    let mut instance = meta
+        .remove_instance(id)
+        .ok_or_else(|| eyre!("Instance `{id}` was removed while renaming it"))?;
+    for file in &mut instance.files {
+        if *file == old_dir {
+            file.clone_from(&new_dir);
+        } else if *file == old_settings {
+            file.clone_from(&new_settings);
+        }
+    }
+    meta.instances.insert(new_name.clone(), instance);
+    if meta.current.as_ref() == Some(id) {
+        meta.current = Some(new_name.clone());
+    }
+    meta.save()?;
+
+    info!("Renamed instance");
+    println!("Renamed `{id}` to `{new_name}`");
+    Ok(())
+}
+
+/// Copies an instance to a new instance named `new_name`, e.g. as a test copy of a server
+///
+/// The copy is created in the instance base directory and uses the same JRE.
+/// Unless `world` is set, the world is left out, so the server generates a new one
+#[instrument(err, ret(level = "debug"))]
+pub(crate) async fn clone_instance(id: &String, new_name: &String, world: bool) -> Result<()> {
+    validate_instance_name(new_name)?;
+    let Some(instance) = META.lock().instances.get(id).cloned() else {
+        return Err(eyre!("Instance `{id}` does not exist"));
+    };
+    if META!().instance_installed(new_name) {
+        return Err(eyre!("Instance `{new_name}` already exists"));
+    }
+    // the world could be copied halfway through a save
+    ensure_not_running(id, "clone")?;
+
+    let old_dir = instance_dir(id, &instance);
+    let new_dir = INSTANCE_BASE_DIR.join(new_name);
+    let old_settings = INSTANCE_SETTINGS_BASE_DIR.join(format!("{id}.toml"));
+    let new_settings = INSTANCE_SETTINGS_BASE_DIR.join(format!("{new_name}.toml"));
+
+    if new_dir.exists() || new_settings.exists() {
+        return Err(eyre!(
+            "Files for `{new_name}` already exist at {} or {}",
+            new_dir.display(),
+            new_settings.display()
+        ));
+    }
+
+    let level_name = ServerProperties::read_or_default(old_dir.join("server.properties"))?
+        .get("level-name")
+        .unwrap_or("world")
+        .to_string();
+    let except = if world {
+        vec![]
+    } else {
+        vec![OsStr::new(&level_name)]
+    };
+
+    let copied = async {
+        copy_dir_except(&old_dir, &new_dir, &except)?;
+
+        let mut settings = InstanceSettings::from_file(&old_settings).await?;
+        settings.replace_paths(&old_dir, &new_dir);
+        settings.save(&new_settings).await
+    };
+    if let Err(e) = copied.await {
+        let _ = std::fs::remove_dir_all(&new_dir);
+        let _ = std::fs::remove_file(&new_settings);
+        return Err(e);
+    }
+
+    let mut clone = instance;
+    clone.files = vec![new_dir.clone(), new_settings.clone()];
+    clone.location = None;
+    clone.last_started = None;
+
+    let mut meta = AppMeta::begin_update(&META)?;
+    // another process may have taken the name while copying
+    if meta.instance_installed(new_name) {
+        drop(meta);
+        let _ = std::fs::remove_dir_all(&new_dir);
+        let _ = std::fs::remove_file(&new_settings);
+        return Err(eyre!("Instance `{new_name}` already exists"));
+    }
+    meta.instances.insert(new_name.clone(), clone);
+    meta.save()?;
+
+    info!("Cloned instance");
+    if world {
+        println!("Cloned `{id}` to `{new_name}`");
+    } else {
+        println!("Cloned `{id}` to `{new_name}`, without the world");
+    }
+    Ok(())
+}
+
+/// The JRE used by an installed instance
+#[derive(Debug)]
+pub(crate) struct InstanceJre {
+    pub instance: String,
+    pub version: u8,
+    /// Whether the JRE is inside the instance directory rather than shared
+    pub bundled: bool,
+    /// Whether the system Java is used rather than a JRE installed by mcdl
+    pub system: bool,
+}
+
+/// The JRE each installed instance runs with, sorted by instance
+pub(crate) async fn instance_jres() -> Result<Vec<InstanceJre>> {
+    let ids = META!().instances.keys().sorted().cloned().collect_vec();
+
+    let mut jres = vec![];
+    for id in ids {
+        let settings =
+            InstanceSettings::from_file(INSTANCE_SETTINGS_BASE_DIR.join(format!("{id}.toml")))
+                .await?;
+        jres.push(InstanceJre {
+            instance: id,
+            version: settings.java.version,
+            bundled: settings.java.bundled,
+            system: settings.java.system,
+        });
+    }
+
+    Ok(jres)
+}
+
+/// Whether the shared JRE `version` is installed and present on disk
+pub(crate) fn shared_jre_installed(version: u8) -> bool {
+    META!().jre_installed(&version) && get_java_path(version).exists()
+}
+
+/// The JRE versions used by installed instances, other than those using the system Java
+async fn required_jres(include_bundled: bool) -> Result<BTreeSet<u8>> {
+    Ok(instance_jres()
+        .await?
+        .into_iter()
+        .filter(|jre| !jre.system && (include_bundled || !jre.bundled))
+        .map(|jre| jre.version)
+        .collect())
+}
+
+/// Installs every shared JRE needed by an installed instance that isn't already present
+#[instrument(err, ret(level = "debug"))]
+pub(crate) async fn sync_jres() -> Result<()> {
+    // instances with a bundled JRE don't need a shared one
+    let required = required_jres(false).await?;
+    debug!(?required, "Found required JREs");
+
+    let (present, missing): (Vec<u8>, Vec<u8>) = required
+        .into_iter()
+        .partition(|&jre| shared_jre_installed(jre));
+
+    let mut install_threads = JoinSet::new();
+    let bars = progress_group();
+    let permits = Arc::new(Semaphore::new(CONFIG.read().concurrency));
+
+    if !missing.is_empty() {
+        // some may be recorded as installed, but missing on disk
+        let mut meta = AppMeta::begin_update(&META)?;
+        for jre in &missing {
+            meta.remove_jre(jre);
+        }
+        meta.save()?;
+    }
+
+    for &jre in &missing {
+        let pb = Progress::in_group(&bars, format!("JRE {jre}"));
+        let thread_permits = permits.clone();
+        install_threads.spawn(async move {
+            pb.set_message("Waiting...");
+            let _permit = thread_permits.acquire_owned().await?;
+            install_jre(&jre, &pb)
+                .await
+                .wrap_err(format!("Failed to install JRE {jre}"))
+        });
+    }
+
+    while let Some(result) = install_threads.join_next().await {
+        result??;
+    }
+
+    println!();
+    if !missing.is_empty() {
+        println!("Installed: {}", missing.iter().join(", "));
+    }
+    if !present.is_empty() {
+        println!("Already present: {}", present.iter().join(", "));
+    }
+    if missing.is_empty() && present.is_empty() {
+        println!("No JREs required by installed instances");
+    }
+
+    Ok(())
+}
+
+/// Downloads JRE archives for other platforms, e.g. to build a bundle that runs anywhere
+///
+/// Archives are stored as-is in `jre/<os>-<arch>/<major>`, since only the native archive format
+/// can be extracted. If `versions` is empty, every JRE used by an installed instance is fetched.
+#[instrument(err, ret(level = "debug"))]
+pub(crate) async fn prefetch_jres(versions: Vec<u8>, platforms: Vec<Platform>) -> Result<()> {
+    let versions = if versions.is_empty() {
+        required_jres(true).await?
+    } else {
+        versions.into_iter().collect()
+    };
+    if versions.is_empty() {
+        return Err(eyre!(
+            "No JREs to prefetch (no instances are installed, and no versions were given)"
+        ));
+    }
+
+    let targets = platforms
+        .iter()
+        .unique()
+        .cartesian_product(&versions)
+        .map(|(platform, &jre)| {
+            let path = JRE_BASE_DIR
+                .join(platform.to_string())
+                .join(jre.to_string())
+                .join(format!("jre.{}", platform.archive_extension()));
+            (platform.clone(), jre, path)
+        })
+        .collect_vec();
+    let (present, missing): (Vec<_>, Vec<_>) =
+        targets.into_iter().partition(|(_, _, path)| path.exists());
+
+    if !missing.is_empty() {
+        println!(
+            "Downloading {} JREs. Each one takes up around 40-50 MB",
+            missing.len()
+        );
+    }
+
+    let mut install_threads = JoinSet::new();
+    let mut task_names = HashMap::new();
+    let bars = progress_group();
+    let permits = Arc::new(Semaphore::new(CONFIG.read().concurrency));
+
+    for (platform, jre, path) in missing {
+        let name = format!("{platform}/{jre}");
+        let pb = Progress::in_group(&bars, format!("JRE {name}"));
+        let thread_permits = permits.clone();
+        let handle = install_threads.spawn(async move {
+            pb.set_message("Waiting...");
+            let _permit = thread_permits.acquire_owned().await?;
+
+            pb.set_message("Downloading JRE...");
+            let archive = download_jre_for(&jre, &platform, &pb).await?;
+            fs::create_dir_all(path.parent().unwrap()).await?;
+            fs::write(&path, &archive)
+                .await
+                .wrap_err(format!("Failed to write {}", path.display()))?;
+
+            pb.finish_with_message("Done!");
+            eyre::Ok(archive.len() as u64)
+        });
+        task_names.insert(handle.id(), name);
+    }
+
+    let mut fetched = vec![];
+    let mut failed = vec![];
+    let mut total_size = 0;
+    while let Some(result) = install_threads.join_next_with_id().await {
+        let (id, result) = match result {
+            Ok((id, result)) => (id, result),
+            Err(e) => (e.id(), Err(e.into())),
+        };
+        let name = task_names.remove(&id).unwrap_or_default();
+
+        match result {
+            Ok(size) => {
+                total_size += size;
+                fetched.push(name);
+            }
+            Err(e) => {
+                error!(name, "{e:?}");
+                failed.push((name, e));
+            }
+        }
+    }
+    for (_, _, path) in &present {
+        total_size += fs::metadata(path).await?.len();
+    }
+
+    println!();
+    if !fetched.is_empty() {
+        println!("Downloaded: {}", fetched.iter().sorted().join(", "));
+    }
+    if !present.is_empty() {
+        println!(
+            "Already present: {}",
+            present
+                .iter()
+                .map(|(platform, jre, _)| format!("{platform}/{jre}"))
+                .join(", ")
+        );
+    }
+    println!(
+        "Total size: {} in {}",
+        format_size(total_size),
+        JRE_BASE_DIR.display()
+    );
+
+    if !failed.is_empty() {
+        println!("Failed:");
+        for (name, e) in &failed {
+            println!("  {name}: {e:#}");
+        }
+
+        return Err(eyre!(
+            "{} of {} downloads failed",
+            failed.len(),
+            failed.len() + fetched.len()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Formats a number of bytes with a binary unit, e.g. `1.5 GiB`
+pub(crate) fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next in &UNITS[1..] {
+        if size < 1024.0 {
+            break;
+        }
+        size /= 1024.0;
+        unit = next;
+    }
+
+    format!("{size:.1} {unit}")
+}
+
+/// Everything needed to start an instance's server
+#[derive(Debug)]
+struct ServerCommand {
+    id: String,
+    instance_path: PathBuf,
+    settings: InstanceSettings,
+    java_path: PathBuf,
+    args: Vec<OsString>,
+    env: BTreeMap<String, String>,
+    /// The command line as it could be typed into a shell, for display
+    command_line: String,
+}
+
+impl ServerCommand {
+    /// Reads an instance's settings to build the command that starts its server
+    async fn new(id: &VersionNumber, env: Vec<(String, String)>) -> Result<Self> {
+        let instance_path = server_instance_dir(&id.to_string())?;
+
+        let settings =
+            InstanceSettings::from_file(INSTANCE_SETTINGS_BASE_DIR.join(format!("{id}.toml")))
+                .await?;
+        debug!(?settings, "Loaded instance settings");
+
+        for warning in misplaced_args(&settings) {
+            warn!(id = %id, "{warning}");
+            eprintln!("Warning: {warning}");
+        }
+
+        let java_path = instance_java_path(&instance_path, &settings);
+        let args = server_args(&settings);
+        let env = server_env(&settings, env);
+        let command_line = std::iter::once(
+            env.iter()
+                .map(|(key, value)| format!("{key}={}", shell_escape::escape(value.into())))
+                .join(" "),
+        )
+        .chain(std::iter::once(shell_join(&java_path, &args)))
+        .filter(|s| !s.is_empty())
+        .join(" ");
+
+        Ok(Self {
+            id: id.to_string(),
+            instance_path,
+            settings,
+            java_path,
+            args,
+            env,
+            command_line,
+        })
+    }
+
+    /// Checks that the JRE is installed, and installs it if not
+    async fn ensure_java(&self) -> Result<()> {
+        let id = &self.id;
+        let jre_version = self.settings.java.version;
+
+        if self.settings.java.system {
+            check_system_java(&self.java_path, jre_version).await?;
+        } else if self.settings.java.bundled {
+            if !self.java_path.exists() {
+                debug!(
+                    jre = jre_version,
+                    "Installing bundled JRE due to config change"
+                );
+                let pb = Progress::new(format!("JRE {jre_version} for {id}"));
+                install_bundled_jre(&jre_version, &self.instance_path, &pb).await?;
+                pb.finish_with_message("Done!");
+            }
+        } else {
+            ensure_jre(&META, id, jre_version, &self.java_path, || async {
+                debug!(jre = jre_version, "Installing JRE due to config change");
+                let pb = Progress::new(format!("JRE {jre_version} for {id}"));
+                install_jre(&jre_version, &pb).await
+            })
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.java_path);
+        command
+            .current_dir(&self.instance_path)
+            .kill_on_drop(true)
+            .args(&self.args)
+            .envs(&self.env);
+        command
+    }
+}
+
+#[instrument(err, ret(level = "debug"), skip(id, env))]
+pub(crate) async fn run_instance(
+    id: VersionNumber,
+    print_command: bool,
+    env: Vec<(String, String)>,
+) -> Result<()> {
+    let server = ServerCommand::new(&id, env).await?;
+    let ServerCommand {
+        instance_path,
+        command_line,
+        ..
+    } = &server;
+
+    if print_command {
+        println!("{command_line}");
+        return Ok(());
+    }
+
+    ensure_not_running(&server.id, "run")?;
+    server.ensure_java().await?;
+    write_server_ip(instance_path, &server.settings.server)?;
+
+    debug!("Starting server with command line: {command_line}");
+    let mut child = server.command().spawn().wrap_err(format!(
+        "Failed to start server with command line: {command_line}"
+    ))?;
+    info!("Started server");
+    let _run_state = child
+        .id()
+        .map(|pid| RunState::new(pid).save(run_state_path(&server.id)))
+        .transpose()?;
+    record_started(&server.id);
+
+    let status = child.wait().await.wrap_err("Failed to wait for server")?;
+    if !status.success() {
+        error!(?status, "Server exited with an error");
+        let upload = Confirm::new()
+            .with_prompt("Server exited with an error. Would you like to upload the crash report?")
+            .default(false)
+            .interact()?;
+
+        if upload {
+            debug!("Uploading crash report");
+            let crash_reports = instance_path.join("crash-reports");
+
+            let latest = std::fs::read_dir(crash_reports)
+                .wrap_err("Failed to read crash reports directory")?
+                .filter_map(Result::ok)
+                .max_by(|a, b| {
+                    let a = a.metadata().unwrap().modified().unwrap();
+                    let b = b.metadata().unwrap().modified().unwrap();
+
+                    a.cmp(&b)
+                })
+                .ok_or_else(|| eyre!("No crash reports found"))?;
+
+            let content =
+                std::fs::read_to_string(latest.path()).wrap_err("Failed to read crash report")?;
+
+            // upload to mclo.gs
+            let response = REQWEST_CLIENT
+                .post("https://api.mclo.gs/1/log")
+                .form(&[("content", content)])
+                .send()
+                .await?;
+
+            // parse json response
+            let response: serde_json::Value = response.json().await?;
+
+            if response["success"].as_bool().unwrap() {
+                println!(
+                    "Crash report uploaded to {}",
+                    response["url"].as_str().unwrap()
+                );
+                debug!(
+                    url = response["url"].as_str().unwrap(),
+                    "Crash report uploaded"
+                );
+            } else {
+                return Err(eyre!(
+                    "Failed to upload crash report: {}",
+                    response["error"].as_str().unwrap()
+                ));
+            }
+        }
+
+        return Err(eyre!(
+            "Server exited with {status}. Command line: {command_line}"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Runs several instances at once, printing their output with each line prefixed by the instance
+///
+/// The servers' consoles aren't connected to stdin. If one exits with an error, the others are
+/// stopped with the `stop` command unless `keep_going` is set. On Ctrl-C, all of them are stopped
+/// and waited for, and a second Ctrl-C kills them
+#[instrument(err, ret(level = "debug"), skip(ids, env))]
+pub(crate) async fn run_instances(
+    ids: Vec<VersionNumber>,
+    print_command: bool,
+    env: Vec<(String, String)>,
+    keep_going: bool,
+) -> Result<()> {
+    let mut servers = vec![];
+    for id in &ids {
+        servers.push(ServerCommand::new(id, env.clone()).await?);
+    }
+
+    if print_command {
+        for server in &servers {
+            println!("{}", server.command_line);
+        }
+        return Ok(());
+    }
+
+    for server in &servers {
+        ensure_not_running(&server.id, "run")?;
+    }
+    // one at a time, since instances can share a JRE
+    for server in &servers {
+        server.ensure_java().await?;
+        write_server_ip(&server.instance_path, &server.settings.server)?;
+    }
+
+    let width = servers.iter().map(|s| s.id.len()).max().unwrap_or_default();
+    let mut prefixes = HashMap::new();
+    let mut consoles = HashMap::new();
+    let mut outputs = vec![];
+    let mut children = JoinSet::new();
+    for server in servers {
+        let prefix = format!("[{:width$}]", server.id);
+        debug!(
+            id = server.id,
+            "Starting server with command line: {}", server.command_line
+        );
+        let mut child = server
+            .command()
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .wrap_err(format!(
+                "Failed to start `{}` with command line: {}",
+                server.id, server.command_line
+            ))?;
+
+        let run_state = child
+            .id()
+            .map(|pid| RunState::new(pid).save(run_state_path(&server.id)))
+            .transpose()?;
+        let pid = child
+            .id()
+            .map_or("unknown".to_string(), |pid| pid.to_string());
+        info!(id = server.id, pid, "Started server");
+        println!("{prefix} Started server (PID {pid})");
+        record_started(&server.id);
+
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let stderr = child.stderr.take().expect("stderr is piped");
+        outputs.push(forward_lines(stdout, prefix.clone(), false));
+        outputs.push(forward_lines(stderr, prefix.clone(), true));
+        consoles.insert(
+            server.id.clone(),
+            child.stdin.take().expect("stdin is piped"),
+        );
+        prefixes.insert(server.id.clone(), prefix);
+
+        let id = server.id;
+        children.spawn(async move {
+            let _run_state = run_state;
+            (child.wait().await, id)
+        });
+    }
+
+    let mut failed = vec![];
+    let mut stopping = false;
+    let mut interrupted = false;
+    loop {
+        let (status, id) = tokio::select! {
+            result = children.join_next() => match result {
+                Some(result) => result?,
+                None => break,
+            },
+            Ok(()) = tokio::signal::ctrl_c() => {
+                if interrupted {
+                    warn!("Interrupted again, killing servers");
+                    children.abort_all();
+                    while children.join_next().await.is_some() {}
+                    return Err(Interrupted.into());
+                }
+
+                warn!("Interrupted, stopping servers");
+                println!("Stopping servers, press Ctrl-C again to kill them");
+                stopping = true;
+                interrupted = true;
+                stop_servers(&mut consoles).await;
+                continue;
+            }
+        };
+
+        let status = status.wrap_err(format!("Failed to wait for `{id}`"))?;
+        let prefix = &prefixes[&id];
+        consoles.remove(&id);
+        // servers also get the interrupt from the terminal, and exit with an error code for it
+        if status.success() || interrupted {
+            println!("{prefix} Server stopped");
+            continue;
+        }
+
+        error!(id, ?status, "Server exited with an error");
+        println!("{prefix} Server exited with {status}");
+        failed.push(id);
+        if !keep_going && !stopping {
+            println!("Stopping the other servers");
+            stopping = true;
+            stop_servers(&mut consoles).await;
+        }
+    }
+
+    for output in outputs {
+        output.await?;
+    }
+
+    if interrupted {
+        return Err(Interrupted.into());
+    }
+    if !failed.is_empty() {
+        return Err(eyre!(
+            "{} server(s) exited with an error: {}",
+            failed.len(),
+            failed.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+/// Prints each line of a server's output with `prefix`, until the output is closed
+fn forward_lines<R>(output: R, prefix: String, stderr: bool) -> task::JoinHandle<()>
+where R: AsyncRead + Unpin + Send + 'static {
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(output).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if stderr {
+                eprintln!("{prefix} {line}");
+            } else {
+                println!("{prefix} {line}");
+            }
+        }
+    })
+}
+
+/// Where the run state of instance `id` is kept while its server runs
+fn run_state_path(id: &str) -> PathBuf {
+    RUN_STATE_DIR.join(format!("{id}.toml"))
+}
+
+/// The run state of instance `id`, if its server is running
+///
+/// Run states left behind by servers that are gone, e.g. because mcdl was killed, are removed
+#[instrument(ret(level = "debug"))]
+pub(crate) fn run_state(id: &str) -> Option<RunState> {
+    let path = run_state_path(id);
+    match RunState::from_file(&path) {
+        Ok(None) => return None,
+        Ok(Some(state)) if state.is_alive() => return Some(state),
+        Ok(Some(state)) => debug!(pid = state.pid, "Removing stale run state"),
+        Err(e) => warn!("Removing unreadable run state: {e:?}"),
+    }
+
+    if let Err(e) = std::fs::remove_file(&path) {
+        warn!("Failed to remove run state {}: {e}", path.display());
+    }
+    None
+}
+
+/// Whether the server of instance `id` was started by `run` and is still running
+pub(crate) fn is_running(id: &str) -> bool {
+    run_state(id).is_some()
+}
+
+/// Fails if the server of instance `id` is running, since `action` isn't safe then
+fn ensure_not_running(id: &str, action: &str) -> Result<()> {
+    match run_state(id) {
+        Some(state) => Err(eyre!(
+            "Can't {action} `{id}` while its server is running (PID {}, started {})",
+            state.pid,
+            state
+                .started
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M:%S")
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Records that the server of instance `id` was just started, for `status`
+///
+/// Failing to record it is only logged, since the server is already running
+fn record_started(id: &str) {
+    let result = AppMeta::begin_update(&META).and_then(|mut meta| {
+        if let Some(instance) = meta.instances.get_mut(id) {
+            instance.last_started = Some(Utc::now());
+        }
+        meta.save()
+    });
+    if let Err(e) = result {
+        warn!(id, "Failed to record the start time: {e:?}");
+    }
+}
+
+/// The port a server listens on if `server-port` isn't set in `server.properties`
+const DEFAULT_SERVER_PORT: u16 = 25565;
+
+/// A summary of an installed instance, as shown by `status`
+#[derive(Debug, Serialize)]
+pub(crate) struct InstanceStatus {
+    pub name: String,
+    pub version: VersionNumber,
+    /// The kind of version, e.g. `release`, if the instance directory describes it
+    #[serde(rename = "type")]
+    pub kind: Option<String>,
+    pub client: bool,
+    /// Where the jar came from, if it isn't the official one
+    pub custom_jar: Option<String>,
+    /// The port the server listens on, or `None` for clients
+    pub port: Option<u16>,
+    pub jre: u8,
+    /// The size of the instance directory, in bytes
+    pub size: u64,
+    pub location: PathBuf,
+    pub running: bool,
+    /// The PID of the server, if it's running
+    pub pid: Option<u32>,
+    pub last_started: Option<DateTime<Utc>>,
+}
+
+/// Gathers the [`InstanceStatus`] of instance `id`
+#[instrument(err, ret(level = "debug"))]
+pub(crate) async fn instance_status(id: &str) -> Result<InstanceStatus> {
+    let instance = META
+        .lock()
+        .instances
+        .get(id)
+        .cloned()
+        .ok_or_else(|| eyre!("Instance `{id}` does not exist"))?;
+    let location = instance_dir(id, &instance);
+
+    let kind = InstanceManifest::read(&location)?.map(|manifest| manifest.kind);
+    // the settings can be changed to use another JRE after the install
+    let jre = InstanceSettings::from_file(INSTANCE_SETTINGS_BASE_DIR.join(format!("{id}.toml")))
+        .await
+        .map_or(instance.jre, |settings| settings.java.version);
+    let port = if instance.client {
+        None
+    } else {
+        let properties = ServerProperties::read_or_default(location.join("server.properties"))?;
+        Some(
+            properties
+                .get("server-port")
+                .and_then(|port| port.parse().ok())
+                .unwrap_or(DEFAULT_SERVER_PORT),
+        )
+    };
+    let pid = run_state(id).map(|state| state.pid);
+
+    Ok(InstanceStatus {
+        name: id.to_string(),
+        version: instance.id,
+        kind,
+        client: instance.client,
+        custom_jar: instance.custom_jar,
+        port,
+        jre,
+        size: dir_size(&location),
+        location,
+        running: pid.is_some(),
+        pid,
+        last_started: instance.last_started,
+    })
+}
+
+/// Sends the `stop` command to each server console, which saves the worlds before exiting
+async fn stop_servers(consoles: &mut HashMap<String, ChildStdin>) {
+    for (id, console) in consoles {
+        if let Err(e) = console.write_all(b"stop\n").await {
+            // most likely already exited
+            warn!(id, "Failed to send stop command: {e}");
+        }
+    }
+}
+
+/// Installs the shared JRE for an instance with `install` if it is missing,
+/// then records the JRE in the instance metadata
+///
+/// The metadata is saved so later runs see the JRE as installed.
+/// Returns whether `install` was called
+///
+/// The JRE counts as missing if `java_path` doesn't exist, even if the metadata says it's installed
+#[instrument(err, ret(level = "debug"), skip(meta, install))]
+async fn ensure_jre<F, Fut>(
+    meta: &Mutex<AppMeta>,
+    id: &String,
+    jre_version: u8,
+    java_path: &Path,
+    install: F,
+) -> Result<bool>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let missing = !(meta.lock().jre_installed(&jre_version) && java_path.is_file());
+    if missing {
+        install().await?;
+    }
+
+    // make sure JRE version is correct
+    let mut meta = AppMeta::begin_update(meta)?;
+    meta.instances
+        .get_mut(id)
+        .ok_or_else(|| eyre!("Instance metadata not found for {id}"))?
+        .jre = jre_version;
+    meta.save()?;
+
+    Ok(missing)
+}
+
+/// Builds the arguments to pass to `java`, in the order JVM args, server jar, server args
+fn server_args(settings: &InstanceSettings) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec![];
+    args.extend(settings.java.args.iter().map(|s| s.into())); // jvm args
+    if settings.server.ipv6 {
+        args.push(PREFER_IPV6_ARG.into());
+    }
+    args.extend(vec!["-jar".into(), settings.server.jar.clone().into()]); // server jar
+    args.extend(settings.server.args.iter().map(|s| s.into())); // server args
+
+    args
+}
+
+/// Writes the `server-ip` from the instance settings to `server.properties`, if they set one
+///
+/// The properties are only rewritten when the value differs, so other changes are left alone
+fn write_server_ip(instance_dir: &Path, settings: &InstanceServerSettings) -> Result<()> {
+    let Some(server_ip) = settings.server_ip() else {
+        return Ok(());
+    };
+
+    let properties_path = instance_dir.join("server.properties");
+    let mut properties = ServerProperties::read_or_default(&properties_path)?;
+    if properties.get("server-ip") != Some(server_ip.as_str()) {
+        debug!(server_ip, "Updating server-ip");
+        properties.set("server-ip", &server_ip);
+        properties.save(&properties_path)?;
+    }
+
+    Ok(())
+}
+
+/// Finds arguments that look like they are in the wrong list in the instance settings
+///
+/// JVM flags only work before `-jar`, and the server ignores or rejects them after it. Likewise,
+/// `-jar` or a bare argument such as a jar path in the JVM args would start something other than
+/// the server. Jars in flags like `-javaagent:agent.jar` are fine
+fn misplaced_args(settings: &InstanceSettings) -> Vec<String> {
+    // options that take the next argument as their value
+    const VALUE_OPTIONS: [&str; 10] = [
+        "-cp",
+        "-classpath",
+        "--class-path",
+        "-p",
+        "--module-path",
+        "--upgrade-module-path",
+        "--add-modules",
+        "--add-opens",
+        "--add-exports",
+        "--add-reads",
+    ];
+    let jvm_flag = |arg: &&String| {
+        ["-X", "-D", "-javaagent:"]
+            .iter()
+            .any(|p| arg.starts_with(p))
+    };
+
+    let mut is_value = false;
+    let in_java = settings
+        .java
+        .args
+        .iter()
+        .filter(|arg| {
+            let misplaced = !is_value && (*arg == "-jar" || !arg.starts_with('-'));
+            is_value = VALUE_OPTIONS.contains(&arg.as_str());
+            misplaced
+        })
+        .map(|arg| {
+            format!("`{arg}` is in the Java args, but the server jar is set with `server.jar`")
+        });
+    let in_server = settings.server.args.iter().filter(jvm_flag).map(|arg| {
+        format!("`{arg}` looks like a JVM flag, but is in the server args instead of the Java args")
+    });
+
+    in_java.chain(in_server).collect()
+}
+
+/// The environment variables to set for the server, with those in `overrides` taking precedence
+fn server_env(
+    settings: &InstanceSettings,
+    overrides: Vec<(String, String)>,
+) -> BTreeMap<String, String> {
+    let mut env = settings.env.clone();
+    env.extend(overrides);
+
+    env
+}
+
+/// Joins a program and its arguments into a shell-escaped command line
+fn shell_join(program: &Path, args: &[OsString]) -> String {
+    std::iter::once(program.as_os_str())
+        .chain(args.iter().map(OsString::as_os_str))
+        .map(|s| shell_escape::escape(s.to_string_lossy()))
+        .join(" ")
+}
+
+/// Prints the `java` executable `run` would start an instance with, and its version
+#[instrument(err, ret(level = "debug"))]
+pub(crate) async fn which_java(id: &str) -> Result<()> {
+    let instance_path = match META!().instances.get(id) {
+        Some(instance) => instance_dir(id, instance),
+        None => return Err(eyre!("Instance `{id}` does not exist")),
+    };
+
+    let settings =
+        InstanceSettings::from_file(INSTANCE_SETTINGS_BASE_DIR.join(format!("{id}.toml"))).await?;
+    let java_path = instance_java_path(&instance_path, &settings);
+
+    println!("{}", java_path.display());
+    if settings.java.system {
+        println!("Source: system Java, {} or newer", settings.java.version);
+        if !java_path.exists() {
+            println!("Version: not found (set JAVA_HOME or add java to PATH)");
+            return Ok(());
+        }
+    } else {
+        let source = if settings.java.bundled {
+            "bundled"
+        } else {
+            "shared"
+        };
+        println!("Source: {source} JRE {}", settings.java.version);
+    }
+    if !java_path.exists() {
+        println!("Version: not installed (it will be installed by `mcdl run`)");
+        return Ok(());
+    }
+
+    // `java -version` prints to stderr
+    let output = Command::new(&java_path)
+        .arg("-version")
+        .output()
+        .await
+        .wrap_err(format!("Failed to run {}", java_path.display()))?;
+    let output = String::from_utf8_lossy(&output.stderr);
+    match parse_java_version(&output) {
+        Some(version) => println!("Version: {version}"),
+        None => println!(
+            "Version: unknown ({})",
+            output.lines().next().unwrap_or_default()
+        ),
+    }
+
+    Ok(())
+}
+
+/// Checks that the system Java at `java_path` exists and is at least version `required`
+///
+/// If its version can't be determined, it's assumed to be new enough
+async fn check_system_java(java_path: &Path, required: u8) -> Result<()> {
+    if !java_path.exists() {
+        return Err(eyre!(
+            "No system Java found, but this instance needs Java {required} or newer. \
+             Set JAVA_HOME or add java to PATH"
+        ));
+    }
+
+    let output = Command::new(java_path)
+        .arg("-version")
+        .output()
+        .await
+        .wrap_err(format!("Failed to run {}", java_path.display()))?;
+    let output = String::from_utf8_lossy(&output.stderr);
+    let Some(version) = parse_java_version(&output) else {
+        warn!(java = %java_path.display(), "Couldn't determine the system Java version");
+        return Ok(());
+    };
+
+    match java_major_version(version) {
+        Some(major) if major < required => Err(eyre!(
+            "System Java {version} at {} is too old, this instance needs Java {required} or newer",
+            java_path.display()
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Gets the major version from a Java version string, e.g. `17` from `17.0.9` and `8` from the
+/// older `1.8.0_392` scheme
+fn java_major_version(version: &str) -> Option<u8> {
+    let mut parts = version.split(|c: char| !c.is_ascii_digit());
+    match parts.next()? {
+        "1" => parts.next()?.parse().ok(),
+        major => major.parse().ok(),
+    }
+}
+
+/// Finds the version in the output of `java -version`, e.g. `17.0.9` from
+/// `openjdk version "17.0.9" 2023-10-17`
+fn parse_java_version(output: &str) -> Option<&str> {
+    let line = output.lines().find(|l| l.contains(" version \""))?;
+    line.split('"').nth(1)
+}
+
+#[instrument(err, ret(level = "debug"))]
+pub(crate) fn locate(what: &String) -> Result<()> {
+    match what.to_ascii_lowercase().as_str() {
+        "java" => {
+            println!("JRE base directory: {}", JRE_BASE_DIR.display());
+        }
+        "instance" => {
+            println!("Instance base directory: {}", INSTANCE_BASE_DIR.display());
+        }
+        "config" => {
+            println!(
+                "Instance settings base directory: {}",
+                INSTANCE_SETTINGS_BASE_DIR.display()
+            );
+        }
+        "log" => {
+            println!("Log base directory: {}", LOG_BASE_DIR.display());
+        }
+        _ => {
+            return Err(eyre!("Unknown location: {what}"));
+        }
+    }
+
+    Ok(())
+}
+
+#[instrument(err, ret(level = "debug"))]
+pub(crate) fn config(edit: bool) -> Result<()> {
+    if !edit {
+        println!("Config file: {}\n", CONFIG_PATH.display());
+        print!("{}", CONFIG.read().to_toml()?);
+        return Ok(());
+    }
+
+    if !CONFIG_PATH.exists() {
+        info!("Creating default config");
+        Config::default().save(CONFIG_PATH.as_path())?;
+    }
+
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| if cfg!(windows) { "notepad" } else { "vi" }.to_string());
+    let mut editor_args = editor.split_whitespace();
+    let program = editor_args
+        .next()
+        .ok_or_else(|| eyre!("Editor command is empty"))?;
+
+    debug!(editor, "Opening config in editor");
+    let status = std::process::Command::new(program)
+        .args(editor_args)
+        .arg(CONFIG_PATH.as_os_str())
+        .status()
+        .wrap_err(format!("Failed to start editor `{editor}`"))?;
+    if !status.success() {
+        return Err(eyre!("Editor `{editor}` exited with {status}"));
+    }
+
+    // report problems now rather than on the next run
+    Config::from_file(CONFIG_PATH.as_path())?;
+    println!("Config saved to {}", CONFIG_PATH.display());
+
+    Ok(())
+}
+
+/// Sets the instance commands use when none is given, or clears it if `id` is `None`
+#[instrument(err, ret(level = "debug"))]
+pub(crate) fn use_instance(id: Option<&str>) -> Result<()> {
+    let mut meta = AppMeta::begin_update(&META)?;
+    match id {
+        Some(id) if !meta.instance_installed(&id.to_string()) => {
+            return Err(eyre!("Instance `{id}` does not exist"));
+        }
+        Some(id) => {
+            meta.current = Some(id.to_string());
+            println!("Now using `{id}`");
+        }
+        None => {
+            meta.current = None;
+            println!("Cleared the current instance");
+        }
+    }
+    meta.save()?;
+
+    Ok(())
+}
+
+/// Adds `add` to and removes `remove` from the tags of instance `id`, then prints its tags
+#[instrument(err, ret(level = "debug"))]
+pub(crate) fn tag_instance(id: &str, add: &[String], remove: &[String]) -> Result<()> {
+    let mut meta = AppMeta::begin_update(&META)?;
+    let instance = meta
+        .instances
+        .get_mut(id)
+        .ok_or_else(|| eyre!("Instance `{id}` does not exist"))?;
+
+    instance.tags.extend(add.iter().cloned());
+    instance.tags.retain(|tag| !remove.contains(tag));
+    if instance.tags.is_empty() {
+        println!("`{id}` has no tags");
+    } else {
+        println!("`{id}`: {}", instance.tags.iter().join(", "));
+    }
+
+    if !add.is_empty() || !remove.is_empty() {
+        meta.save()?;
+    }
+
+    Ok(())
+}
+
+/// The names of installed instances with any of `tags`, sorted
+pub(crate) fn tagged_instances(tags: &[String]) -> Vec<String> {
+    META.lock()
+        .instances
+        .iter()
+        .filter(|(_, instance)| tags.iter().any(|tag| instance.tags.contains(tag)))
+        .map(|(name, _)| name.clone())
+        .sorted()
+        .collect()
+}
+
+/// Checks that each service can be reached within `timeout`, printing the results
+#[instrument(err, ret(level = "debug"))]
+pub(crate) async fn doctor(timeout: Duration) -> Result<()> {
+    match https_proxy() {
+        Some(proxy) => println!("Proxy: {proxy}"),
+        None => println!("Proxy: none"),
+    }
+
+    let results = probe_services(timeout).await;
+    let failed = results
+        .iter()
+        .filter(|(.., result)| result.is_err())
+        .count();
+    for (name, url, result) in results {
+        match result {
+            Ok(latency) => println!("{name} ({url}): ok, {} ms", latency.as_millis()),
+            Err(e) => println!("{name} ({url}): {e}"),
+        }
+    }
+
+    let meta = META.lock();
+    let mut mismatched = 0;
+    for (id, instance) in meta.instances.iter().sorted_by_key(|(id, _)| *id) {
+        match verify_instance_jar(id, instance) {
+            Some(Ok(())) => println!("Instance {id}: jar ok"),
+            Some(Err(e)) => {
+                mismatched += 1;
+                println!("Instance {id}: {e}");
+            }
+            None => debug!(id, "No recorded download to verify"),
+        }
+    }
+
+    if failed > 0 {
+        return Err(eyre!("{failed} service(s) could not be reached"));
+    }
+    if mismatched > 0 {
+        return Err(eyre!(
+            "{mismatched} instance jar(s) don't match the download they were installed from"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks an instance's jar against the download recorded when it was installed
+///
+/// Returns `None` if there is no recorded download, e.g. for custom jars
+fn verify_instance_jar(id: &str, instance: &InstanceMeta) -> Option<Result<()>> {
+    let download = instance.download.as_ref()?;
+    let jar_name = if instance.client {
+        "client.jar"
+    } else {
+        "server.jar"
+    };
+    let jar = instance_dir(id, instance).join(jar_name);
+
+    let bytes = match std::fs::read(&jar) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return Some(Err(
+                eyre!(e).wrap_err(format!("Failed to read {}", jar.display()))
+            ))
+        }
+    };
+
+    Some(
+        download
+            .checksum()
+            .and_then(|checksum| verify(&bytes, &checksum))
+            .wrap_err(format!("{jar_name} doesn't match {}", download.url)),
+    )
+}
+
+/// Opens an instance directory in the file manager, or in a shell if `shell` is true
+///
+/// If there is no file manager to open, e.g. over SSH, the path is printed instead
+#[instrument(err, ret(level = "debug"))]
+pub(crate) fn open_instance(id: &str, shell: bool) -> Result<()> {
+    let instance_path = match META!().instances.get(id) {
+        Some(instance) => instance_dir(id, instance),
+        None => return Err(eyre!("Instance `{id}` does not exist")),
+    };
+    if !instance_path.is_dir() {
+        return Err(eyre!(
+            "Instance directory {} does not exist",
+            instance_path.display()
+        ));
+    }
+
+    if shell {
+        let shell = if cfg!(windows) {
+            std::env::var("COMSPEC").unwrap_or_else(|_| "cmd".to_string())
+        } else {
+            std::env::var("SHELL").unwrap_or_else(|_| "sh".to_string())
+        };
+
+        debug!(shell, "Starting shell in instance directory");
+        println!(
+            "Starting {shell} in {}, exit the shell to return",
+            instance_path.display()
+        );
+        std::process::Command::new(&shell)
+            .current_dir(&instance_path)
+            .status()
+            .wrap_err(format!("Failed to start shell `{shell}`"))?;
+        return Ok(());
+    }
+
+    let opener = match std::env::consts::OS {
+        "windows" => Some("explorer"),
+        "macos" => Some("open"),
+        // without a display, there is no file manager to open
+        _ if std::env::var_os("DISPLAY").is_none()
+            && std::env::var_os("WAYLAND_DISPLAY").is_none() =>
+        {
+            None
+        }
+        _ => Some("xdg-open"),
+    };
+
+    let opened = opener.is_some_and(|opener| {
+        debug!(opener, "Opening instance directory");
+        match std::process::Command::new(opener)
+            .arg(&instance_path)
+            .status()
+        {
+            // explorer exits with 1 even when it succeeds
+            Ok(status) => status.success() || opener == "explorer",
+            Err(e) => {
+                debug!(opener, error = %e, "Failed to start file manager");
+                false
+            }
+        }
+    });
+
+    if !opened {
+        println!("{}", instance_path.display());
+    }
+
+    Ok(())
+}
+
+#[instrument(err, ret(level = "debug"), skip(id))]
+pub(crate) async fn service(
+    id: VersionNumber,
+    kind: ServiceKind,
+    user: Option<String>,
+    install: bool,
+) -> Result<()> {
+    let id = id.to_string();
+    let instance_path = server_instance_dir(&id)?;
+
+    let settings =
+        InstanceSettings::from_file(INSTANCE_SETTINGS_BASE_DIR.join(format!("{id}.toml"))).await?;
+    let java_path = instance_java_path(&instance_path, &settings);
+
+    let definition = ServiceDefinition {
+        instance: id,
+        mcdl_path: std::env::current_exe().wrap_err("Failed to locate the mcdl executable")?,
+        working_dir: instance_path.clone(),
+        java_path,
+        user,
+    };
+    let contents = definition.render(kind);
+
+    if !install {
+        print!("{contents}");
+        return Ok(());
+    }
+
+    let dir = match kind {
+        ServiceKind::Windows => instance_path,
+        ServiceKind::Systemd if definition.user.is_some() => PathBuf::from("/etc/systemd/system"),
+        ServiceKind::Systemd => BaseDirs::new()
+            .ok_or_else(|| eyre!("Failed to locate the user config directory"))?
+            .config_dir()
+            .join("systemd/user"),
+        ServiceKind::Launchd if definition.user.is_some() => {
+            PathBuf::from("/Library/LaunchDaemons")
+        }
+        ServiceKind::Launchd => BaseDirs::new()
+            .ok_or_else(|| eyre!("Failed to locate the home directory"))?
+            .home_dir()
+            .join("Library/LaunchAgents"),
+    };
+    let path = dir.join(definition.file_name(kind));
+
+    std::fs::create_dir_all(&dir).wrap_err(format!("Failed to create {}", dir.display()))?;
+    std::fs::write(&path, contents).wrap_err(format!("Failed to write {}", path.display()))?;
+    info!(path = %path.display(), "Installed service definition");
+
+    println!("Service definition written to {}", path.display());
+    let name = definition.name();
+    match kind {
+        ServiceKind::Systemd => {
+            let scope = if definition.user.is_some() {
+                ""
+            } else {
+                " --user"
+            };
+            println!("Enable it with `systemctl{scope} daemon-reload && systemctl{scope} enable --now {name}`");
+        }
+        ServiceKind::Launchd => {
+            println!("Load it with `launchctl load -w {}`", path.display());
+        }
+        ServiceKind::Windows => {
+            println!(
+                "Run it from an elevated prompt to register the `{name}` service (requires NSSM)"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_sizes() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(45 * 1024 * 1024), "45.0 MiB");
+        assert_eq!(format_size(3 * 1024 * 1024 * 1024), "3.0 GiB");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn shell_join_escapes_args() {
+        let args = vec!["-Xmx4G".into(), "-jar".into(), "my server.jar".into()];
+        assert_eq!(
+            shell_join(Path::new("/opt/jre/bin/java"), &args),
+            "/opt/jre/bin/java -Xmx4G -jar 'my server.jar'"
+        );
+    }
+
+    #[test]
+    fn parse_java_versions() {
+        let output = "Picked up JAVA_TOOL_OPTIONS: -Xss1M\n\
+                      openjdk version \"17.0.9\" 2023-10-17\n\
+                      OpenJDK Runtime Environment Temurin-17.0.9+9 (build 17.0.9+9)\n";
+        assert_eq!(parse_java_version(output), Some("17.0.9"));
+        assert_eq!(
+            parse_java_version("java version \"1.8.0_392\"\n"),
+            Some("1.8.0_392")
+        );
+        assert_eq!(parse_java_version("Error: could not find libjava.so"), None);
+    }
 
-        info!(
-            jre = jre_version,
-            version = version_display,
-            "Installing JRE"
+    #[test]
+    fn expand_name_templates() {
+        let version: GameVersion = serde_json::from_value(serde_json::json!({
+            "id": "23w13a",
+            "type": "snapshot",
+            "url": "",
+            "time": "2023-03-29T13:00:00+00:00",
+            "releaseTime": "2023-03-29T13:00:00+00:00",
+        }))
+        .unwrap();
+
+        assert_eq!(
+            expand_name_template("srv-{version}-{kind}-{n}", &version, 2).unwrap(),
+            "srv-23w13a-snapshot-2"
         );
+        assert_eq!(expand_name_template("plain", &version, 1).unwrap(), "plain");
 
-        let pb_jre = bars.add(
-            ProgressBar::new_spinner()
-                .with_style(PB_STYLE.clone())
-                .with_prefix(format!("JRE {jre_version} for {}", version.id)),
+        let err = expand_name_template("srv-{id}", &version, 1).unwrap_err();
+        assert!(
+            err.to_string().contains("Unknown placeholder `{id}`"),
+            "{err}"
+        );
+        assert!(
+            err.to_string().contains("{version}, {kind} and {n}"),
+            "{err}"
         );
-        pb_jre.enable_steady_tick(Duration::from_millis(100));
+        assert!(expand_name_template("srv-{version", &version, 1).is_err());
+    }
 
-        // at the same time, spawn a thread to install the JRE
-        install_threads.spawn(async move {
-            pb_jre.set_message("Installing JRE...");
-            install_jre(&jre_version, &pb_jre)
-                .await
-                .wrap_err(format!("Failed to install JRE {jre_version}"))?;
+    #[test]
+    fn plan_install_names() {
+        let version = |id: &str| -> GameVersion {
+            serde_json::from_value(serde_json::json!({
+                "id": id,
+                "type": "release",
+                "url": "",
+                "time": "2023-06-12T13:25:51+00:00",
+                "releaseTime": "2023-06-12T13:25:51+00:00",
+            }))
+            .unwrap()
+        };
+        let (v1, v2) = (version("1.20.1"), version("1.19.4"));
+        let names = |installs: Vec<(&GameVersion, String)>| {
+            installs.into_iter().map(|(_, name)| name).collect_vec()
+        };
 
-            Ok::<(), eyre::Report>(())
-        });
+        let options = InstallOptions::default();
+        let installs = plan_installs(vec![&v1, &v2, &v1], &options).unwrap();
+        assert_eq!(names(installs), ["1.20.1", "1.19.4"]);
 
-        debug!(version = version_display, version.url, "Exiting loop");
+        let options = InstallOptions {
+            client: true,
+            ..Default::default()
+        };
+        let installs = plan_installs(vec![&v1], &options).unwrap();
+        assert_eq!(names(installs), ["1.20.1-client"]);
+
+        let options = InstallOptions {
+            name: Some("test-{n}".to_string()),
+            ..Default::default()
+        };
+        let installs = plan_installs(vec![&v1, &v1, &v2], &options).unwrap();
+        assert_eq!(names(installs), ["test-1", "test-2"]);
+
+        let options = InstallOptions {
+            name: Some("test".to_string()),
+            ..Default::default()
+        };
+        let installs = plan_installs(vec![&v1, &v1], &options).unwrap();
+        assert_eq!(names(installs), ["test"]);
+        let err = plan_installs(vec![&v1, &v2], &options).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("More than one instance would be named `test`"),
+            "{err}"
+        );
     }
 
-    while let Some(result) = install_threads.join_next().await {
-        result?.wrap_err("Failed to install server or JRE")?;
+    #[test]
+    fn java_major_versions() {
+        assert_eq!(java_major_version("17.0.9"), Some(17));
+        assert_eq!(java_major_version("21"), Some(21));
+        assert_eq!(java_major_version("22-ea"), Some(22));
+        assert_eq!(java_major_version("1.8.0_392"), Some(8));
+        assert_eq!(java_major_version("unknown"), None);
     }
 
-    Ok(())
-}
+    #[tokio::test]
+    async fn check_missing_system_java() {
+        let err = check_system_java(Path::new("/nonexistent/bin/java"), 17)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Java 17 or newer"), "{err}");
+    }
 
-// pub(crate) async fn install_version(version: &GameVersion) -> Result<()> {
-//     install_versions(vec![version]).await
-// }
+    #[test]
+    fn server_args_order() {
+        let mut settings = InstanceSettings::new(17);
+        settings.java.args = vec![
+            "-Xmx4G".to_string(),
+            "-Dlog4j2.formatMsgNoLookups=true".to_string(),
+        ];
+        settings.server.args = vec![
+            "--nogui".to_string(),
+            "--port".to_string(),
+            "25566".to_string(),
+        ];
+
+        assert_eq!(
+            server_args(&settings),
+            [
+                "-Xmx4G",
+                "-Dlog4j2.formatMsgNoLookups=true",
+                "-jar",
+                "server.jar",
+                "--nogui",
+                "--port",
+                "25566"
+            ]
+        );
+        assert!(misplaced_args(&settings).is_empty());
+    }
 
-#[instrument(err, ret(level = "debug"), skip(pb))]
-async fn install_jre(major_version: &u8, pb: &ProgressBar) -> Result<()> {
-    let jre_dir = JRE_BASE_DIR.join(major_version.to_string());
+    #[test]
+    fn bind_and_ipv6_settings() {
+        use rand::distributions::{Alphanumeric, DistString};
 
-    if META!().jre_installed(major_version) {
-        pb.finish_with_message("Cancelled (already installed)");
-        debug!("Cancelled JRE install (this should never happen)");
-        return Ok(());
+        let suf: String = Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
+        let dir = std::env::temp_dir().join(format!("mcdl-bind-{suf}"));
+        std::fs::create_dir_all(&dir).unwrap();
+        scopeguard::defer! {
+            let _ = std::fs::remove_dir_all(&dir);
+        }
+        let properties_path = dir.join("server.properties");
+        std::fs::write(&properties_path, "motd=hi\nserver-ip=10.0.0.1\n").unwrap();
+
+        // neither set: the properties are left alone
+        let mut settings = InstanceSettings::new(17);
+        write_server_ip(&dir, &settings.server).unwrap();
+        let properties = ServerProperties::read_or_default(&properties_path).unwrap();
+        assert_eq!(properties.get("server-ip"), Some("10.0.0.1"));
+        assert!(!server_args(&settings).contains(&PREFER_IPV6_ARG.into()));
+
+        settings.server.ipv6 = true;
+        write_server_ip(&dir, &settings.server).unwrap();
+        let properties = ServerProperties::read_or_default(&properties_path).unwrap();
+        assert_eq!(properties.get("server-ip"), Some(""));
+        assert_eq!(properties.get("motd"), Some("hi"));
+        let args = server_args(&settings);
+        let jar = args.iter().position(|arg| arg == "-jar").unwrap();
+        assert!(args[..jar].contains(&PREFER_IPV6_ARG.into()));
+
+        settings.server.bind = Some("::1".parse().unwrap());
+        write_server_ip(&dir, &settings.server).unwrap();
+        let properties = ServerProperties::read_or_default(&properties_path).unwrap();
+        assert_eq!(properties.get("server-ip"), Some("::1"));
     }
 
-    pb.set_message("Downloading JRE...");
-    info!("Starting JRE download");
-    let jre = download_jre(major_version).await?;
-    info!("Downloaded JRE");
+    #[test]
+    fn find_misplaced_args() {
+        let mut settings = InstanceSettings::new(17);
+        settings.java.args = vec![
+            "-Xmx4G".to_string(),
+            "-jar".to_string(),
+            "paper.jar".to_string(),
+        ];
+        settings.server.args = vec!["--nogui".to_string(), "-XX:+UseG1GC".to_string()];
+
+        let warnings = misplaced_args(&settings);
+        assert_eq!(warnings.len(), 3, "{warnings:?}");
+        assert!(warnings[0].contains("`-jar`"));
+        assert!(warnings[1].contains("`paper.jar`"));
+        assert!(warnings[2].contains("`-XX:+UseG1GC`"));
+
+        // jars that are part of a flag, or the value of one, aren't the server jar
+        settings.java.args = vec![
+            "-javaagent:x.jar".to_string(),
+            "-Xbootclasspath/a:lib.jar".to_string(),
+            "-cp".to_string(),
+            "lib.jar".to_string(),
+        ];
+        settings.server.args = vec![];
+        assert_eq!(misplaced_args(&settings), Vec::<String>::new());
+    }
 
-    pb.set_message("Extracting JRE...");
-    info!("Starting JRE extraction");
-    extract_jre(jre, &jre_dir).wrap_err("Failed to extract JRE")?;
-    info!("Extracted JRE");
+    #[test]
+    fn server_env_precedence() {
+        let mut settings = InstanceSettings::new(17);
+        settings.env.insert("TZ".to_string(), "UTC".to_string());
+        settings.env.insert("A".to_string(), "settings".to_string());
 
-    pb.set_message("Updating metadata...");
-    META!().add_jre(*major_version);
-    META!().save()?;
+        let env = server_env(&settings, vec![("A".to_string(), "cli".to_string())]);
+        assert_eq!(env["TZ"], "UTC");
+        assert_eq!(env["A"], "cli");
+    }
 
-    pb.finish_with_message("Done!");
-    info!("Installed JRE");
-    Ok(())
-}
+    #[tokio::test]
+    #[cfg(unix)]
+    async fn run_hook_env_and_status() {
+        let dir = std::env::temp_dir();
+        let hook = r#"test "$MCDL_INSTANCE_ID" = 1.20.1 && test "$MCDL_INSTANCE_VERSION" = 1.20.1 && test "$MCDL_JRE_VERSION" = 17 && test "$(pwd -P)" = "$(cd "$MCDL_INSTANCE_DIR" && pwd -P)""#;
+        run_hook(hook, &dir, "1.20.1", "1.20.1", 17).await.unwrap();
+
+        // a named instance keeps its name and version apart
+        let hook =
+            r#"test "$MCDL_INSTANCE_ID" = survival && test "$MCDL_INSTANCE_VERSION" = 1.20.1"#;
+        run_hook(hook, &dir, "survival", "1.20.1", 17)
+            .await
+            .unwrap();
+
+        let err = run_hook("exit 3", &dir, "1.20.1", "1.20.1", 17)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("exited with"), "{err}");
+    }
 
-#[instrument(err, ret(level = "debug"), skip(id))]
-pub(crate) fn uninstall_instance(id: VersionNumber) -> Result<()> {
-    let pb = ProgressBar::new_spinner()
-        .with_style(PB_STYLE.clone())
-        .with_prefix(id.to_string());
-    pb.enable_steady_tick(Duration::from_millis(100));
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn extract_jre_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use rand::distributions::{Alphanumeric, DistString};
+
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        for (path, mode) in [
+            ("jdk-17.0.9+9-jre/bin/java", 0o644), // fixed up as a fallback
+            ("jdk-17.0.9+9-jre/bin/keytool", 0o755),
+            ("jdk-17.0.9+9-jre/lib/libjava.so", 0o644),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(0);
+            header.set_mode(mode);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, std::io::empty())
+                .unwrap();
+        }
+        let archive = builder.into_inner().unwrap().finish().unwrap();
 
-    let mut instance_files = vec![];
+        let suf: String = Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
+        let dir = std::env::temp_dir().join(format!("mcdl-jre-{suf}"));
+        scopeguard::defer! {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
 
-    pb.set_message("Checking if instance exists...");
-    if let Some(instance) = META!().instances.get(&id.to_string()) {
-        instance_files.extend(instance.files.clone());
-    } else {
-        return Err(eyre!("Instance `{id}` does not exist"));
+        extract_jre(archive.into(), &dir).unwrap();
+
+        let mode = |path: &str| {
+            std::fs::metadata(dir.join(path))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777
+        };
+        assert_eq!(mode("bin/java"), 0o755);
+        assert_eq!(mode("bin/keytool"), 0o755);
+        assert_eq!(mode("lib/libjava.so"), 0o644);
     }
 
-    pb.set_message("Removing files...");
-    for path in &instance_files {
-        if !path.exists() {
-            warn!(?path, "File does not exist");
-            continue;
+    #[tokio::test]
+    #[cfg(target_os = "linux")]
+    async fn partial_jre_is_reextracted() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use rand::distributions::{Alphanumeric, DistString};
+
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(0);
+        header.set_mode(0o755);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "jdk-17.0.9+9-jre/bin/java", std::io::empty())
+            .unwrap();
+        let archive: Bytes = builder.into_inner().unwrap().finish().unwrap().into();
+
+        let suf: String = Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
+        let dir = std::env::temp_dir().join(format!("mcdl-partial-jre-{suf}"));
+        let jre_dir = dir.join("17");
+        let java_path = java_path_in(&jre_dir);
+        scopeguard::defer! {
+            std::fs::remove_dir_all(&dir).unwrap();
         }
 
-        if path.is_dir() {
-            info!(?path, "Removing directory");
-            std::fs::remove_dir_all(path)
-                .wrap_err(format!("Failed to remove directory {}", path.display()))?;
-        } else {
-            info!(?path, "Removing file");
-            std::fs::remove_file(path)
-                .wrap_err(format!("Failed to remove file {}", path.display()))?;
-        }
+        // an interrupted extraction, recorded in the metadata or not
+        std::fs::create_dir_all(jre_dir.join("lib")).unwrap();
+        std::fs::create_dir_all(partial_jre_dir(&jre_dir).join("bin")).unwrap();
+        let mut meta = AppMeta::new(dir.join("meta.mpk"));
+        meta.add_instance(InstanceMeta::new("1.20.1".parse().unwrap(), 17));
+        meta.add_jre(17);
+        let meta = Mutex::new(meta);
+
+        let installed = ensure_jre(&meta, &"1.20.1".to_string(), 17, &java_path, || async {
+            extract_jre(archive, &jre_dir)
+        })
+        .await
+        .unwrap();
+
+        assert!(installed, "partial JRE was treated as installed");
+        assert!(
+            java_path.is_file(),
+            "{} does not exist",
+            java_path.display()
+        );
+        assert!(
+            !jre_dir.join("lib").exists(),
+            "partial JRE was not replaced"
+        );
+        assert!(!partial_jre_dir(&jre_dir).exists());
+    }
 
-        META!()
-            .instances
-            .get_mut(&id.to_string())
-            .unwrap()
-            .remove_file(path);
-        META!().save()?;
+    #[test]
+    fn check_disk_space_by_filesystem() {
+        // not created, so the check falls back to the temp directory
+        let dir = std::env::temp_dir().join("mcdl-space-check").join("1.20.1");
+        check_disk_space(vec![(dir.clone(), 1024), (std::env::temp_dir(), 0)]).unwrap();
+
+        let err = check_disk_space(vec![
+            (dir, u64::MAX / 2),
+            (std::env::temp_dir(), u64::MAX / 2),
+        ])
+        .unwrap_err()
+        .to_string();
+        assert!(err.starts_with("Not enough disk space in"), "{err}");
+        assert!(err.contains("--no-space-check"), "{err}");
     }
 
-    pb.set_message("Updating metadata...");
-    META!().remove_instance(&id.to_string());
-    META!().save()?;
+    #[test]
+    fn verify_recorded_jar() {
+        use rand::distributions::{Alphanumeric, DistString};
 
-    // bonus: remove jre if it's not used by any other instances
+        let suf: String = Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
+        let dir = std::env::temp_dir().join(format!("mcdl-verify-{suf}"));
+        std::fs::create_dir_all(&dir).unwrap();
+        scopeguard::defer! {
+            let _ = std::fs::remove_dir_all(&dir);
+        }
 
-    pb.finish_with_message("Done!");
-    Ok(())
-}
+        let mut instance = InstanceMeta::new("1.20.1".parse().unwrap(), 17);
+        instance.location = Some(dir.clone());
+        assert!(verify_instance_jar("1.20.1", &instance).is_none());
+
+        // the SHA-1 of "hello"
+        instance.download = Some(
+            serde_json::from_value(serde_json::json!({
+                "sha1": "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d",
+                "size": 5,
+                "url": "https://example.com/server.jar",
+            }))
+            .unwrap(),
+        );
+        let err = verify_instance_jar("1.20.1", &instance)
+            .unwrap()
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Failed to read"), "{err}");
 
-#[instrument(err, ret(level = "debug"), skip(id))]
-pub(crate) async fn run_instance(id: VersionNumber) -> Result<()> {
-    let instance_path = INSTANCE_BASE_DIR.join(id.to_string());
+        std::fs::write(dir.join("server.jar"), "hello").unwrap();
+        verify_instance_jar("1.20.1", &instance).unwrap().unwrap();
 
-    if !META!().instance_installed(&id.to_string()) {
-        return Err(eyre!("Instance `{id}` does not exist"));
+        std::fs::write(dir.join("server.jar"), "tampered").unwrap();
+        let err = verify_instance_jar("1.20.1", &instance)
+            .unwrap()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "server.jar doesn't match https://example.com/server.jar"
+        );
     }
 
-    let settings =
-        InstanceSettings::from_file(INSTANCE_SETTINGS_BASE_DIR.join(format!("{id}.toml"))).await?;
-    debug!(?settings, "Loaded instance settings");
+    #[test]
+    fn check_tags() {
+        for tag in ["prod", "test-2", "1.20", "creative_world"] {
+            assert_eq!(validate_tag(tag).unwrap(), tag);
+        }
+        for tag in ["", "two words", "a,b", "a/b"] {
+            assert!(validate_tag(tag).is_err(), "{tag:?}");
+        }
+    }
 
-    // check if the JRE is installed and install it if not
-    let jre_version = settings.java.version;
+    #[test]
+    fn check_instance_names() {
+        for name in [
+            "1.20.1",
+            "3D Shareware v1.34",
+            "my server",
+            "CONSOLE",
+            "a.b",
+        ] {
+            assert!(check_instance_name(name, true).is_ok(), "{name}");
+            assert!(check_instance_name(name, false).is_ok(), "{name}");
+        }
 
-    if !META!().jre_installed(&jre_version) {
-        debug!(jre = jre_version, "Installing JRE due to config change");
-        let pb = ProgressBar::new_spinner()
-            .with_style(PB_STYLE.clone())
-            .with_prefix(format!("JRE {jre_version} for {id}"));
-        pb.enable_steady_tick(Duration::from_millis(100));
+        let err = |name, windows| check_instance_name(name, windows).unwrap_err().to_string();
+        assert_eq!(
+            err("a/b", false),
+            "`a/b` is not a valid instance name: it contains characters that aren't allowed in \
+             file names (try `a-b`)"
+        );
+        assert!(err("..", false).contains("is a relative path (try `instance-..`)"));
+        assert!(err("  ", false).contains("must not be empty"));
 
-        install_jre(&jre_version, &pb).await?;
+        // only reserved on Windows
+        for name in ["CON", "nul.txt", "what?", "server."] {
+            assert!(check_instance_name(name, false).is_ok(), "{name}");
+        }
+        assert!(err("CON", true).ends_with("is a reserved device name (try `instance-CON`)"));
+        assert!(err("nul.txt", true).contains("is a reserved device name"));
+        assert!(err("what?", true).ends_with("(try `what-`)"));
+        assert!(err("server. ", true).ends_with("ends with a dot or space (try `server`)"));
     }
 
-    // make sure JRE version is correct
-    META!()
-        .instances
-        .get_mut(&id.to_string())
-        .ok_or_else(|| eyre!("Instance metadata not found for {id}"))?
-        .jre = jre_version;
-    META!().save()?;
-
-    // add all arguments
-    let mut args: Vec<OsString> = vec![];
-    args.extend(settings.java.args.iter().map(|s| s.into())); // jvm args
-    args.extend(vec!["-jar".into(), settings.server.jar.into()]); // server jar
-    args.extend(settings.server.args.iter().map(|s| s.into())); // server args
+    #[test]
+    fn validate_output_dirs() {
+        use rand::distributions::{Alphanumeric, DistString};
 
-    let args_string = args
-        .iter()
-        .map(|s| shell_escape::escape(Cow::Borrowed(s.to_str().unwrap())))
-        .join(" ");
+        let suf: String = Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
+        let base = std::env::temp_dir().join(format!("mcdl-output-{suf}"));
+        scopeguard::defer! {
+            std::fs::remove_dir_all(&base).unwrap();
+        }
 
-    let java_path = get_java_path(jre_version);
+        let mut meta = AppMeta::new(base.join("meta.mpk"));
 
-    debug!(
-        "Starting server with command line: {java} {args}",
-        java = java_path.display(),
-        args = args_string
-    );
-    let mut child = Command::new(&java_path)
-        .current_dir(&instance_path)
-        .kill_on_drop(true)
-        .args(&args)
-        .spawn()
-        .wrap_err(format!(
-            "Failed to start server with command line: {java} {args}",
-            java = java_path.display(),
-            args = args_string
-        ))?;
-    info!("Started server");
+        // made absolute, but only created by the install
+        std::fs::create_dir_all(&base).unwrap();
+        let dir = validate_output_dir(&base.join("new").join("..").join("server"), &meta).unwrap();
+        assert!(dir.is_absolute() && !dir.exists());
+        assert_eq!(dir, base.canonicalize().unwrap().join("server"));
 
-    let status = child.wait().await.wrap_err("Failed to wait for server")?;
-    if !status.success() {
-        error!(?status, "Server exited with an error");
-        let upload = Confirm::new()
-            .with_prompt("Server exited with an error. Would you like to upload the crash report?")
-            .default(false)
-            .interact()?;
+        // an empty directory is fine
+        let empty = base.join("empty");
+        std::fs::create_dir_all(&empty).unwrap();
+        assert_eq!(
+            validate_output_dir(&empty, &meta).unwrap(),
+            empty.canonicalize().unwrap()
+        );
 
-        if upload {
-            debug!("Uploading crash report");
-            let crash_reports = instance_path.join("crash-reports");
+        let mut instance = InstanceMeta::new("1.20.1".parse().unwrap(), 17);
+        instance.location = Some(dir.clone());
+        meta.add_instance(instance);
+        let err = validate_output_dir(&dir, &meta).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("already used by instance `1.20.1`"),
+            "{err}"
+        );
 
-            let latest = std::fs::read_dir(crash_reports)
-                .wrap_err("Failed to read crash reports directory")?
-                .filter_map(Result::ok)
-                .max_by(|a, b| {
-                    let a = a.metadata().unwrap().modified().unwrap();
-                    let b = b.metadata().unwrap().modified().unwrap();
+        let other = base.join("other");
+        std::fs::create_dir_all(&other).unwrap();
+        std::fs::write(other.join("server.jar"), "").unwrap();
+        let err = validate_output_dir(&other, &meta).unwrap_err();
+        assert!(err.to_string().contains("is not empty"), "{err}");
+        assert!(other.join("server.jar").exists());
+    }
 
-                    a.cmp(&b)
-                })
-                .ok_or_else(|| eyre!("No crash reports found"))?;
+    #[tokio::test]
+    async fn stored_asset_is_not_downloaded() {
+        use rand::distributions::{Alphanumeric, DistString};
 
-            let content =
-                std::fs::read_to_string(latest.path()).wrap_err("Failed to read crash report")?;
+        let suf: String = Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
+        let dir = std::env::temp_dir().join(format!("mcdl-assets-{suf}"));
+        scopeguard::defer! {
+            let _ = std::fs::remove_dir_all(&dir);
+        }
 
-            // upload to mclo.gs
-            let response = REQWEST_CLIENT
-                .post("https://api.mclo.gs/1/log")
-                .form(&[("content", content)])
-                .send()
-                .await?;
+        let object: AssetObject = serde_json::from_str(
+            r#"{"hash": "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d", "size": 5}"#,
+        )
+        .unwrap();
+        let path = dir.join(object.path().unwrap());
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        // stored by another install in the meantime
+        std::fs::write(&path, "hello").unwrap();
+
+        download_asset(&object, &path).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello");
+        assert_eq!(
+            std::fs::read_dir(path.parent().unwrap()).unwrap().count(),
+            1
+        );
+    }
 
-            // parse json response
-            let response: serde_json::Value = response.json().await?;
+    #[test]
+    fn remove_partial_installs() {
+        use rand::distributions::{Alphanumeric, DistString};
+
+        let suf: String = Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
+        let dir = std::env::temp_dir().join(format!("mcdl-partial-{suf}"));
+        let instance = dir.join("instance");
+        let settings = dir.join("settings.toml");
+        let finished = dir.join("finished");
+        let jre = dir.join("jre");
+        for path in [&instance, &finished, &jre] {
+            std::fs::create_dir_all(path).unwrap();
+        }
+        std::fs::write(&settings, "").unwrap();
 
-            if response["success"].as_bool().unwrap() {
-                println!(
-                    "Crash report uploaded to {}",
-                    response["url"].as_str().unwrap()
-                );
-                debug!(
-                    url = response["url"].as_str().unwrap(),
-                    "Crash report uploaded"
-                );
-            } else {
-                return Err(eyre!(
-                    "Failed to upload crash report: {}",
-                    response["error"].as_str().unwrap()
-                ));
-            }
+        scopeguard::defer! {
+            std::fs::remove_dir_all(&dir).unwrap();
         }
 
-        return Err(eyre!(
-            "Server exited with {status}. Command line: {java} {args}",
-            java = java_path.display(),
-            args = args_string
-        ));
+        let mut meta = AppMeta::new(dir.join("meta.mpk"));
+        meta.add_instance(InstanceMeta::new("1.20.2".parse().unwrap(), 17));
+        PartialInstall::remove_all(
+            &[
+                PartialInstall::Instance(
+                    "1.20.1".to_string(),
+                    vec![instance.clone(), settings.clone()],
+                ),
+                PartialInstall::Instance("1.20.2".to_string(), vec![finished.clone()]),
+                PartialInstall::Jre(17, jre.clone()),
+            ],
+            &meta,
+        );
+
+        assert!(!instance.exists() && !settings.exists() && !jre.exists());
+        assert!(finished.exists(), "finished install was removed");
     }
 
-    Ok(())
-}
+    #[tokio::test]
+    async fn ensure_jre_is_saved() {
+        use rand::distributions::{Alphanumeric, DistString};
 
-#[instrument(err, ret(level = "debug"))]
-pub(crate) fn locate(what: &String) -> Result<()> {
-    match what.to_ascii_lowercase().as_str() {
-        "java" => {
-            println!("JRE base directory: {}", JRE_BASE_DIR.display());
-        }
-        "instance" => {
-            println!("Instance base directory: {}", INSTANCE_BASE_DIR.display());
-        }
-        "config" => {
-            println!(
-                "Instance settings base directory: {}",
-                INSTANCE_SETTINGS_BASE_DIR.display()
-            );
-        }
-        "log" => {
-            println!("Log base directory: {}", LOG_BASE_DIR.display());
-        }
-        _ => {
-            return Err(eyre!("Unknown location: {what}"));
+        let suf: String = Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
+        let path = PathBuf::from(format!("meta-{suf}.mpk"));
+        let java_path = PathBuf::from(format!("java-{suf}"));
+
+        scopeguard::defer! {
+            std::fs::remove_file(&path).unwrap();
+            std::fs::remove_file(format!("meta-{suf}.mpk.lock")).unwrap();
+            std::fs::remove_file(&java_path).unwrap();
         }
-    }
 
-    Ok(())
-}
+        let id = "1.20.1".to_string();
+        let mut meta = AppMeta::new(path.clone());
+        meta.add_instance(InstanceMeta::new(id.parse().unwrap(), 8));
+        meta.save().unwrap();
+
+        let installs = std::sync::atomic::AtomicUsize::new(0);
+        // each run reads the metadata from disk, like a new process would
+        for _ in 0..2 {
+            let meta = Mutex::new(AppMeta::read_or_create(&path).unwrap());
+            ensure_jre(&meta, &id, 17, &java_path, || async {
+                installs.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                std::fs::write(&java_path, "")?;
+                // like `install_jre`, which records the JRE on disk
+                let mut meta = AppMeta::begin_update(&meta)?;
+                meta.add_jre(17);
+                meta.save()
+            })
+            .await
+            .unwrap();
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        assert_eq!(installs.into_inner(), 1, "JRE was installed more than once");
+        let meta = AppMeta::from_file(&path).unwrap();
+        assert_eq!(meta.instances[&id].jre, 17);
+        assert!(meta.jre_installed(&17));
+    }
 
     #[tokio::test]
     #[cfg(not(target_os = "macos"))]
@@ -462,10 +3644,10 @@ mod tests {
             "JRE 8 is already installed"
         );
 
-        install_jre(&version, &ProgressBar::hidden()).await.unwrap();
+        install_jre(&version, &Progress::hidden()).await.unwrap();
 
         assert!(
-            get_java_path(version.clone()).exists(),
+            get_java_path(version).exists(),
             "{:?} does not exist",
             get_java_path(8)
         );
@@ -476,9 +3658,46 @@ mod tests {
 
 // platform specific stuff
 
-#[cfg(windows)]
+/// Extracts a JRE archive into `jre_dir`, replacing whatever is there
+///
+/// The archive is unpacked next to `jre_dir` and only moved into place once it is complete, so an
+/// interrupted extraction never leaves a partial JRE where a working one is expected
 #[instrument(err, ret(level = "debug"), skip(jre))]
 fn extract_jre(jre: Bytes, jre_dir: &PathBuf) -> Result<()> {
+    let partial_dir = partial_jre_dir(jre_dir);
+    if partial_dir.exists() {
+        debug!(path = %partial_dir.display(), "Removing leftover partial JRE");
+        std::fs::remove_dir_all(&partial_dir)?;
+    }
+
+    if let Err(e) = unpack_jre(jre, &partial_dir) {
+        let _ = std::fs::remove_dir_all(&partial_dir);
+        return Err(e);
+    }
+
+    // anything already here is incomplete, otherwise it wouldn't be installed again
+    if jre_dir.exists() {
+        debug!(path = %jre_dir.display(), "Replacing incomplete JRE");
+        std::fs::remove_dir_all(jre_dir)?;
+    }
+    std::fs::rename(&partial_dir, jre_dir).wrap_err(format!(
+        "Failed to move JRE into place: {path}",
+        path = jre_dir.display()
+    ))?;
+
+    Ok(())
+}
+
+/// Where a JRE is unpacked before being moved to `jre_dir`
+fn partial_jre_dir(jre_dir: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(jre_dir.file_name().unwrap_or_default());
+    name.push(".partial");
+    jre_dir.with_file_name(name)
+}
+
+#[cfg(windows)]
+fn unpack_jre(jre: Bytes, jre_dir: &PathBuf) -> Result<()> {
     use std::io::{BufReader, Cursor, Read};
 
     use zip::ZipArchive;
@@ -523,8 +3742,7 @@ fn extract_jre(jre: Bytes, jre_dir: &PathBuf) -> Result<()> {
 }
 
 #[cfg(target_os = "linux")]
-#[instrument(err, ret(level = "debug"), skip(jre))]
-fn extract_jre(jre: Bytes, jre_dir: &PathBuf) -> Result<()> {
+fn unpack_jre(jre: Bytes, jre_dir: &PathBuf) -> Result<()> {
     use std::os::unix::fs::PermissionsExt;
 
     use bytes::Buf;
@@ -533,6 +3751,10 @@ fn extract_jre(jre: Bytes, jre_dir: &PathBuf) -> Result<()> {
 
     let mut reader = jre.reader();
     let mut archive = Archive::new(GzDecoder::new(&mut reader));
+    // apply each entry's permission bits (but not setuid and friends), so every binary and
+    // library in the JRE keeps the mode it was packaged with
+    archive.set_preserve_permissions(false);
+    archive.set_mask(0);
     let entries = archive.entries()?;
 
     std::fs::create_dir_all(jre_dir).wrap_err(format!(
@@ -548,6 +3770,10 @@ fn extract_jre(jre: Bytes, jre_dir: &PathBuf) -> Result<()> {
         let path: PathBuf = path.components().skip(1).collect();
         let path = jre_dir.join(path);
 
+        // archives don't have to list directories before their contents
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
         entry.unpack(path)?;
     }
 
@@ -560,23 +3786,58 @@ fn extract_jre(jre: Bytes, jre_dir: &PathBuf) -> Result<()> {
         ));
     }
 
+    // the archive should already have made it executable
     let mut perms = std::fs::metadata(&java_path)?.permissions();
-    perms.set_mode(0o755);
-    std::fs::set_permissions(&java_path, perms)?;
+    if perms.mode() & 0o111 == 0 {
+        warn!("java is not executable, fixing permissions");
+        perms.set_mode(0o755);
+        std::fs::set_permissions(&java_path, perms)?;
+    }
 
     Ok(())
 }
 
 #[cfg(not(any(windows, target_os = "linux")))]
-#[instrument(err, ret(level = "debug"), skip(_jre))]
-fn extract_jre(_jre: Bytes, _jre_dir: &PathBuf) -> Result<()> {
+fn unpack_jre(_jre: Bytes, _jre_dir: &PathBuf) -> Result<()> {
     Err(eyre!("Unsupported OS")) // TODO fail gracefully
 }
 
 #[instrument(ret(level = "debug"))]
 fn get_java_path(version: u8) -> PathBuf {
-    JRE_BASE_DIR
-        .join(version.to_string())
+    java_path_in(&JRE_BASE_DIR.join(version.to_string()))
+}
+
+fn java_path_in(jre_dir: &Path) -> PathBuf {
+    jre_dir
         .join("bin")
         .join(format!("java{}", std::env::consts::EXE_SUFFIX))
 }
+
+/// The `java` executable an instance runs with, whether or not it is installed
+fn instance_java_path(instance_dir: &Path, settings: &InstanceSettings) -> PathBuf {
+    if settings.java.system {
+        // if it isn't found, `run` reports that before trying to start it
+        system_java_path()
+            .unwrap_or_else(|| PathBuf::from(format!("java{}", std::env::consts::EXE_SUFFIX)))
+    } else if settings.java.bundled {
+        java_path_in(&bundled_jre_dir(instance_dir, settings.java.version))
+    } else {
+        get_java_path(settings.java.version)
+    }
+}
+
+/// The system `java` executable, from `JAVA_HOME` if it's set and `PATH` otherwise
+fn system_java_path() -> Option<PathBuf> {
+    if let Some(java_home) = std::env::var_os("JAVA_HOME").filter(|v| !v.is_empty()) {
+        return Some(java_path_in(Path::new(&java_home))).filter(|p| p.is_file());
+    }
+
+    let java = format!("java{}", std::env::consts::EXE_SUFFIX);
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(&java))
+        .find(|p| p.is_file())
+}
+
+fn bundled_jre_dir(instance_dir: &Path, version: u8) -> PathBuf {
+    instance_dir.join("jre").join(version.to_string())
+}
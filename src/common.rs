@@ -1,3 +1,5 @@
+use std::path::{Path, PathBuf};
+
 use lazy_static::lazy_static;
 
 lazy_static! {
@@ -12,31 +14,119 @@ lazy_static! {
             env!("VERGEN_GIT_SHA"),
         )
     };
-    pub static ref REQWEST_CLIENT: reqwest::Client = {
-        let mut headers = reqwest::header::HeaderMap::new();
-        headers.insert(
-            reqwest::header::USER_AGENT,
-            reqwest::header::HeaderValue::from_str(&format!(
-                "mcdl/{} ({})",
-                MCDL_VERSION.as_str(),
-                env!("CARGO_PKG_HOMEPAGE")
-            ))
-            .expect("failed to build user agent header"),
-        );
-
-        reqwest::Client::builder()
-            .default_headers(headers)
-            .tcp_keepalive(Some(std::time::Duration::from_secs(10)))
-            .build()
-            .expect("failed to build reqwest client")
-    };
-    pub static ref PROJ_DIRS: directories::ProjectDirs =
-        directories::ProjectDirs::from("com.github", "paenis", env!("CARGO_PKG_NAME"))
-            .expect("failed to get project directories");
+    /// The version with the build details, for `--version` and bug reports
+    pub static ref MCDL_LONG_VERSION: String = format!(
+        "{}\ncommit: {}\nprofile: {}\nrustc channel: {}\ntarget: {}",
+        MCDL_VERSION.as_str(),
+        env!("VERGEN_GIT_SHA"),
+        match env!("VERGEN_CARGO_OPT_LEVEL") {
+            "0" | "1" => "debug",
+            _ => "release",
+        },
+        env!("MCDL_RUSTC_CHANNEL"),
+        env!("VERGEN_CARGO_TARGET_TRIPLE"),
+    );
+    pub static ref REQWEST_CLIENT: reqwest::Client = client_builder()
+        .build()
+        .expect("failed to build reqwest client");
+    /// A client that returns redirects instead of following them, for when the redirect itself matters
+    pub static ref REQWEST_CLIENT_NO_REDIRECT: reqwest::Client = client_builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("failed to build reqwest client");
+    pub static ref PROJ_DIRS: ProjectDirs = ProjectDirs::new();
     pub static ref LOG_BASE_DIR: std::path::PathBuf = PROJ_DIRS.data_local_dir().join("log");
+    pub static ref CONFIG_PATH: std::path::PathBuf = PROJ_DIRS.config_dir().join("config.toml");
+    pub(crate) static ref CONFIG: parking_lot::RwLock<crate::types::config::Config> =
+        parking_lot::RwLock::new(crate::types::config::Config::default());
     static ref META_PATH: std::path::PathBuf = PROJ_DIRS.data_local_dir().join("meta.mpk");
     pub(crate) static ref META: std::sync::Arc<parking_lot::Mutex<crate::types::meta::AppMeta>> =
         std::sync::Arc::new(parking_lot::Mutex::new(
-            crate::types::meta::AppMeta::read_or_create(META_PATH.as_path())
+            crate::types::meta::AppMeta::read_or_create(META_PATH.as_path()).unwrap_or_else(|e| {
+                // nothing works without the meta, and it can't be replaced without losing data
+                eprintln!("Error: {e:?}");
+                std::process::exit(1);
+            })
         ));
 }
+
+fn client_builder() -> reqwest::ClientBuilder {
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert(
+        reqwest::header::USER_AGENT,
+        reqwest::header::HeaderValue::from_str(&format!(
+            "mcdl/{} ({})",
+            MCDL_VERSION.as_str(),
+            env!("CARGO_PKG_HOMEPAGE")
+        ))
+        .expect("failed to build user agent header"),
+    );
+
+    reqwest::Client::builder()
+        .default_headers(headers)
+        .tcp_keepalive(Some(std::time::Duration::from_secs(10)))
+}
+
+/// The directories mcdl keeps its files in
+///
+/// These are the platform's usual directories, unless `MCDL_HOME` is set, in which case
+/// everything is kept under it. If there is no home directory to find the usual ones from, e.g.
+/// in a minimal container, a directory in the temporary directory is used instead
+pub struct ProjectDirs {
+    data_local: PathBuf,
+    config: PathBuf,
+    config_local: PathBuf,
+    cache: PathBuf,
+}
+
+impl ProjectDirs {
+    fn new() -> Self {
+        if let Some(home) = std::env::var_os("MCDL_HOME").filter(|home| !home.is_empty()) {
+            return Self::under(Path::new(&home));
+        }
+
+        match directories::ProjectDirs::from("com.github", "paenis", env!("CARGO_PKG_NAME")) {
+            Some(dirs) => Self {
+                data_local: dirs.data_local_dir().to_path_buf(),
+                config: dirs.config_dir().to_path_buf(),
+                config_local: dirs.config_local_dir().to_path_buf(),
+                cache: dirs.cache_dir().to_path_buf(),
+            },
+            None => {
+                let home = std::env::temp_dir().join(env!("CARGO_PKG_NAME"));
+                // this runs before logging is set up, since the log directory is one of these
+                eprintln!(
+                    "Warning: couldn't find a home directory, so files are kept in {}. Set MCDL_HOME to keep them elsewhere",
+                    home.display()
+                );
+                Self::under(&home)
+            }
+        }
+    }
+
+    /// Keeps everything under `home`
+    fn under(home: &Path) -> Self {
+        Self {
+            data_local: home.join("data"),
+            config: home.join("config"),
+            config_local: home.join("config"),
+            cache: home.join("cache"),
+        }
+    }
+
+    pub fn data_local_dir(&self) -> &Path {
+        &self.data_local
+    }
+
+    pub fn config_dir(&self) -> &Path {
+        &self.config
+    }
+
+    pub fn config_local_dir(&self) -> &Path {
+        &self.config_local
+    }
+
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache
+    }
+}
@@ -5,16 +5,19 @@ pub(crate) mod common;
 pub(crate) mod types;
 pub(crate) mod utils;
 
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::IsTerminal;
-use std::path::PathBuf;
+use std::io::{IsTerminal, Read};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
+use std::time::Duration;
 
 use async_once::AsyncOnce;
 use chrono::Utc;
 use clap::builder::NonEmptyStringValueParser;
 use clap::error::ErrorKind;
-use clap::{arg, command, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
 use color_eyre::config::{HookBuilder, Theme};
 use color_eyre::eyre::{eyre, Result, WrapErr};
 use color_eyre::owo_colors::OwoColorize;
@@ -23,12 +26,20 @@ use itertools::Itertools;
 use lazy_static::lazy_static;
 use prettytable::format::FormatBuilder;
 use prettytable::{row, Cell, Row, Table};
-use tracing::{debug, info, instrument};
-
-use crate::common::{LOG_BASE_DIR, MCDL_VERSION, META, PROJ_DIRS};
+use serde_json::json;
+use tracing::{debug, error, info, instrument, warn};
+
+use crate::common::{CONFIG, CONFIG_PATH, LOG_BASE_DIR, MCDL_LONG_VERSION, MCDL_VERSION, META};
+use crate::types::adoptium::{platform_images, Platform};
+use crate::types::checksum::Checksum;
+use crate::types::config::{validate_manifest_url, ColorChoice, Config, ProgressMode};
+use crate::types::env_file::EnvFile;
+use crate::types::mappings::MappingsFormat;
 use crate::types::meta::AsArgs;
-use crate::types::version::{GameVersionList, VersionNumber};
-use crate::utils::net::get_version_manifest;
+use crate::types::service::ServiceKind;
+use crate::types::version::{group_versions, GameVersion, GameVersionList, VersionNumber};
+use crate::utils::fs::dir_size;
+use crate::utils::net::{get_jre_assets, get_version_manifest, get_version_metadata};
 
 lazy_static! {
     static ref MANIFEST: AsyncOnce<GameVersionList> = AsyncOnce::new(async {
@@ -36,18 +47,89 @@ lazy_static! {
             .await
             .expect("Failed to get version manifest")
     });
+    /// The width to fit help and tables to, if set with `--max-term-width` or `COLUMNS`
+    ///
+    /// Read before parsing, since help is rendered while parsing
+    static ref TERM_WIDTH_OVERRIDE: Option<usize> =
+        global_arg(&std::env::args().collect_vec(), "--max-term-width")
+            .map(String::from)
+            .or_else(|| std::env::var("COLUMNS").ok())
+            .and_then(|width| width.parse().ok())
+            .filter(|&width| width > 0);
 }
 
 /* cli */
 
 #[doc(hidden)]
 #[derive(Parser, Debug)]
-#[command(author, version = MCDL_VERSION.as_str())]
+#[command(author, version = MCDL_VERSION.as_str(), long_version = MCDL_LONG_VERSION.as_str())]
 #[command(arg_required_else_help = true, subcommand_required = true)]
+// use the whole terminal instead of at most 100 columns
+#[command(max_term_width = 0)]
 /// A tool for managing Minecraft server versions
 struct Cli {
     #[command(subcommand)]
     action: Action,
+    #[arg(long, global = true, value_enum)]
+    /// When to use colored output
+    ///
+    /// Overrides the `color` key in the global config
+    color: Option<ColorChoice>,
+    #[arg(long, global = true, value_enum)]
+    /// How to display progress for long-running operations
+    ///
+    /// `auto` animates progress when stdout is a terminal and behaves like `plain` otherwise.
+    /// Overrides the `progress` key in the global config
+    progress: Option<ProgressMode>,
+    #[arg(long, global = true)]
+    /// Refuse versions that aren't releases, pre-releases or snapshots
+    ///
+    /// Such versions are hidden from `list`, and are an error anywhere else a version is given,
+    /// including commands on instances of them, such as `run` and `uninstall`.
+    /// This is only a safety gate for automation and changes nothing else.
+    /// Overrides the `strict` key in the global config
+    strict: bool,
+    #[arg(long, global = true, value_name = "PATH")]
+    /// Load the version manifest from a local JSON file instead of fetching it
+    ///
+    /// Neither the network nor the cache is used for the manifest, so versions can be listed and
+    /// validated with no internet access at all.
+    /// Overrides the `manifest_file` and `manifest_url` keys in the global config
+    manifest_file: Option<PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "URL",
+        conflicts_with = "manifest_file"
+    )]
+    #[arg(value_parser = |s: &str| validate_manifest_url(s).map(|()| s.to_string()))]
+    /// Fetch the version manifest from this URL instead of from Mojang, e.g. a private mirror
+    ///
+    /// The manifest must have the same format as Mojang's `version_manifest_v2.json`, and is
+    /// cached separately for each URL.
+    /// Overrides the `manifest_url` and `manifest_file` keys in the global config
+    manifest_url: Option<String>,
+    #[arg(long, global = true)]
+    /// Neither read nor write cached API responses for this run
+    ///
+    /// Everything is fetched fresh and nothing is saved, e.g. to rule out a corrupted cache.
+    /// To fetch fresh data but still cache it, set `cache_ttl` to 0 instead. The manifest from
+    /// `--manifest-file` is never cached either way
+    no_cache: bool,
+    #[arg(long, global = true, value_name = "PATH")]
+    /// Also write logs to this file, e.g. for unattended runs
+    ///
+    /// Logs are always written to a new file in the log directory; this adds a second one at a
+    /// path of your choosing, with the same filter. It is never colored
+    log_file: Option<PathBuf>,
+    #[arg(long, global = true, value_enum, default_value_t = LogRotation::Never, requires = "log_file")]
+    /// When to start a new log file. Daily files get the date appended to their name
+    log_file_rotation: LogRotation,
+    #[arg(long, global = true, value_name = "COLUMNS", value_parser = clap::value_parser!(u16).range(1..))]
+    /// The width to fit help and tables to, instead of the terminal's width
+    ///
+    /// Also applies when output isn't a terminal. The `COLUMNS` environment variable works too
+    max_term_width: Option<u16>,
 }
 
 #[doc(hidden)]
@@ -57,41 +139,194 @@ enum Action {
     List {
         #[command(flatten)]
         filter: Option<ListFilter>,
+        #[arg(long, value_enum)]
+        /// A shorthand for the version type flags. `snapshot` lists snapshots and pre-releases
+        ///
+        /// `-r`, `-p`, `-s`, `-o` and `-a` override the channel when given.
+        channel: Option<Channel>,
         #[arg(short, long)]
         /// List installed instances and their versions
         installed: bool,
+        #[arg(long, value_enum, default_value_t = ListFormat::Table)]
+        /// The output format. `tsv` and `csv` print a header line followed by one row per version,
+        /// `json` prints an array of objects, and `plain` prints only IDs, one per line
+        format: ListFormat,
+        #[arg(long, conflicts_with = "format")]
+        /// Print only IDs, one per line, without color or decoration. Same as `--format plain`
+        ///
+        /// With `--installed`, instance names are printed instead
+        plain_ids: bool,
+        #[arg(long, conflicts_with_all = ["installed", "format", "plain_ids"])]
+        /// Group versions by `major.minor`, with pre-releases nested under their release.
+        /// Snapshots are grouped by year
+        tree: bool,
+        #[arg(long, requires = "tree")]
+        /// Show one line per group instead of every version
+        collapse: bool,
+        #[arg(short, long, conflicts_with_all = ["tree", "format", "plain_ids"])]
+        /// Show a table with the type and release date of each version, instead of only their IDs
+        ///
+        /// Installed instances are always shown in detail
+        detailed: bool,
+        #[arg(long)]
+        /// Also list experimental snapshots, e.g. `1.18_experimental-snapshot-1`
+        ///
+        /// These are otherwise only listed with `--all`
+        include_experimental: bool,
+        #[arg(long, value_name = "VERSION", value_parser = |s: &str| parse_version_arg(s))]
+        #[arg(conflicts_with_all = ["tree", "format", "plain_ids", "detailed"])]
+        /// Explain why a version is or isn't listed with the given options, instead of listing
+        explain: Option<VersionNumber>,
+        #[arg(long, value_delimiter = ',', requires = "installed")]
+        #[arg(value_parser = |s: &str| app::validate_tag(s))]
+        /// Only list installed instances with any of these tags
+        tag: Vec<String>,
     },
     /// Get information about a Minecraft version
     Info {
-        #[arg(required = true, value_parser = |s: &str| validate_version_number(s))]
+        #[arg(required_unless_present = "installed", value_parser = |s: &str| validate_version_number(s))]
         #[arg(short, long)]
         /// The Minecraft version to get information about
-        version: VersionNumber,
-    },
-    /// Install a server instance
-    Install {
-        #[arg(value_delimiter = ',', num_args = 0.., value_parser = |s: &str| validate_version_number(s))]
-        #[arg(short, long)]
-        /// The version(s) to install
         ///
-        /// Defaults to latest release version if none is provided.
-        /// Can be specified multiple times, or as a comma or space-separated list.
-        version: Option<Vec<VersionNumber>>,
-        // #[arg(short, long)]
-        // name: Option<String>,
+        /// `latest`, `latest-snapshot`, `latest-prerelease` and `latest-any` pick the newest
+        /// version of that kind.
+        version: Option<VersionNumber>,
+        #[arg(long, value_name = "INSTANCE", conflicts_with_all = ["version", "compare", "sha"])]
+        /// Show where an installed instance's jar came from instead, as recorded at install
+        ///
+        /// Includes the download URL, size and SHA-1, so the jar can be audited or downloaded
+        /// again without the manifest. `mcdl doctor` verifies jars against the recorded SHA-1.
+        installed: Option<String>,
+        #[arg(long, value_name = "VERSION", value_parser = |s: &str| validate_version_number(s))]
+        /// Another version to compare against side by side, e.g. before updating
+        ///
+        /// A change in the required Java version is highlighted, since updating across it
+        /// needs a new JRE.
+        compare: Option<VersionNumber>,
+        #[arg(long, conflicts_with = "compare")]
+        /// Also show the SHA-1 of each download, e.g. to verify a server jar downloaded elsewhere
+        sha: bool,
     },
+    /// Install a server instance
+    Install(InstallArgs),
     /// Uninstall a server instance
     Uninstall {
         #[arg(required = true, value_parser = NonEmptyStringValueParser::new())]
         #[arg(short, long)]
         version: String, // in the future, `name` will be used instead
+        #[arg(long)]
+        /// Show what would be removed and how much space it would free, without removing anything
+        dry_run: bool,
+    },
+    /// Rename a server instance
+    ///
+    /// The instance directory and settings file are renamed to match. Once renamed,
+    /// use the new name wherever a version is expected, e.g. `mcdl run --version <NEW_NAME>`.
+    Rename {
+        #[arg(value_parser = NonEmptyStringValueParser::new())]
+        /// The instance to rename
+        instance: String,
+        #[arg(value_parser = NonEmptyStringValueParser::new())]
+        /// The new name of the instance
+        new_name: String,
+        #[arg(long)]
+        /// Also rename the world and update `level-name` in `server.properties`
+        level_name: bool,
+    },
+    /// Copy a server instance to a new instance, e.g. to try changes on a test copy
+    ///
+    /// The instance directory and settings are copied, and the copy uses the same JRE. It's
+    /// created in the instance base directory, even if the original was installed elsewhere.
+    Clone {
+        #[arg(value_parser = NonEmptyStringValueParser::new())]
+        /// The instance to copy
+        instance: String,
+        #[arg(value_parser = NonEmptyStringValueParser::new())]
+        /// The name of the new instance
+        new_name: String,
+        #[arg(long)]
+        /// Leave out the world, so the copy generates a new one when it's first run
+        no_world: bool,
     },
     /// Run a server instance
     Run {
-        #[arg(required = true, value_parser = NonEmptyStringValueParser::new())]
-        #[arg(short, long)]
-        /// The version to run
-        version: String, // in the future, `name` will be used instead
+        #[arg(value_parser = NonEmptyStringValueParser::new())]
+        #[arg(short, long, value_delimiter = ',', num_args = 1..)]
+        /// The instance(s) to run. Defaults to the current instance (see `mcdl use`)
+        ///
+        /// Several instances are run at the same time, with their output combined and each line
+        /// prefixed with the instance name. Their consoles can't be used, and Ctrl-C stops them all.
+        version: Vec<String>,
+        #[arg(long, value_delimiter = ',', conflicts_with = "version")]
+        #[arg(value_parser = |s: &str| app::validate_tag(s))]
+        /// Run every instance with any of these tags, like giving them all to `--version`
+        tag: Vec<String>,
+        #[arg(long)]
+        /// Print the command line used to start the server instead of running it
+        print_command: bool,
+        #[arg(long)]
+        /// When running several instances, keep the others running if one exits with an error
+        ///
+        /// Otherwise, the others are stopped too.
+        keep_going: bool,
+        #[arg(short, long, value_name = "KEY=VALUE", value_parser = parse_env_var)]
+        /// Set an environment variable for the server. Can be given multiple times
+        ///
+        /// Takes precedence over `--env-file` and the `[env]` table in the instance settings,
+        /// which take precedence over the environment mcdl was started with.
+        env: Vec<(String, String)>,
+        #[arg(long, value_name = "PATH")]
+        /// Set environment variables for the server from a dotenv-style file of KEY=VALUE lines
+        ///
+        /// Useful for keeping secrets like RCON passwords out of shell history. Takes
+        /// precedence over the `[env]` table in the instance settings.
+        env_file: Option<PathBuf>,
+    },
+    /// Show or change the tags of an instance
+    ///
+    /// Tags are freeform labels for organizing instances, e.g. `prod` or `creative`. Use them to
+    /// pick instances with `mcdl list --installed --tag` and `mcdl run --tag`.
+    Tag {
+        #[arg(value_parser = NonEmptyStringValueParser::new())]
+        /// The instance to show or change the tags of
+        instance: String,
+        #[command(subcommand)]
+        action: Option<TagAction>,
+    },
+    /// Print the `java` executable an instance runs with, and its version
+    ///
+    /// Doesn't start the server or install anything.
+    WhichJava {
+        #[arg(value_parser = NonEmptyStringValueParser::new())]
+        /// The instance to check. Defaults to the current instance (see `mcdl use`)
+        instance: Option<String>,
+    },
+    /// Manage the shared Java Runtime Environments
+    Jre {
+        #[command(subcommand)]
+        action: JreAction,
+    },
+    /// Open an instance directory in the file manager
+    ///
+    /// Prints the path instead if there is no file manager to open.
+    Open {
+        #[arg(value_parser = NonEmptyStringValueParser::new())]
+        /// The instance to open. Defaults to the current instance (see `mcdl use`)
+        instance: Option<String>,
+        #[arg(long)]
+        /// Start a shell in the instance directory instead
+        shell: bool,
+    },
+    /// Set the current instance, which commands use when no instance is given
+    ///
+    /// Prints the current instance if none is given.
+    Use {
+        #[arg(value_parser = NonEmptyStringValueParser::new(), conflicts_with = "clear")]
+        /// The instance to use
+        instance: Option<String>,
+        #[arg(long)]
+        /// Unset the current instance
+        clear: bool,
     },
     /// Print the path to a config file or instance directory
     Locate {
@@ -100,6 +335,306 @@ enum Action {
         /// The file or directory to locate
         what: WhatEnum,
     },
+    /// Generate a service definition that runs a server instance on startup
+    Service {
+        #[arg(required = true, value_parser = NonEmptyStringValueParser::new())]
+        #[arg(short, long)]
+        /// The version to run
+        version: String, // in the future, `name` will be used instead
+        #[arg(short = 't', long = "type", value_enum)]
+        /// The service manager to generate a definition for [default: native to this OS]
+        kind: Option<ServiceKind>,
+        #[arg(short, long)]
+        /// Run the service as this user (installs a system-wide service)
+        user: Option<String>,
+        #[arg(long)]
+        /// Write the definition to the service manager's directory instead of printing it
+        install: bool,
+    },
+    /// Show or edit configuration
+    Config {
+        #[arg(short, long, required = true)]
+        /// Operate on the global config file
+        global: bool,
+        #[arg(short, long)]
+        /// Open the config file in your editor ($VISUAL or $EDITOR)
+        edit: bool,
+    },
+    /// Check installed instances for newer releases
+    ///
+    /// Only patch releases of the same `major.minor` line count by default, e.g.
+    /// `1.20.1 -> 1.20.4`. Instances of snapshots, pre-releases and other versions aren't checked.
+    Outdated {
+        #[arg(long)]
+        /// Also count releases of newer `major.minor` lines, e.g. `1.20.1 -> 1.21`
+        major: bool,
+    },
+    /// Summarize installed instances, including whether their servers are running
+    ///
+    /// Shows each instance's version, port, JRE, size on disk and when it was last started.
+    /// Only servers started with `mcdl run` are known to be running.
+    Status {
+        #[arg(value_parser = NonEmptyStringValueParser::new())]
+        /// The instance to summarize. Defaults to all instances
+        instance: Option<String>,
+        #[arg(long)]
+        /// Print the summaries as a JSON array, e.g. for dashboards
+        json: bool,
+    },
+    /// Check that the services mcdl downloads from can be reached, and that instance jars are intact
+    ///
+    /// Reports each service's latency, or why it couldn't be reached. Instance jars are checked
+    /// against the SHA-1 recorded when they were installed, for instances that have one.
+    Doctor {
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+        /// How long to wait for each service, in seconds
+        timeout: u64,
+    },
+    /// Print a JSON Schema for the global config or the instance settings
+    ///
+    /// Point your editor at it to get validation and completion while editing them.
+    #[cfg(feature = "schema")]
+    #[command(hide = true)]
+    Schema {
+        #[arg(value_enum)]
+        /// The file to print the schema for
+        what: SchemaTarget,
+    },
+}
+
+#[doc(hidden)]
+#[derive(Subcommand, Debug)]
+enum TagAction {
+    /// Add tags to the instance
+    Add {
+        #[arg(required = true, value_delimiter = ',')]
+        #[arg(value_parser = |s: &str| app::validate_tag(s))]
+        /// The tags to add
+        tags: Vec<String>,
+    },
+    /// Remove tags from the instance
+    Remove {
+        #[arg(required = true, value_delimiter = ',')]
+        /// The tags to remove
+        tags: Vec<String>,
+    },
+}
+
+#[doc(hidden)]
+#[derive(Subcommand, Debug)]
+enum JreAction {
+    /// Install every JRE required by installed instances that isn't already present
+    ///
+    /// Instances with a bundled JRE are skipped.
+    Sync,
+    /// List the JRE each installed instance needs, and which of those are installed
+    List,
+    /// Download JREs for other platforms, e.g. to build a bundle that runs anywhere
+    ///
+    /// Archives are stored unextracted in `jre/<os>-<arch>/<major>` under the data directory.
+    /// A JRE is around 40-50 MB per platform, so this can take up a lot of space.
+    Prefetch {
+        #[arg(short, long, value_delimiter = ',')]
+        /// The JRE major versions to download. Defaults to those used by installed instances
+        jre: Vec<u8>,
+        #[arg(long, value_delimiter = ',', conflicts_with = "all_platforms")]
+        /// The operating systems to download for, using Adoptium's names (linux, mac, windows, ...).
+        /// Defaults to the current OS
+        os: Vec<String>,
+        #[arg(long, value_delimiter = ',', conflicts_with = "all_platforms")]
+        /// The architectures to download for, using Adoptium's names (x64, aarch64, ...).
+        /// Defaults to the current architecture
+        arch: Vec<String>,
+        #[arg(long)]
+        /// Download for Linux and macOS on x64 and aarch64, and Windows on x64
+        all_platforms: bool,
+    },
+    /// List the platforms and image types Adoptium serves a JRE for
+    ///
+    /// Use this to find valid `--os` and `--arch` values for `jre prefetch`.
+    Platforms {
+        /// The JRE major version, e.g. 21
+        jre: u8,
+    },
+}
+
+#[doc(hidden)]
+#[derive(Args, Debug)]
+struct InstallArgs {
+    #[arg(value_delimiter = ',', num_args = 0.., value_parser = |s: &str| validate_version_number(s))]
+    #[arg(short, long)]
+    /// The version(s) to install
+    ///
+    /// Defaults to latest release version if none is provided.
+    /// Can be specified multiple times, or as a comma or space-separated list.
+    /// `latest`, `latest-snapshot`, `latest-prerelease` and `latest-any` pick the newest
+    /// version of that kind.
+    version: Option<Vec<VersionNumber>>,
+    #[arg(value_name = "-", value_parser = ["-"], conflicts_with = "version")]
+    /// Read the versions to install from stdin instead, one per line
+    ///
+    /// Empty lines and `#` comments are ignored, so the output of `mcdl list --plain-ids` or a
+    /// file of versions can be piped in. Invalid lines are reported with their line numbers, and
+    /// nothing is installed unless `--keep-going` is given.
+    stdin: Option<String>,
+    #[arg(long, value_enum, conflicts_with_all = ["version", "stdin"])]
+    /// Which versions to pick from when no version is given. Defaults to `release`
+    ///
+    /// `snapshot` installs the manifest's latest snapshot, which can be a pre-release or, right
+    /// after one comes out, a release. `all` installs the newest version of any kind.
+    channel: Option<Channel>,
+    #[arg(short, long, value_parser = clap::value_parser!(u16).range(1..))]
+    /// The maximum number of versions to install at the same time
+    ///
+    /// Overrides the `concurrency` key in the global config
+    jobs: Option<u16>,
+    #[arg(long)]
+    /// Accept the Minecraft EULA (https://aka.ms/MinecraftEULA) for the installed versions
+    ///
+    /// To accept it for all future installs, set `accept_eula = true` in the global config.
+    /// If neither is set, you will be asked when running interactively.
+    accept_eula: bool,
+    #[arg(long, conflicts_with = "accept_eula")]
+    /// Don't write `eula.txt`, e.g. for a proxy server jar given with `--server-jar`
+    ///
+    /// Servers that need it won't start until it is created.
+    no_eula_file: bool,
+    #[arg(long, value_name = "PATH")]
+    /// An existing world directory to use as the instance's world
+    ///
+    /// The world is copied into the instance directory as `world`.
+    world: Option<PathBuf>,
+    #[arg(long, requires = "world")]
+    /// Symlink the world given by `--world` instead of copying it
+    link_world: bool,
+    #[arg(long)]
+    /// Install the JRE inside the instance directory instead of sharing it
+    ///
+    /// Makes the instance self-contained (e.g. for copying to another machine),
+    /// at the cost of extra disk space.
+    bundled_jre: bool,
+    #[arg(long, conflicts_with = "bundled_jre")]
+    /// Don't install a JRE, and run with the system Java instead
+    ///
+    /// `run` uses `JAVA_HOME`, or `java` from `PATH` if it's not set, and fails if that Java is
+    /// missing or older than the version needs.
+    skip_jre: bool,
+    #[arg(long)]
+    /// Keep installing the remaining versions if one fails
+    ///
+    /// Failures are reported at the end, and the exit code is non-zero if any occurred.
+    keep_going: bool,
+    #[arg(long)]
+    /// Install even if there doesn't seem to be enough free disk space
+    ///
+    /// The space needed is estimated from the download sizes in the version metadata, plus around
+    /// 200 MiB for each JRE and the size of a copied world.
+    no_space_check: bool,
+    #[arg(long, value_name = "PATH|URL")]
+    /// Use this server jar instead of downloading the official one
+    ///
+    /// Useful for testing or for patched server builds. Only one version can be installed
+    /// with a custom jar, and it is recorded so the jar is never replaced by the official one.
+    server_jar: Option<app::ServerJar>,
+    #[arg(long, value_name = "ALGO:HEX", requires = "server_jar")]
+    /// The expected checksum of the jar given by `--server-jar`, e.g. `sha256:<hex>`
+    ///
+    /// Supported algorithms are sha1 and sha256. Official jars are always verified
+    /// against the checksum in the version metadata.
+    server_jar_checksum: Option<Checksum>,
+    #[arg(long, value_name = "COMMAND")]
+    /// A shell command to run in each instance directory after it is installed
+    ///
+    /// The instance is described by the `MCDL_INSTANCE_DIR`, `MCDL_INSTANCE_ID` (its name),
+    /// `MCDL_INSTANCE_VERSION` and `MCDL_JRE_VERSION` environment variables.
+    /// The install fails, and the instance is removed again, if the command does.
+    /// Overrides the `after_install` key in the global config.
+    after_install: Option<String>,
+    #[arg(long)]
+    /// Don't fail the install if the `--after-install` command fails
+    ignore_hook_errors: bool,
+    #[arg(long, value_name = "PATH")]
+    /// Create the instance in this directory instead of the instance base directory
+    ///
+    /// The directory is created if it doesn't exist, and must be empty if it does, since
+    /// uninstalling removes it. Only one version can be installed to a given directory.
+    output_dir: Option<PathBuf>,
+    #[arg(short, long, value_name = "TEMPLATE")]
+    /// Name the instance instead of using the version ID
+    ///
+    /// Can contain `{version}`, `{kind}` (the release type, e.g. `snapshot`) and `{n}` (counting
+    /// from 1) placeholders, e.g. `test-{version}`, so that each installed version gets a
+    /// different name.
+    name: Option<String>,
+    #[arg(long, value_delimiter = ',', value_parser = |s: &str| app::validate_tag(s))]
+    /// Tag the installed instances, e.g. `prod`. See `mcdl tag`
+    tag: Vec<String>,
+    #[arg(long)]
+    /// Download the server mappings into the instance directory as `server_mappings.txt`
+    ///
+    /// Mojang publishes mappings for 1.14.4 (snapshot 19w36a) and later. For older versions,
+    /// a warning is logged and the install continues without them.
+    mappings: bool,
+    #[arg(long, value_enum, requires = "mappings", default_value_t = MappingsFormat::Proguard)]
+    /// The format to store the mappings given by `--mappings` in
+    ///
+    /// The ProGuard mappings are always kept. `tiny` also writes a Tiny v2 conversion
+    /// as `server_mappings.tiny`.
+    mappings_format: MappingsFormat,
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "server_jar", "world", "mappings", "accept_eula", "no_eula_file", "bind", "ipv6"
+        ]
+    )]
+    /// Install the client jar instead of the server, e.g. for use with a custom launcher
+    ///
+    /// The jar is saved as `client.jar`, and the JRE is installed as usual. Instances are named
+    /// `<version>-client` unless `--name` is given. mcdl can't start a client, so `run` and
+    /// `service` refuse client instances.
+    client: bool,
+    #[arg(long, requires = "client")]
+    /// Also download the libraries and asset index of the client given by `--client`
+    ///
+    /// The libraries for the current OS are saved under `libraries`, and the asset index under
+    /// `assets/indexes`, in the same layout as the official launcher. The assets themselves
+    /// aren't downloaded.
+    client_libraries: bool,
+    #[arg(long, requires = "client_libraries")]
+    /// Also download the game assets listed in the asset index given by `--client-libraries`
+    ///
+    /// Assets are stored once by hash in `assets/objects` under the data directory, which is
+    /// shared by all client instances and linked into each one, so the instance's `assets`
+    /// directory can be passed to the client as `--assetsDir`. The first download is several
+    /// hundred MB.
+    assets: bool,
+    #[arg(long, value_name = "ADDRESS")]
+    /// The IP address the server listens on, e.g. to run several instances on one host
+    ///
+    /// Written to `server-ip` in `server.properties`. It's stored in the instance settings, and
+    /// `run` writes it again if the properties are changed.
+    bind: Option<IpAddr>,
+    #[arg(long)]
+    /// Let the server accept IPv6 connections as well as IPv4
+    ///
+    /// Starts Java with `-Djava.net.preferIPv4Stack=false`, and leaves `server-ip` empty so the
+    /// server listens on every address, unless `--bind` is also given.
+    ipv6: bool,
+    #[arg(long)]
+    /// Print what would be installed, then exit without installing anything
+    ///
+    /// Versions like `latest` are resolved to their IDs, and the instance names, locations, jars
+    /// and JREs are the ones an install with the same arguments would use. Only version metadata
+    /// is downloaded.
+    resolve_only: bool,
+    #[arg(long, value_enum, requires = "resolve_only", default_value_t = ListFormat::Table)]
+    /// The output format of `--resolve-only`
+    format: ListFormat,
+    #[arg(long)]
+    /// Don't start the interactive install wizard when no version is given
+    ///
+    /// The wizard only runs when stdin is a terminal. Without it, the latest release is installed.
+    no_interactive: bool,
 }
 
 #[doc(hidden)]
@@ -116,13 +651,86 @@ struct ListFilter {
     /// Only list snapshot versions
     snapshot: bool,
     #[arg(short, long)]
-    /// Only list other versions
+    /// Only list other versions, e.g. April Fools versions
+    ///
+    /// Experimental snapshots are listed with `--include-experimental` instead
     other: bool,
     #[arg(short, long)]
     /// List all versions
     all: bool,
 }
 
+impl ListFilter {
+    /// The flag that selects this filter
+    fn flag(&self) -> &'static str {
+        match (
+            self.release,
+            self.pre_release,
+            self.snapshot,
+            self.other,
+            self.all,
+        ) {
+            (true, _, _, _, _) => "--release",
+            // only possible through the channel, since the flags are exclusive
+            (_, true, true, _, _) => "--channel snapshot",
+            (_, true, _, _, _) => "--pre-release",
+            (_, _, true, _, _) => "--snapshot",
+            (_, _, _, true, _) => "--other",
+            (_, _, _, _, true) => "--all",
+            _ => unreachable!(),
+        }
+    }
+
+    /// Whether `version` is listed
+    fn includes(&self, version: &GameVersion, include_experimental: bool, strict: bool) -> bool {
+        self.explain(version, include_experimental, strict).0
+    }
+
+    /// Whether `version` is listed, along with each check that decided it
+    fn explain(
+        &self,
+        version: &GameVersion,
+        include_experimental: bool,
+        strict: bool,
+    ) -> (bool, Vec<String>) {
+        let id = &version.id;
+        let flag = self.flag();
+        let mut trace = vec![];
+
+        // kept apart from the other non-standard versions, so they're only listed on request
+        let mut included = if id.is_experimental() {
+            let reason = match (include_experimental, self.all) {
+                (true, _) => "which --include-experimental includes",
+                (_, true) => "which --all includes",
+                _ => "which are only listed with --include-experimental or --all",
+            };
+            trace.push(format!("`{id}` is an experimental snapshot, {reason}"));
+            include_experimental || self.all
+        } else {
+            let (kind, matches) = match id {
+                VersionNumber::Release(_) => ("a release", self.release),
+                VersionNumber::PreRelease(_) => ("a pre-release", self.pre_release),
+                VersionNumber::Snapshot(_) => ("a snapshot", self.snapshot),
+                VersionNumber::Other(_) => ("an other version", self.other),
+            };
+            let included = matches || self.all;
+            let verb = if included { "includes" } else { "excludes" };
+            trace.push(format!("`{id}` is {kind} by its ID, which {flag} {verb}"));
+            included
+        };
+
+        if strict && id.is_other() {
+            trace.push(
+                "--strict hides versions that aren't a release, pre-release or snapshot"
+                    .to_string(),
+            );
+            included = false;
+        }
+
+        (included, trace)
+    }
+}
+
 impl Default for ListFilter {
     fn default() -> Self {
         Self {
@@ -135,6 +743,116 @@ impl Default for ListFilter {
     }
 }
 
+#[doc(hidden)]
+#[derive(Clone, Copy, ValueEnum, Debug, Display, PartialEq, Eq)]
+enum Channel {
+    /// Releases only
+    #[display("release")]
+    Release,
+    /// Snapshots and pre-releases
+    #[display("snapshot")]
+    Snapshot,
+    /// Every version
+    #[display("all")]
+    All,
+}
+
+impl Channel {
+    /// The list filter the channel stands for
+    fn filter(self) -> ListFilter {
+        match self {
+            Channel::Release => ListFilter::default(),
+            Channel::Snapshot => ListFilter {
+                release: false,
+                pre_release: true,
+                snapshot: true,
+                ..ListFilter::default()
+            },
+            Channel::All => ListFilter {
+                release: false,
+                all: true,
+                ..ListFilter::default()
+            },
+        }
+    }
+
+    /// The alias of the version installed from the channel when none is given
+    fn latest_alias(self) -> &'static str {
+        match self {
+            Channel::Release => "latest",
+            Channel::Snapshot => "latest-snapshot",
+            Channel::All => "latest-any",
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Clone, Copy, ValueEnum, Debug, Display, PartialEq, Eq)]
+enum ListFormat {
+    /// A human-readable table, or one version per line unless `--detailed` is given
+    #[display("table")]
+    Table,
+    /// Tab-separated values
+    #[display("tsv")]
+    Tsv,
+    /// Comma-separated values
+    #[display("csv")]
+    Csv,
+    /// A JSON array, with more detail for installed instances
+    #[display("json")]
+    Json,
+    /// Bare IDs, one per line, for scripts. Never colored or decorated
+    #[display("plain")]
+    Plain,
+}
+
+#[doc(hidden)]
+#[cfg(feature = "schema")]
+#[derive(Clone, Copy, ValueEnum, Debug, Display, PartialEq, Eq)]
+enum SchemaTarget {
+    /// The global config, `config.toml`
+    #[display("config")]
+    Config,
+    /// The settings of an instance, in the instance settings directory
+    #[display("settings")]
+    Settings,
+}
+
+#[doc(hidden)]
+#[derive(Clone, Copy, ValueEnum, Debug, Display, PartialEq, Eq)]
+enum LogRotation {
+    /// Always append to the same file
+    #[display("never")]
+    Never,
+    /// Start a new file every day
+    #[display("daily")]
+    Daily,
+}
+
+impl ListFormat {
+    /// Joins `fields` into a single row, escaping them as needed
+    ///
+    /// Only meaningful for delimited formats
+    fn row<S: AsRef<str>>(&self, fields: &[S]) -> String {
+        let fields = fields.iter().map(AsRef::as_ref);
+        match self {
+            ListFormat::Csv => fields
+                .map(|f| {
+                    if f.contains([',', '"', '\n', '\r']) {
+                        format!("\"{}\"", f.replace('"', "\"\""))
+                    } else {
+                        f.to_string()
+                    }
+                })
+                .join(","),
+            // tsv has no escaping, so replace anything that would break a row
+            _ => fields
+                .map(|f| f.replace(['\t', '\n', '\r'], " "))
+                .join("\t"),
+        }
+    }
+}
+
 #[doc(hidden)]
 #[derive(Clone, Copy, ValueEnum, Debug, Display)]
 enum WhatEnum {
@@ -150,24 +868,86 @@ enum WhatEnum {
 
 #[instrument(level = "debug", err, ret)]
 fn validate_version_number(v: &str) -> Result<VersionNumber> {
+    let version = parse_version_arg(v)?;
+
     // lol
-    let valid_versions: Vec<VersionNumber> =
-        tokio::runtime::Runtime::new().unwrap().block_on(async {
-            MANIFEST
-                .get()
-                .await
-                .versions
-                .iter()
-                .map(|v| v.id.clone())
-                .collect()
-        });
+    tokio::runtime::Runtime::new().unwrap().block_on(async {
+        version.resolve(MANIFEST.get().await)?;
+        Ok(version)
+    })
+}
+
+/// Parses a version or instance name from the command line
+///
+/// Surrounding whitespace, e.g. from copy-pasting or a trailing newline in a pipeline, is ignored
+fn parse_version_arg(v: &str) -> Result<VersionNumber> {
+    v.trim().parse()
+}
 
-    let version = v.parse()?;
+/// Finds the value of the global option `name`, e.g. `--manifest-file`, in `args`
+///
+/// For options that are needed before `args` are parsed
+fn global_arg<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .enumerate()
+        // like clap, stop at `--` and take the last occurrence
+        .take_while(|(_, arg)| *arg != "--")
+        .filter_map(|(i, arg)| match arg.strip_prefix(name) {
+            Some("") => args.get(i + 1).map(String::as_str),
+            Some(value) => value.strip_prefix('='),
+            None => None,
+        })
+        .last()
+}
 
-    if valid_versions.contains(&version) {
-        Ok(version)
-    } else {
-        Err(eyre!("Version does not exist"))
+/// Whether the global flag `name`, e.g. `--no-cache`, is in `args`
+///
+/// For flags that are needed before `args` are parsed
+fn global_flag(args: &[String], name: &str) -> bool {
+    args.iter()
+        .take_while(|arg| *arg != "--")
+        .any(|arg| arg == name)
+}
+
+/// Normalizes an instance name given on the command line, or falls back to the current instance
+fn instance_or_current(instance: Option<String>) -> Result<String> {
+    match instance {
+        // normalize so that e.g. `1.0.0` finds the `1.0` instance
+        Some(instance) => Ok(parse_version_arg(&instance)?.to_string()),
+        None => META.lock().current_instance().cloned().ok_or_else(|| {
+            eyre!("No instance given, and there is no current instance. Give one, or set one with `mcdl use <instance>`")
+        }),
+    }
+}
+
+/// Errors if strict mode is on and `version` isn't a release, pre-release or snapshot
+fn check_strict(version: &VersionNumber) -> Result<()> {
+    if CONFIG.read().strict && version.is_other() {
+        return Err(eyre!(
+            "`{version}` is not a release, pre-release or snapshot, which strict mode doesn't allow"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Like [`check_strict`], for the version of instance `id`, if it exists
+fn check_strict_instance(id: &str) -> Result<()> {
+    let version = META
+        .lock()
+        .instances
+        .get(id)
+        .map(|instance| instance.id.clone());
+    match version {
+        Some(version) => check_strict(&version),
+        None => Ok(()),
+    }
+}
+
+fn parse_env_var(s: &str) -> Result<(String, String)> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(eyre!("Expected KEY=VALUE, got `{s}`")),
     }
 }
 
@@ -184,15 +964,25 @@ async fn main() -> Result<()> {
         "mcdl-{}{}.log",
         Utc::now().format("%Y%m%d-%H%M%S"),
         if args.len() > 1 {
-            format!("-{}", args[1])
+            // global options can come first, and their values can be paths
+            format!(
+                "-{}",
+                args[1].replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', "_")
+            )
         } else {
             String::new()
         }
     );
     let log_path = LOG_BASE_DIR.join(log_name);
 
-    // set up tracing
-    install_tracing(&log_path)?;
+    // set up tracing, before parsing so that parsing can be traced too
+    let log_file = global_arg(&args, "--log-file").map(|path| {
+        let rotation = global_arg(&args, "--log-file-rotation")
+            .and_then(|rotation| LogRotation::from_str(rotation, true).ok())
+            .unwrap_or(LogRotation::Never);
+        (PathBuf::from(path), rotation)
+    });
+    let _log_guard = install_tracing(&log_path, log_file)?;
     info!("Logging to {}", log_path.display());
 
     // install color_eyre
@@ -203,23 +993,163 @@ async fn main() -> Result<()> {
 
     info!("Args: {}", args.as_args_string());
 
+    // load config before parsing, since versions are validated against the (cached) manifest
+    let config = Config::load(CONFIG_PATH.as_path());
+    if let Ok(config) = &config {
+        *CONFIG.write() = config.clone();
+    }
+    // needed by validation too, so it can't wait for parsing
+    if let Some(path) = global_arg(&args, "--manifest-file") {
+        let mut config = CONFIG.write();
+        config.manifest_file = Some(PathBuf::from(path));
+        // the flags override both config keys, so a `manifest_url` from the config doesn't apply
+        config.manifest_url = None;
+    }
+    if let Some(url) = global_arg(&args, "--manifest-url") {
+        validate_manifest_url(url)?;
+        let mut config = CONFIG.write();
+        config.manifest_url = Some(url.to_string());
+        config.manifest_file = None;
+    }
+    if global_flag(&args, "--no-cache") {
+        CONFIG.write().no_cache = true;
+    }
+
     // lol again
-    let cli = tokio::task::spawn_blocking(Cli::parse).await?;
+    let cli = tokio::task::spawn_blocking(|| {
+        let mut command = Cli::command();
+        if let Some(width) = *TERM_WIDTH_OVERRIDE {
+            command = command.term_width(width);
+        }
+        Cli::from_arg_matches(&command.get_matches()).unwrap_or_else(|e| e.exit())
+    })
+    .await?;
     debug!(?cli);
 
+    match config {
+        // still allow fixing a broken config
+        Err(e) if matches!(cli.action, Action::Config { .. }) => {
+            eprintln!("Invalid config, using defaults: {e:#}\n");
+        }
+        Err(e) => return Err(e),
+        Ok(_) => {}
+    }
+
+    if let Some(color) = cli.color {
+        CONFIG.write().color = color;
+    }
+    if let Some(progress) = cli.progress {
+        CONFIG.write().progress = progress;
+    }
+    if cli.strict {
+        CONFIG.write().strict = true;
+    }
+
     match cli.action {
-        Action::List { filter, installed } => list_impl(filter, installed).await?,
-        Action::Info { version } => info_impl(version).await?,
-        Action::Install { version } => install_impl(version).await?,
-        Action::Uninstall { version } => uninstall_impl(version)?,
-        Action::Run { version } => run_impl(version).await?,
+        Action::List {
+            filter,
+            channel,
+            installed,
+            format,
+            plain_ids,
+            tree,
+            collapse,
+            detailed,
+            include_experimental,
+            explain,
+            tag,
+        } => {
+            let filter = filter.or_else(|| channel.map(Channel::filter));
+            if let Some(version) = explain {
+                explain_list_filter(
+                    filter,
+                    installed.then_some(tag),
+                    include_experimental,
+                    &version,
+                )
+                .await?;
+                return Ok(());
+            }
+
+            let format = if plain_ids { ListFormat::Plain } else { format };
+            if format == ListFormat::Plain {
+                let mut config = CONFIG.write();
+                config.color = ColorChoice::Never;
+                config.progress = ProgressMode::Hidden;
+            }
+            list_impl(
+                filter,
+                installed.then_some(tag),
+                format,
+                tree,
+                collapse,
+                detailed,
+                include_experimental,
+            )
+            .await?
+        }
+        Action::Info {
+            version,
+            installed,
+            compare,
+            sha,
+        } => match (version, installed, compare) {
+            (_, Some(instance), _) => info_installed_impl(&instance)?,
+            (Some(version), None, Some(other)) => compare_impl(version, other).await?,
+            (Some(version), None, None) => info_impl(version, sha).await?,
+            (None, None, _) => unreachable!("clap requires a version or an instance"),
+        },
+        Action::Install(args) => install_impl(args).await?,
+        Action::Uninstall { version, dry_run } => uninstall_impl(version, dry_run)?,
+        Action::Rename {
+            instance,
+            new_name,
+            level_name,
+        } => rename_impl(instance, new_name, level_name)?,
+        Action::Clone {
+            instance,
+            new_name,
+            no_world,
+        } => clone_impl(instance, new_name, no_world).await?,
+        Action::Run {
+            version,
+            tag,
+            print_command,
+            keep_going,
+            env,
+            env_file,
+        } => run_impl(version, tag, print_command, keep_going, env, env_file).await?,
+        Action::Tag { instance, action } => tag_impl(instance, action)?,
+        Action::WhichJava { instance } => which_java_impl(instance).await?,
+        Action::Jre { action } => jre_impl(action).await?,
+        Action::Open { instance, shell } => open_impl(instance, shell)?,
+        Action::Use { instance, clear } => use_impl(instance, clear)?,
         Action::Locate { what } => locate_impl(what)?,
+        Action::Service {
+            version,
+            kind,
+            user,
+            install,
+        } => service_impl(version, kind, user, install).await?,
+        Action::Config { global: _, edit } => config_impl(edit)?,
+        Action::Outdated { major } => outdated_impl(major).await?,
+        Action::Status { instance, json } => status_impl(instance, json).await?,
+        Action::Doctor { timeout } => doctor_impl(timeout).await?,
+        #[cfg(feature = "schema")]
+        Action::Schema { what } => schema_impl(what)?,
     }
 
     Ok(())
 }
 
-fn install_tracing(path: &PathBuf) -> Result<()> {
+/// Sets up logging to `path`, and to `extra` if given
+///
+/// The returned guard flushes the extra log file when dropped, so it must be kept until exit
+fn install_tracing(
+    path: &PathBuf,
+    extra: Option<(PathBuf, LogRotation)>,
+) -> Result<Option<tracing_appender::non_blocking::WorkerGuard>> {
+    use tracing_appender::rolling;
     use tracing_error::ErrorLayer;
     use tracing_subscriber::prelude::*;
     use tracing_subscriber::{fmt, EnvFilter};
@@ -235,13 +1165,40 @@ fn install_tracing(path: &PathBuf) -> Result<()> {
     let filter_layer =
         EnvFilter::try_from_default_env().or_else(|_| EnvFilter::try_new("mcdl=debug"))?;
 
+    let (extra_layer, guard) = match extra {
+        Some((path, rotation)) => {
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            let name = path
+                .file_name()
+                .ok_or_else(|| eyre!("Invalid log file path {}", path.display()))?;
+            std::fs::create_dir_all(dir)
+                .wrap_err(format!("Failed to create log directory {}", dir.display()))?;
+
+            let appender = match rotation {
+                LogRotation::Never => rolling::never(dir, name),
+                LogRotation::Daily => rolling::daily(dir, name),
+            };
+            let (writer, guard) = tracing_appender::non_blocking(appender);
+            let layer = fmt::layer()
+                .with_ansi(false)
+                .with_thread_ids(true)
+                .with_writer(writer);
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
     tracing_subscriber::registry()
         .with(filter_layer)
         .with(fmt_layer)
+        .with(extra_layer)
         .with(ErrorLayer::default())
         .init();
 
-    Ok(())
+    Ok(guard)
 }
 
 /* end main */
@@ -249,7 +1206,15 @@ fn install_tracing(path: &PathBuf) -> Result<()> {
 /* impls */
 
 #[instrument(err, ret(level = "debug"), skip(filter))]
-async fn list_impl(filter: Option<ListFilter>, installed: bool) -> Result<()> {
+async fn list_impl(
+    filter: Option<ListFilter>,
+    installed: Option<Vec<String>>,
+    format: ListFormat,
+    tree: bool,
+    collapse: bool,
+    detailed: bool,
+    include_experimental: bool,
+) -> Result<()> {
     let filter = filter.unwrap_or_default();
     debug!(?filter);
 
@@ -258,142 +1223,635 @@ async fn list_impl(filter: Option<ListFilter>, installed: bool) -> Result<()> {
         .await
         .versions
         .iter()
-        .filter(|v| {
-            match (
-                filter.release,
-                filter.pre_release,
-                filter.snapshot,
-                filter.other,
-                filter.all,
-            ) {
-                (true, _, _, _, _) => v.id.is_release(),
-                (_, true, _, _, _) => v.id.is_pre_release(),
-                (_, _, true, _, _) => v.id.is_snapshot(),
-                (_, _, _, true, _) => v.id.is_other(),
-                (_, _, _, _, true) => true,
-                _ => unreachable!(),
-            }
-        })
+        .filter(|v| filter.includes(v, include_experimental, CONFIG.read().strict))
         .sorted()
         .collect_vec();
 
     info!("Found {} matching versions", versions.len());
 
-    if installed {
+    // with the tags to filter instances by, if any
+    if let Some(tags) = installed {
         // installed versions only, more info
         info!("Filtering for installed versions");
 
-        let installed_instances = &META.lock().instances;
+        let versions_by_id: HashMap<&VersionNumber, &GameVersion> =
+            versions.iter().map(|v| (&v.id, *v)).collect();
+
+        let meta = META.lock();
+        let current = meta.current_instance();
+        let installed_instances = &meta.instances;
         let filtered_instances = installed_instances
             .iter()
-            .filter(|(_, i)| versions.iter().any(|v| v.id == i.id))
+            .filter(|(_, i)| versions_by_id.contains_key(&i.id))
+            .filter(|(_, i)| tags.is_empty() || tags.iter().any(|tag| i.tags.contains(tag)))
             .collect_vec();
 
         info!("Found {} installed versions", filtered_instances.len());
+        if format == ListFormat::Plain {
+            for (id, _) in filtered_instances {
+                println!("{id}");
+            }
+            return Ok(());
+        }
+        if format == ListFormat::Json {
+            let instances = filtered_instances
+                .iter()
+                .map(|(id, instance)| {
+                    let version = versions_by_id[&instance.id];
+                    json!({
+                        "id": id,
+                        "version": version.id,
+                        "type": version.release_type,
+                        "jre": instance.jre,
+                        "location": app::instance_dir(id, instance),
+                        "custom_jar": instance.custom_jar,
+                        "tags": instance.tags,
+                        "client": instance.client,
+                        "current": current == Some(*id),
+                        "running": app::is_running(id),
+                    })
+                })
+                .collect_vec();
+
+            println!("{}", serde_json::to_string_pretty(&instances)?);
+            return Ok(());
+        }
+        if format != ListFormat::Table {
+            println!(
+                "{}",
+                format.row(&["id", "version", "type", "jre", "location"])
+            );
+            for (id, instance) in filtered_instances {
+                let version = versions_by_id[&instance.id];
+                let location = app::instance_dir(id, instance);
+
+                println!(
+                    "{}",
+                    format.row(&[
+                        id.to_string(),
+                        version.id.to_string(),
+                        version.release_type.clone(),
+                        instance.jre.to_string(),
+                        location.display().to_string(),
+                    ])
+                );
+            }
+            return Ok(());
+        }
+
         if filtered_instances.is_empty() {
             println!("No matching versions installed");
             return Ok(());
         }
 
-        let mut table = Table::new();
-        table.set_format(
-            FormatBuilder::new()
-                .column_separator(' ')
-                .borders(' ')
-                .padding(1, 1)
-                .build(),
-        );
-
-        table.set_titles(row![b => "ID", "Version", "Type", "JRE"]);
+        let rows = filtered_instances
+            .into_iter()
+            .map(|(id, instance)| {
+                let version = versions_by_id[&instance.id];
+                let location = app::instance_dir(id, instance);
+                let size = app::format_size(dir_size(&location));
+                // marked like the current branch in `git branch`
+                let name = match current {
+                    Some(current) if current == id => format!("* {id}"),
+                    Some(_) => format!("  {id}"),
+                    None => id.to_string(),
+                };
+
+                (
+                    row![name, version.id, version.kind(), instance.jre, r->size],
+                    location,
+                )
+            })
+            .collect_vec();
 
-        for (id, instance) in filtered_instances {
-            let version = versions.iter().find(|v| v.id == instance.id).unwrap();
-            let location = PROJ_DIRS.data_local_dir().join("instance").join(id);
+        // locations are long, so they get a column only if the table still fits
+        let mut table =
+            list_table(row![bFc => "Name", "Version", "Type", "JRE", "Size", "Location"]);
+        for (row, location) in &rows {
+            let mut row = row.clone();
+            row.add_cell(Cell::new(&location.display().to_string()));
+            table.add_row(row);
+        }
 
-            table.add_row(row![id, version.id, version.release_type, instance.jre]);
-            table.add_row(row![H4->format!("{} {}", "Location:".bold(), location.display())]);
-            table.add_empty_row();
+        if !fits_terminal(&table) {
+            let label = if CONFIG.read().color.should_colorize() {
+                "Location:".bold().to_string()
+            } else {
+                "Location:".to_string()
+            };
+
+            table = list_table(row![bFc => "Name", "Version", "Type", "JRE", "Size"]);
+            for (row, location) in rows {
+                table.add_row(row);
+                table.add_row(row![H5->format!("{label} {}", location.display())]);
+                table.add_empty_row();
+            }
         }
 
-        table.printstd();
+        print_table(&table)?;
     } else {
         // short info for all versions
         info!("Filtering for all versions");
 
-        if !std::io::stdout().is_terminal() {
+        if tree {
+            print_tree(&versions, collapse);
+            return Ok(());
+        }
+        if format == ListFormat::Plain || (format == ListFormat::Table && !detailed) {
             for v in versions {
                 println!("{}", v.id);
             }
             return Ok(());
         }
+        if format == ListFormat::Json {
+            let versions = versions
+                .iter()
+                .map(|v| {
+                    json!({
+                        "id": v.id,
+                        "type": v.release_type,
+                        "release_time": v.release_time,
+                    })
+                })
+                .collect_vec();
+
+            println!("{}", serde_json::to_string_pretty(&versions)?);
+            return Ok(());
+        }
+        if format != ListFormat::Table {
+            println!("{}", format.row(&["id", "type", "release_time"]));
+            for v in versions {
+                println!(
+                    "{}",
+                    format.row(&[
+                        v.id.to_string(),
+                        v.release_type.clone(),
+                        v.release_time.to_rfc3339(),
+                    ])
+                );
+            }
+            return Ok(());
+        }
 
-        let mut table = Table::new();
-        table.set_format(
-            FormatBuilder::new()
-                .column_separator(' ')
-                .borders(' ')
-                .padding(1, 1)
-                .build(),
-        );
-
-        table.set_titles(row![b => "Version", "Type", "Release Date"]);
+        let mut table = list_table(row![bFc => "Version", "Type", "Release Date"]);
         for version in versions {
             table.add_row(Row::new(vec![
                 Cell::new(&version.id.to_string()),
-                Cell::new(&version.release_type.to_string()).style_spec(
-                    match version.release_type.as_str() {
-                        "release" => "Fgb",
-                        _ => "",
-                    },
-                ),
+                Cell::new(version.kind()).style_spec(match version.release_type.as_str() {
+                    "release" => "Fgb",
+                    _ => "",
+                }),
                 Cell::new(&version.release_time.to_string()),
             ]));
         }
 
-        table.printstd();
+        print_table(&table)?;
+    }
+
+    Ok(())
+}
+
+/// Prints each check `list` makes on `version` with the given options, and whether it's listed
+///
+/// `installed` holds the tags to filter instances by, like for [`list_impl`]
+#[instrument(err, ret(level = "debug"), skip(filter))]
+async fn explain_list_filter(
+    filter: Option<ListFilter>,
+    installed: Option<Vec<String>>,
+    include_experimental: bool,
+    version: &VersionNumber,
+) -> Result<()> {
+    let manifest = MANIFEST.get().await;
+    let Some(version) = manifest.versions.iter().find(|v| v.id == *version) else {
+        println!("`{version}` is not in the version manifest, so it is never listed");
+        let suggestions = manifest.suggest(&version.to_string());
+        if !suggestions.is_empty() {
+            println!("Similar versions: {}", suggestions.iter().join(", "));
+        }
+        return Ok(());
+    };
+
+    println!(
+        "`{}` is in the version manifest with type `{}`",
+        version.id, version.release_type
+    );
+    // e.g. pre-releases are `snapshot` in the manifest
+    println!("Versions are filtered by the format of their ID, not their manifest type");
+
+    let filter = match filter {
+        Some(filter) => {
+            println!("Filter: {}", filter.flag());
+            filter
+        }
+        None => {
+            println!("Filter: --release (the default)");
+            ListFilter::default()
+        }
+    };
+
+    let (mut listed, trace) = filter.explain(version, include_experimental, CONFIG.read().strict);
+    for step in trace {
+        println!("  - {step}");
+    }
+
+    if let (Some(tags), true) = (installed, listed) {
+        let meta = META.lock();
+        let instances = meta
+            .instances
+            .iter()
+            .filter(|(_, i)| i.id == version.id)
+            .sorted_by_key(|(name, _)| *name)
+            .collect_vec();
+        if instances.is_empty() {
+            println!("  - --installed hides it, as no instance uses it");
+            listed = false;
+        } else {
+            println!(
+                "  - --installed lists the instances using it: {}",
+                instances.iter().map(|(name, _)| name).join(", ")
+            );
+        }
+
+        if listed && !tags.is_empty() {
+            let wanted = tags.iter().map(|t| format!("`{t}`")).join(" or ");
+            let tagged = instances
+                .iter()
+                .filter(|(_, i)| tags.iter().any(|tag| i.tags.contains(tag)))
+                .map(|(name, _)| name)
+                .collect_vec();
+            if tagged.is_empty() {
+                println!("  - --tag hides it, as no instance using it is tagged {wanted}");
+                listed = false;
+            } else {
+                println!(
+                    "  - --tag keeps the instances tagged {wanted}: {}",
+                    tagged.iter().join(", ")
+                );
+            }
+        }
+    }
+
+    let result = if listed { "listed" } else { "not listed" };
+    println!("Result: `{}` is {result}", version.id);
+
+    Ok(())
+}
+
+fn print_tree(versions: &[&GameVersion], collapse: bool) {
+    let colorize = CONFIG.read().color.should_colorize();
+
+    for (series, nodes) in group_versions(versions.iter().map(|v| &v.id)) {
+        let heading = if colorize {
+            series.bold().to_string()
+        } else {
+            series.to_string()
+        };
+
+        if collapse {
+            let count = nodes
+                .iter()
+                .map(|n| 1 + n.pre_releases.len())
+                .sum::<usize>();
+            let latest = nodes
+                .last()
+                .map(|n| n.version.to_string())
+                .unwrap_or_default();
+            println!("{heading} ({count} versions, latest {latest})");
+            continue;
+        }
+
+        println!("{heading}");
+        for (i, node) in nodes.iter().enumerate() {
+            let last = i == nodes.len() - 1;
+            println!("{} {}", if last { "└─" } else { "├─" }, node.version);
+
+            let indent = if last { "  " } else { "│ " };
+            for (j, pre) in node.pre_releases.iter().enumerate() {
+                let branch = if j == node.pre_releases.len() - 1 {
+                    "└─"
+                } else {
+                    "├─"
+                };
+                println!("{indent}  {branch} {pre}");
+            }
+        }
+    }
+}
+
+/// Creates an empty, borderless table for `list`
+fn list_table(titles: Row) -> Table {
+    let mut table = Table::new();
+    table.set_format(
+        FormatBuilder::new()
+            .column_separator(' ')
+            .borders(' ')
+            .padding(1, 1)
+            .build(),
+    );
+    table.set_titles(titles);
+
+    table
+}
+
+/// Whether `table` fits in the terminal without wrapping
+///
+/// Always true if stdout is not a terminal and no width was given, since there is nothing to wrap
+fn fits_terminal(table: &Table) -> bool {
+    let width = match *TERM_WIDTH_OVERRIDE {
+        Some(width) => width,
+        None => match console::Term::stdout().size_checked() {
+            Some((_, width)) => width as usize,
+            None => return true,
+        },
+    };
+
+    table
+        .to_string()
+        .lines()
+        .all(|line| line.chars().count() <= width)
+}
+
+fn print_table(table: &Table) -> Result<()> {
+    if CONFIG.read().color.should_colorize() {
+        table.print_tty(true)?;
+    } else {
+        table.print(&mut std::io::stdout())?;
     }
 
     Ok(())
 }
 
 #[instrument(err, ret(level = "debug"))]
-async fn info_impl(version: VersionNumber) -> Result<()> {
-    let version = MANIFEST
-        .get()
-        .await
-        .versions
-        .iter()
-        .find(|v| v.id == version)
-        .expect("infallible");
+async fn info_impl(version: VersionNumber, sha: bool) -> Result<()> {
+    let version = version.resolve(MANIFEST.get().await)?;
+    check_strict(&version.id)?;
 
     let time_format = "%-d %B %Y at %-I:%M:%S%P UTC";
     let message = format!(
         "Version {} ({})\nReleased: {}\nLast updated: {}",
         version.id,
-        version.release_type,
+        version.kind(),
         version.release_time.format(time_format),
         version.time.format(time_format),
     );
 
     println!("{message}");
 
+    // the basic info above doesn't need the metadata, so it's still shown without it
+    let metadata = match get_version_metadata(version).await {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            warn!("Failed to get version metadata: {e}");
+            println!("Downloads: unknown (couldn't get version metadata)");
+            return Ok(());
+        }
+    };
+
+    let java = &metadata.java_version;
+    let installed = if app::shared_jre_installed(java.major_version) {
+        "installed"
+    } else {
+        "not installed"
+    };
+    if java.assumed {
+        println!(
+            "Requires Java {} (assumed, since the version doesn't specify one; {installed})",
+            java.major_version
+        );
+    } else {
+        println!("Requires Java {} ({installed})", java.major_version);
+    }
+
+    let downloads = metadata.downloads_by_size();
+    if downloads.is_empty() {
+        println!(
+            "Downloads: none ({})",
+            metadata.missing_jar_reason("server").unwrap_or_default()
+        );
+        return Ok(());
+    }
+
+    let width = downloads
+        .iter()
+        .map(|(kind, _)| kind.len())
+        .chain(["total".len()])
+        .max()
+        .expect("infallible");
+    println!("Downloads:");
+    for (kind, download) in &downloads {
+        let size = app::format_size(download.size);
+        if sha {
+            println!("  {kind:width$}  {size:<10}  sha1 {}", download.sha1());
+        } else {
+            println!("  {kind:width$}  {size}");
+        }
+    }
+    let total = downloads.iter().map(|(_, d)| d.size).sum();
+    println!("  {:width$}  {}", "total", app::format_size(total));
+    if let Some(reason) = metadata.missing_jar_reason("server") {
+        println!("No server: {reason}");
+    }
+
+    Ok(())
+}
+
+#[instrument(err, ret(level = "debug"))]
+fn info_installed_impl(id: &str) -> Result<()> {
+    let meta = META.lock();
+    let instance = meta
+        .instances
+        .get(id)
+        .ok_or_else(|| eyre!("Instance `{id}` does not exist"))?;
+
+    println!(
+        "Instance {id}\nVersion: {}\nLocation: {}",
+        instance.id,
+        app::instance_dir(id, instance).display()
+    );
+    let kind = if instance.client { "client" } else { "server" };
+    match (&instance.custom_jar, &instance.download) {
+        (Some(jar), _) => println!("Jar: custom {kind} jar from {jar}"),
+        (None, Some(download)) => println!(
+            "Jar: official {kind} jar\nURL: {}\nSize: {}\nSHA-1: {}",
+            download.url,
+            app::format_size(download.size),
+            download.sha1()
+        ),
+        (None, None) => println!("Jar: official {kind} jar (download not recorded at install)"),
+    }
+
+    Ok(())
+}
+
+#[instrument(err, ret(level = "debug"))]
+async fn compare_impl(version: VersionNumber, other: VersionNumber) -> Result<()> {
+    let manifest = MANIFEST.get().await;
+    let versions = [version.resolve(manifest)?, other.resolve(manifest)?];
+    check_strict(&versions[0].id)?;
+    check_strict(&versions[1].id)?;
+    let [meta, other_meta] = [
+        get_version_metadata(versions[0]).await?,
+        get_version_metadata(versions[1]).await?,
+    ];
+
+    let date_format = "%-d %B %Y";
+    let java = [
+        meta.java_version.major_version,
+        other_meta.java_version.major_version,
+    ];
+    let java_changed = java[0] != java[1];
+    let downloads = [&meta, &other_meta].map(|m| {
+        if m.downloads.is_empty() {
+            "none".to_string()
+        } else {
+            m.downloads.keys().sorted().join(", ")
+        }
+    });
+
+    let mut table = Table::new();
+    table.set_format(
+        FormatBuilder::new()
+            .column_separator(' ')
+            .borders(' ')
+            .padding(1, 1)
+            .build(),
+    );
+    table.set_titles(row![b => "", versions[0].id, versions[1].id]);
+    table.add_row(row![b->"Type", versions[0].kind(), versions[1].kind()]);
+    table.add_row(row![
+        b->"Released",
+        versions[0].release_time.format(date_format),
+        versions[1].release_time.format(date_format)
+    ]);
+    let java_style = if java_changed { "Fyb" } else { "" };
+    table.add_row(Row::new(vec![
+        Cell::new("Java").style_spec("b"),
+        Cell::new(&java[0].to_string()).style_spec(java_style),
+        Cell::new(&java[1].to_string()).style_spec(java_style),
+    ]));
+    table.add_row(row![b->"Downloads", downloads[0], downloads[1]]);
+    print_table(&table)?;
+
+    if java_changed {
+        println!(
+            "\nThe required Java version changes from {} to {}, so switching needs JRE {}",
+            java[0], java[1], java[1]
+        );
+    }
+
     Ok(())
 }
 
-#[instrument(err, ret(level = "debug"), skip(versions))]
-async fn install_impl(versions: Option<Vec<VersionNumber>>) -> Result<()> {
+#[instrument(err, ret(level = "debug"))]
+async fn install_impl(args: InstallArgs) -> Result<()> {
     let manifest = MANIFEST.get().await;
-    let game_versions = &manifest.versions;
-    let latest = &manifest.latest;
 
+    let mut versions = args.version;
+    let mut invalid_lines = 0;
+    if args.stdin.is_some() {
+        let mut input = String::new();
+        std::io::stdin()
+            .read_to_string(&mut input)
+            .wrap_err("Failed to read versions from stdin")?;
+
+        let (valid, errors) = versions_from_lines(&input, manifest);
+        if !errors.is_empty() {
+            let errors = errors.iter().join("\n");
+            if !args.keep_going {
+                return Err(eyre!("Invalid versions on stdin:\n{errors}"));
+            }
+            eprintln!("Skipping invalid versions on stdin:\n{errors}\n");
+        }
+        invalid_lines = errors.len();
+        versions = Some(valid);
+    }
+
+    // the same version given twice is still one install
+    if versions
+        .as_ref()
+        .is_some_and(|v| v.iter().unique().count() > 1)
+    {
+        for (used, flag) in [
+            (args.server_jar.is_some(), "--server-jar"),
+            (args.output_dir.is_some(), "--output-dir"),
+        ] {
+            if used {
+                Cli::command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        format!("{flag} can only be used when installing a single version"),
+                    )
+                    .exit();
+            }
+        }
+    }
+
+    let mut memory = None;
+    if versions.is_none()
+        && args.channel.is_none()
+        && !args.no_interactive
+        && !args.resolve_only
+        && std::io::stdin().is_terminal()
+    {
+        match app::install_wizard(manifest)? {
+            Some((version, wizard_memory)) => {
+                versions = Some(vec![version.id.clone()]);
+                memory = wizard_memory;
+            }
+            None => {
+                println!("Install cancelled");
+                return Ok(());
+            }
+        }
+    }
+
+    let options = app::InstallOptions {
+        concurrency: args
+            .jobs
+            .map_or_else(|| CONFIG.read().concurrency, usize::from),
+        // the EULA is only written for servers
+        accept_eula: !args.client
+            && (args.accept_eula
+                || CONFIG.read().accept_eula
+                || (!args.no_eula_file && !args.resolve_only && app::prompt_eula()?)),
+        eula_file: !args.no_eula_file && !args.client,
+        world: args.world,
+        link_world: args.link_world,
+        bundled_jre: args.bundled_jre,
+        system_java: args.skip_jre,
+        keep_going: args.keep_going,
+        space_check: !args.no_space_check,
+        server_jar: args.server_jar,
+        server_jar_checksum: args.server_jar_checksum,
+        after_install: args
+            .after_install
+            .or_else(|| CONFIG.read().after_install.clone()),
+        ignore_hook_errors: args.ignore_hook_errors,
+        mappings: args.mappings.then_some(args.mappings_format),
+        output_dir: args.output_dir,
+        name: args.name,
+        tags: args.tag,
+        client: args.client,
+        client_libraries: args.client_libraries,
+        assets: args.assets,
+        bind: args.bind,
+        ipv6: args.ipv6,
+        memory,
+    };
     if versions.is_none() {
-        println!("Installing latest release version\n");
-        let latest = game_versions
-            .iter()
-            .find(|v| v.id == latest.release)
-            .ok_or_else(|| eyre!("No latest release version found"))?;
-        app::install_versions(vec![latest])
-            .await
+        let channel = args.channel.unwrap_or(Channel::Release);
+        let latest = manifest
+            .latest(channel.latest_alias())
+            .expect("infallible")?;
+        if args.resolve_only {
+            return print_resolved_installs(vec![latest], &options, args.format).await;
+        }
+
+        match channel {
+            Channel::All => println!("Installing newest version ({})\n", latest.id),
+            _ => println!("Installing latest {channel} version ({})\n", latest.id),
+        }
+        exit_if_interrupted(app::install_versions(vec![latest], options).await)
             .wrap_err("Error while installing latest version")?;
 
         return Ok(());
@@ -406,36 +1864,439 @@ async fn install_impl(versions: Option<Vec<VersionNumber>>) -> Result<()> {
             .exit();
     }
 
+    let to_install_versions = versions
+        .iter()
+        .map(|v| {
+            let version = v.resolve(manifest)?;
+            check_strict(&version.id)?;
+            Ok(version)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if args.resolve_only {
+        return print_resolved_installs(to_install_versions, &options, args.format).await;
+    }
+
+    let unique_ids = to_install_versions
+        .iter()
+        .map(|v| v.id.to_string())
+        .unique()
+        .collect::<Vec<_>>();
     println!(
         "Installing {} version{}: {}\n",
-        versions.len(),
-        if versions.len() == 1 { "" } else { "s" },
-        versions.iter().map(ToString::to_string).join(", ")
+        unique_ids.len(),
+        if unique_ids.len() == 1 { "" } else { "s" },
+        unique_ids.join(", ")
     );
+    exit_if_interrupted(app::install_versions(to_install_versions, options).await)
+        .wrap_err("Error while installing versions")?;
 
-    let to_install_versions = game_versions
-        .iter()
-        .filter(|v| versions.contains(&v.id))
-        .collect_vec();
-    app::install_versions(to_install_versions)
+    if invalid_lines > 0 {
+        return Err(eyre!(
+            "{invalid_lines} invalid version{} on stdin were skipped",
+            if invalid_lines == 1 { "" } else { "s" }
+        ));
+    }
+
+    Ok(())
+}
+
+/// Prints the instances an install would create, for `install --resolve-only`
+async fn print_resolved_installs(
+    versions: Vec<&GameVersion>,
+    options: &app::InstallOptions,
+    format: ListFormat,
+) -> Result<()> {
+    let jar = match &options.server_jar {
+        Some(jar) => jar.to_string(),
+        None if options.client => "client".to_string(),
+        None => "server".to_string(),
+    };
+    let java = if options.system_java {
+        "system"
+    } else if options.bundled_jre {
+        "bundled"
+    } else {
+        "shared"
+    };
+
+    let mut installs = vec![];
+    for (version, name) in app::plan_installs(versions, options)? {
+        let jre = get_version_metadata(version)
+            .await?
+            .java_version
+            .major_version;
+        let location = options.instance_dir(&name);
+        installs.push((version, name, jre, location));
+    }
+
+    match format {
+        ListFormat::Plain => {
+            for (_, name, _, _) in installs {
+                println!("{name}");
+            }
+        }
+        ListFormat::Json => {
+            let installs = installs
+                .iter()
+                .map(|(version, name, jre, location)| {
+                    json!({
+                        "name": name,
+                        "version": version.id,
+                        "type": version.release_type,
+                        "jar": jar,
+                        "jre": jre,
+                        "java": java,
+                        "location": location,
+                        "tags": options.tags,
+                    })
+                })
+                .collect_vec();
+            println!("{}", serde_json::to_string_pretty(&installs)?);
+        }
+        ListFormat::Tsv | ListFormat::Csv => {
+            println!(
+                "{}",
+                format.row(&["name", "version", "type", "jar", "jre", "java", "location"])
+            );
+            for (version, name, jre, location) in installs {
+                println!(
+                    "{}",
+                    format.row(&[
+                        name,
+                        version.id.to_string(),
+                        version.release_type.clone(),
+                        jar.clone(),
+                        jre.to_string(),
+                        java.to_string(),
+                        location.display().to_string(),
+                    ])
+                );
+            }
+        }
+        ListFormat::Table => {
+            let mut table =
+                list_table(row![bFc => "Name", "Version", "Type", "Jar", "JRE", "Location"]);
+            for (version, name, jre, location) in installs {
+                table.add_row(row![
+                    name,
+                    version.id,
+                    version.kind(),
+                    jar,
+                    format!("{jre} ({java})"),
+                    location.display()
+                ]);
+            }
+            print_table(&table)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses and resolves versions given one per line, skipping empty lines and `#` comments
+///
+/// Returns the versions that exist, and an error for each line that doesn't, with its line number
+fn versions_from_lines(
+    input: &str,
+    manifest: &GameVersionList,
+) -> (Vec<VersionNumber>, Vec<String>) {
+    let mut versions = vec![];
+    let mut errors = vec![];
+    for (i, line) in input.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let version = parse_version_arg(line).and_then(|version| {
+            let version = version.resolve(manifest)?;
+            check_strict(&version.id)?;
+            Ok(version.id.clone())
+        });
+        match version {
+            Ok(version) => versions.push(version),
+            Err(e) => errors.push(format!("line {}: {e}", i + 1)),
+        }
+    }
+
+    (versions, errors)
+}
+
+/// Exits with the conventional status for SIGINT if `result` is from an interrupted operation
+fn exit_if_interrupted<T>(result: Result<T>) -> Result<T> {
+    if let Err(e) = &result {
+        if e.downcast_ref::<app::Interrupted>().is_some() {
+            eprintln!("Interrupted");
+            std::process::exit(130);
+        }
+    }
+
+    result
+}
+
+#[instrument(err, ret(level = "debug"))]
+fn uninstall_impl(version: String, dry_run: bool) -> Result<()> {
+    let id = parse_version_arg(&version)?;
+    check_strict_instance(&id.to_string())?;
+    app::uninstall_instance(id, dry_run).wrap_err("Error while uninstalling instance")?;
+
+    Ok(())
+}
+
+#[instrument(err, ret(level = "debug"))]
+fn rename_impl(instance: String, new_name: String, level_name: bool) -> Result<()> {
+    // normalize so that e.g. `1.0.0` finds the `1.0` instance
+    let instance = parse_version_arg(&instance)?.to_string();
+    app::rename_instance(&instance, &new_name, level_name)
+        .wrap_err("Error while renaming instance")?;
+
+    Ok(())
+}
+
+#[instrument(err, ret(level = "debug"))]
+async fn clone_impl(instance: String, new_name: String, no_world: bool) -> Result<()> {
+    let instance = parse_version_arg(&instance)?.to_string();
+    app::clone_instance(&instance, &new_name, !no_world)
         .await
-        .wrap_err("Error while installing versions")?;
+        .wrap_err("Error while cloning instance")?;
+
+    Ok(())
+}
+
+#[instrument(err, ret(level = "debug"), skip(env))]
+async fn run_impl(
+    version: Vec<String>,
+    tag: Vec<String>,
+    print_command: bool,
+    keep_going: bool,
+    env: Vec<(String, String)>,
+    env_file: Option<PathBuf>,
+) -> Result<()> {
+    // variables from the file come first, so `--env` overrides them
+    let env = match env_file {
+        Some(path) => EnvFile::from_file(path)?
+            .vars
+            .into_iter()
+            .chain(env)
+            .collect(),
+        None => env,
+    };
+
+    let instances = if !tag.is_empty() {
+        let instances = app::tagged_instances(&tag);
+        if instances.is_empty() {
+            return Err(eyre!(
+                "No instances are tagged {}",
+                tag.iter().map(|t| format!("`{t}`")).join(" or ")
+            ));
+        }
+        instances
+    } else if version.is_empty() {
+        vec![instance_or_current(None)?]
+    } else {
+        version
+            .into_iter()
+            .map(|instance| instance_or_current(Some(instance)))
+            .collect::<Result<Vec<_>>>()?
+    };
+    if let Some(instance) = instances.iter().duplicates().next() {
+        return Err(eyre!("Instance `{instance}` was given more than once"));
+    }
+    for instance in &instances {
+        check_strict_instance(instance)?;
+    }
+    let ids = instances
+        .iter()
+        .map(|instance| instance.parse())
+        .collect::<Result<Vec<VersionNumber>>>()?;
+
+    match <[_; 1]>::try_from(ids) {
+        Ok([id]) => app::run_instance(id, print_command, env)
+            .await
+            .wrap_err("Error while running server")?,
+        Err(ids) => {
+            exit_if_interrupted(app::run_instances(ids, print_command, env, keep_going).await)
+                .wrap_err("Error while running servers")?
+        }
+    }
 
     Ok(())
 }
 
 #[instrument(err, ret(level = "debug"))]
-fn uninstall_impl(version: String) -> Result<()> {
-    app::uninstall_instance(version.parse()?).wrap_err("Error while uninstalling instance")?;
+fn tag_impl(instance: String, action: Option<TagAction>) -> Result<()> {
+    // normalize so that e.g. `1.0.0` finds the `1.0` instance
+    let instance = parse_version_arg(&instance)?.to_string();
+    let (add, remove) = match action {
+        Some(TagAction::Add { tags }) => (tags, vec![]),
+        Some(TagAction::Remove { tags }) => (vec![], tags),
+        None => (vec![], vec![]),
+    };
+    app::tag_instance(&instance, &add, &remove).wrap_err("Error while tagging instance")?;
 
     Ok(())
 }
 
 #[instrument(err, ret(level = "debug"))]
-async fn run_impl(version: String) -> Result<()> {
-    app::run_instance(version.parse()?)
+async fn which_java_impl(instance: Option<String>) -> Result<()> {
+    let instance = instance_or_current(instance)?;
+    check_strict_instance(&instance)?;
+    app::which_java(&instance)
         .await
-        .wrap_err("Error while running server")?;
+        .wrap_err("Error while finding Java")?;
+
+    Ok(())
+}
+
+#[instrument(err, ret(level = "debug"))]
+fn open_impl(instance: Option<String>, shell: bool) -> Result<()> {
+    let instance = instance_or_current(instance)?;
+    app::open_instance(&instance, shell).wrap_err("Error while opening instance")?;
+
+    Ok(())
+}
+
+#[instrument(err, ret(level = "debug"))]
+fn use_impl(instance: Option<String>, clear: bool) -> Result<()> {
+    if clear {
+        return app::use_instance(None).wrap_err("Error while clearing the current instance");
+    }
+
+    match instance {
+        Some(instance) => {
+            let instance = parse_version_arg(&instance)?.to_string();
+            app::use_instance(Some(&instance))
+                .wrap_err("Error while setting the current instance")?;
+        }
+        None => match META.lock().current_instance() {
+            Some(current) => println!("{current}"),
+            None => println!("No current instance"),
+        },
+    }
+
+    Ok(())
+}
+
+#[instrument(err, ret(level = "debug"))]
+async fn jre_impl(action: JreAction) -> Result<()> {
+    match action {
+        JreAction::Sync => app::sync_jres()
+            .await
+            .wrap_err("Error while syncing JREs")?,
+        JreAction::List => list_jres().await.wrap_err("Error while listing JREs")?,
+        JreAction::Prefetch {
+            jre,
+            os,
+            arch,
+            all_platforms,
+        } => {
+            let platforms = if all_platforms {
+                Platform::all()
+            } else {
+                let native = Platform::native();
+                let os = if os.is_empty() { vec![native.os] } else { os };
+                let arch = if arch.is_empty() {
+                    vec![native.arch]
+                } else {
+                    arch
+                };
+                os.iter()
+                    .cartesian_product(&arch)
+                    .map(|(os, arch)| Platform {
+                        os: os.clone(),
+                        arch: arch.clone(),
+                    })
+                    .collect()
+            };
+
+            app::prefetch_jres(jre, platforms)
+                .await
+                .wrap_err("Error while prefetching JREs")?
+        }
+        JreAction::Platforms { jre } => list_jre_platforms(jre)
+            .await
+            .wrap_err(format!("Error while listing platforms for Java {jre}"))?,
+    }
+
+    Ok(())
+}
+
+async fn list_jres() -> Result<()> {
+    let jres = app::instance_jres().await?;
+    if jres.is_empty() {
+        println!("No instances installed");
+        return Ok(());
+    }
+
+    let format = FormatBuilder::new()
+        .column_separator(' ')
+        .borders(' ')
+        .padding(1, 1)
+        .build();
+
+    let mut table = Table::new();
+    table.set_format(format);
+    table.set_titles(row![b => "Instance", "JRE", "Source"]);
+    for jre in &jres {
+        let source = match (jre.system, jre.bundled) {
+            (true, _) => "system",
+            (_, true) => "bundled",
+            _ => "shared",
+        };
+        table.add_row(row![jre.instance, jre.version, source]);
+    }
+    print_table(&table)?;
+    println!();
+
+    let mut table = Table::new();
+    table.set_format(format);
+    table.set_titles(row![b => "JRE", "Instances", "Installed"]);
+    let by_version = jres
+        .iter()
+        .sorted_by_key(|jre| jre.version)
+        .chunk_by(|jre| jre.version);
+    for (version, group) in &by_version {
+        let group = group.collect_vec();
+        // instances with a bundled JRE or the system Java don't need the shared one
+        let installed = if group.iter().all(|jre| jre.bundled) {
+            "bundled only"
+        } else if group.iter().all(|jre| jre.system) {
+            "system only"
+        } else if group.iter().all(|jre| jre.bundled || jre.system) {
+            "not needed"
+        } else if app::shared_jre_installed(version) {
+            "yes"
+        } else {
+            "no"
+        };
+        table.add_row(row![version, group.len(), installed]);
+    }
+    print_table(&table)?;
+
+    Ok(())
+}
+
+async fn list_jre_platforms(major_version: u8) -> Result<()> {
+    let assets = get_jre_assets(major_version).await?;
+    let platforms = platform_images(&assets);
+    if platforms.is_empty() {
+        println!("No builds of Java {major_version} found");
+        return Ok(());
+    }
+
+    let format = FormatBuilder::new()
+        .column_separator(' ')
+        .borders(' ')
+        .padding(1, 1)
+        .build();
+
+    let mut table = Table::new();
+    table.set_format(format);
+    table.set_titles(row![b => "OS", "Arch", "Images"]);
+    for (platform, images) in &platforms {
+        table.add_row(row![platform.os, platform.arch, images.iter().join(", ")]);
+    }
+    print_table(&table)?;
 
     Ok(())
 }
@@ -448,4 +2309,160 @@ fn locate_impl(what: WhatEnum) -> Result<()> {
     Ok(())
 }
 
+#[instrument(err, ret(level = "debug"))]
+async fn service_impl(
+    version: String,
+    kind: Option<ServiceKind>,
+    user: Option<String>,
+    install: bool,
+) -> Result<()> {
+    let kind = kind.unwrap_or_else(ServiceKind::native);
+    let id = parse_version_arg(&version)?;
+    check_strict_instance(&id.to_string())?;
+    app::service(id, kind, user, install)
+        .await
+        .wrap_err(format!("Error while generating {kind} service"))?;
+
+    Ok(())
+}
+
+#[cfg(feature = "schema")]
+#[instrument(err, ret(level = "debug"))]
+fn schema_impl(what: SchemaTarget) -> Result<()> {
+    let schema = match what {
+        SchemaTarget::Config => schemars::schema_for!(Config),
+        SchemaTarget::Settings => schemars::schema_for!(types::meta::InstanceSettings),
+    };
+    println!("{}", serde_json::to_string_pretty(&schema)?);
+
+    Ok(())
+}
+
+#[instrument(err, ret(level = "debug"))]
+fn config_impl(edit: bool) -> Result<()> {
+    app::config(edit).wrap_err("Error while accessing config")?;
+
+    Ok(())
+}
+
+#[instrument(err, ret(level = "debug"))]
+async fn outdated_impl(major: bool) -> Result<()> {
+    if META.lock().instances.is_empty() {
+        println!("No instances installed");
+        return Ok(());
+    }
+
+    let manifest = MANIFEST.get().await;
+    let meta = META.lock();
+
+    let mut outdated = 0;
+    for (name, instance) in meta.instances.iter().sorted_by_key(|(name, _)| *name) {
+        let Some(newer) = manifest.newer_release(&instance.id, major) else {
+            continue;
+        };
+
+        outdated += 1;
+        if *name == instance.id.to_string() {
+            println!("{name} -> {} available", newer.id);
+        } else {
+            println!("{name} ({}) -> {} available", instance.id, newer.id);
+        }
+    }
+
+    if outdated == 0 {
+        println!("All instances are up to date");
+    }
+
+    Ok(())
+}
+
+#[instrument(err, ret(level = "debug"))]
+async fn status_impl(instance: Option<String>, json: bool) -> Result<()> {
+    let statuses = match instance {
+        // asked for by name, so there is nothing else to show if it fails
+        Some(instance) => {
+            let id = parse_version_arg(&instance)?.to_string();
+            let status = app::instance_status(&id).await?;
+            vec![(id, Ok(status))]
+        }
+        // one broken instance shouldn't hide the others
+        None => {
+            let ids = META.lock().instances.keys().sorted().cloned().collect_vec();
+            let mut statuses = vec![];
+            for id in ids {
+                let status = app::instance_status(&id).await;
+                if let Err(e) = &status {
+                    error!(id, "Failed to read instance status: {e:?}");
+                }
+                statuses.push((id, status));
+            }
+            statuses
+        }
+    };
+
+    if json {
+        let statuses = statuses
+            .into_iter()
+            .map(|(id, status)| match status {
+                Ok(status) => serde_json::to_value(status),
+                Err(e) => Ok(serde_json::json!({ "name": id, "error": format!("{e:#}") })),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        println!("{}", serde_json::to_string_pretty(&statuses)?);
+        return Ok(());
+    }
+    if statuses.is_empty() {
+        println!("No instances installed");
+        return Ok(());
+    }
+
+    let mut table = list_table(
+        row![bFc => "Name", "Status", "Version", "Type", "Port", "JRE", "Size", "Last started"],
+    );
+    for (id, status) in statuses {
+        let status = match status {
+            Ok(status) => status,
+            Err(e) => {
+                table.add_row(Row::new(vec![
+                    Cell::new(&id),
+                    Cell::new("error").style_spec("Fr"),
+                    Cell::new(&format!("{e:#}")).with_hspan(6),
+                ]));
+                continue;
+            }
+        };
+        let state = match status.pid {
+            Some(pid) => Cell::new(&format!("running ({pid})")).style_spec("Fg"),
+            None if status.client => Cell::new("client"),
+            None => Cell::new("stopped").style_spec("Fr"),
+        };
+        let last_started = status.last_started.map_or("never".to_string(), |time| {
+            time.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        });
+
+        table.add_row(Row::new(vec![
+            Cell::new(&status.name),
+            state,
+            Cell::new(&status.version.to_string()),
+            Cell::new(status.kind.as_deref().unwrap_or("unknown")),
+            Cell::new(&status.port.map_or("-".to_string(), |port| port.to_string())),
+            Cell::new(&status.jre.to_string()),
+            Cell::new(&app::format_size(status.size)).style_spec("r"),
+            Cell::new(&last_started),
+        ]));
+    }
+    print_table(&table)?;
+
+    Ok(())
+}
+
+#[instrument(err, ret(level = "debug"))]
+async fn doctor_impl(timeout: u64) -> Result<()> {
+    app::doctor(Duration::from_secs(timeout)).await?;
+
+    Ok(())
+}
+
 /* end impls */
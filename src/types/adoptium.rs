@@ -0,0 +1,195 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::str::FromStr;
+
+use color_eyre::eyre::{eyre, Report, Result};
+use derive_more::derive::Display;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+/// The Java feature releases served by Adoptium, as returned by `/v3/info/available_releases`
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct AvailableReleases {
+    pub available_lts_releases: Vec<u8>,
+    pub available_releases: Vec<u8>,
+    pub most_recent_lts: u8,
+}
+
+impl AvailableReleases {
+    /// Returns an error listing the available versions if `major_version` is not served
+    pub fn check(&self, major_version: u8, vendor: &str) -> Result<()> {
+        if self.available_releases.contains(&major_version) {
+            return Ok(());
+        }
+
+        Err(eyre!(
+            "Java {major_version} is not available from {vendor} (available: {}; LTS: {})",
+            self.available_releases.iter().join(", "),
+            self.available_lts_releases.iter().join(", "),
+        ))
+    }
+}
+
+/// A build served by Adoptium, as returned by `/v3/assets/latest/{feature_version}/{jvm_impl}`
+///
+/// Only the fields needed to tell which platforms a Java version is available for are kept
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Asset {
+    pub binary: AssetBinary,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct AssetBinary {
+    pub os: String,
+    pub architecture: String,
+    pub image_type: String,
+}
+
+/// Groups `assets` by platform, with the image types (jre, jdk, ...) served for each
+pub(crate) fn platform_images(assets: &[Asset]) -> BTreeMap<Platform, BTreeSet<&str>> {
+    let mut platforms: BTreeMap<Platform, BTreeSet<&str>> = BTreeMap::new();
+    for asset in assets {
+        let platform = Platform {
+            os: asset.binary.os.clone(),
+            arch: asset.binary.architecture.clone(),
+        };
+        platforms
+            .entry(platform)
+            .or_default()
+            .insert(&asset.binary.image_type);
+    }
+
+    platforms
+}
+
+/// An operating system and architecture to download a JRE for, using Adoptium's names
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Display)]
+#[display("{os}-{arch}")]
+pub(crate) struct Platform {
+    pub os: String,
+    pub arch: String,
+}
+
+impl Platform {
+    /// The platform mcdl is running on
+    pub fn native() -> Self {
+        Self {
+            os: match std::env::consts::OS {
+                "macos" => "mac",
+                os => os,
+            }
+            .to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+        }
+    }
+
+    /// The desktop and server platforms most players are on
+    pub fn all() -> Vec<Self> {
+        [
+            ("linux", "x64"),
+            ("linux", "aarch64"),
+            ("mac", "x64"),
+            ("mac", "aarch64"),
+            ("windows", "x64"),
+        ]
+        .into_iter()
+        .map(|(os, arch)| Self {
+            os: os.to_string(),
+            arch: arch.to_string(),
+        })
+        .collect()
+    }
+
+    /// The file extension of JRE archives for this platform
+    pub fn archive_extension(&self) -> &'static str {
+        match self.os.as_str() {
+            "windows" => "zip",
+            _ => "tar.gz",
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // the OS may contain a dash (e.g. `alpine-linux`), the architecture never does
+        match s.rsplit_once('-') {
+            Some((os, arch)) if !os.is_empty() && !arch.is_empty() => Ok(Self {
+                os: os.to_string(),
+                arch: arch.to_string(),
+            }),
+            _ => Err(eyre!(
+                "Invalid platform `{s}` (expected <os>-<arch>, e.g. linux-x64)"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_platform() {
+        let platform: Platform = "alpine-linux-x64".parse().unwrap();
+        assert_eq!(platform.os, "alpine-linux");
+        assert_eq!(platform.arch, "x64");
+        assert_eq!(platform.to_string(), "alpine-linux-x64");
+
+        assert_eq!(
+            "windows-x64"
+                .parse::<Platform>()
+                .unwrap()
+                .archive_extension(),
+            "zip"
+        );
+        assert!("linux".parse::<Platform>().is_err());
+        assert!("linux-".parse::<Platform>().is_err());
+    }
+
+    #[test]
+    fn check_available_releases() {
+        let releases: AvailableReleases = serde_json::from_str(
+            r#"{
+                "available_lts_releases": [8, 11, 17, 21],
+                "available_releases": [8, 11, 16, 17, 18, 21, 22],
+                "most_recent_feature_release": 22,
+                "most_recent_feature_version": 23,
+                "most_recent_lts": 21,
+                "tip_version": 24
+            }"#,
+        )
+        .unwrap();
+
+        assert!(releases.check(17, "eclipse").is_ok());
+
+        let err = releases.check(9, "eclipse").unwrap_err().to_string();
+        assert!(
+            err.starts_with("Java 9 is not available from eclipse"),
+            "{err}"
+        );
+        assert!(err.contains("8, 11, 16, 17, 18, 21, 22"), "{err}");
+    }
+
+    #[test]
+    fn group_platform_images() {
+        let assets: Vec<Asset> = serde_json::from_str(
+            r#"[
+                {"binary": {"architecture": "x64", "os": "linux", "image_type": "jre", "jvm_impl": "hotspot"}, "vendor": "eclipse"},
+                {"binary": {"architecture": "x64", "os": "linux", "image_type": "jdk", "jvm_impl": "hotspot"}, "vendor": "eclipse"},
+                {"binary": {"architecture": "aarch64", "os": "mac", "image_type": "jdk", "jvm_impl": "hotspot"}, "vendor": "eclipse"},
+                {"binary": {"architecture": "x64", "os": "linux", "image_type": "jre", "jvm_impl": "hotspot"}, "vendor": "eclipse"}
+            ]"#,
+        )
+        .unwrap();
+
+        let platforms = platform_images(&assets);
+        assert_eq!(
+            platforms
+                .iter()
+                .map(|(platform, images)| format!("{platform}: {}", images.iter().join(",")))
+                .collect_vec(),
+            ["linux-x64: jdk,jre", "mac-aarch64: jdk"]
+        );
+    }
+}
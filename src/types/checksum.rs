@@ -0,0 +1,134 @@
+use std::str::FromStr;
+
+use color_eyre::eyre::{eyre, Report, Result};
+use derive_more::derive::Display;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use tracing::{debug, instrument};
+
+/// A hash algorithm used to verify downloads
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display)]
+pub(crate) enum ChecksumAlgo {
+    /// Used by Mojang's version metadata
+    #[display("sha1")]
+    Sha1,
+    /// Used by Adoptium and most mirrors
+    #[display("sha256")]
+    Sha256,
+}
+
+impl ChecksumAlgo {
+    /// The length of a digest, in hex characters
+    fn hex_len(&self) -> usize {
+        match self {
+            ChecksumAlgo::Sha1 => 40,
+            ChecksumAlgo::Sha256 => 64,
+        }
+    }
+
+    fn digest(&self, bytes: &[u8]) -> String {
+        match self {
+            ChecksumAlgo::Sha1 => hex::encode(Sha1::digest(bytes)),
+            ChecksumAlgo::Sha256 => hex::encode(Sha256::digest(bytes)),
+        }
+    }
+}
+
+impl FromStr for ChecksumAlgo {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('-', "").as_str() {
+            "sha1" => Ok(ChecksumAlgo::Sha1),
+            "sha256" => Ok(ChecksumAlgo::Sha256),
+            _ => Err(eyre!(
+                "Unsupported checksum algorithm `{s}` (expected sha1 or sha256)"
+            )),
+        }
+    }
+}
+
+/// The expected checksum of a download, written as `<algo>:<hex>`
+#[derive(Clone, Debug, PartialEq, Eq, Display)]
+#[display("{algo}:{value}")]
+pub(crate) struct Checksum {
+    pub algo: ChecksumAlgo,
+    /// The lowercase hex digest
+    pub value: String,
+}
+
+impl Checksum {
+    pub fn new(algo: ChecksumAlgo, value: &str) -> Result<Self> {
+        let value = value.to_ascii_lowercase();
+        if value.len() != algo.hex_len() || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(eyre!(
+                "Invalid {algo} checksum `{value}` (expected {} hex characters)",
+                algo.hex_len()
+            ));
+        }
+
+        Ok(Self { algo, value })
+    }
+}
+
+impl FromStr for Checksum {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (algo, value) = s
+            .split_once(':')
+            .ok_or_else(|| eyre!("Invalid checksum `{s}` (expected <algo>:<hex>)"))?;
+
+        Self::new(algo.parse()?, value)
+    }
+}
+
+/// Checks that `bytes` match the `expected` checksum
+#[instrument(err, skip(bytes), fields(len = bytes.len(), %expected))]
+pub(crate) fn verify(bytes: &[u8], expected: &Checksum) -> Result<()> {
+    let actual = expected.algo.digest(bytes);
+    if actual != expected.value {
+        return Err(eyre!(
+            "Checksum mismatch (expected {expected}, got {}:{actual})",
+            expected.algo
+        ));
+    }
+
+    debug!("Checksum verified");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_checksums() {
+        let sha1: Checksum = "sha1:aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d"
+            .parse()
+            .unwrap();
+        let sha256: Checksum =
+            "SHA-256:2CF24DBA5FB0A30E26E83B2AC5B9E29E1B161E5C1FA7425E73043362938B9824"
+                .parse()
+                .unwrap();
+
+        assert!(verify(b"hello", &sha1).is_ok());
+        assert!(verify(b"hello", &sha256).is_ok());
+
+        let err = verify(b"goodbye", &sha1).unwrap_err();
+        assert!(err.to_string().starts_with("Checksum mismatch"), "{err}");
+    }
+
+    #[test]
+    fn parse_checksum_invalid() {
+        assert!("aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d"
+            .parse::<Checksum>()
+            .is_err());
+        assert!("md5:5d41402abc4b2a76b9719d911017c592"
+            .parse::<Checksum>()
+            .is_err());
+        assert!("sha256:aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d"
+            .parse::<Checksum>()
+            .is_err());
+    }
+}
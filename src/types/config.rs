@@ -0,0 +1,337 @@
+use std::fmt::Debug;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use derive_more::derive::Display;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument};
+
+use crate::types::meta::{AsArgs, DEFAULT_JVM_ARGS};
+
+/// When to use colored output
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum, Display,
+)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub(crate) enum ColorChoice {
+    /// Use color if stdout is a terminal and `NO_COLOR` is not set
+    #[default]
+    #[display("auto")]
+    Auto,
+    /// Always use color
+    #[display("always")]
+    Always,
+    /// Never use color
+    #[display("never")]
+    Never,
+}
+
+impl ColorChoice {
+    pub fn should_colorize(&self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => std::env::var_os("NO_COLOR").is_none() && stdout_is_terminal(),
+        }
+    }
+}
+
+/// How to display progress for long-running operations
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum, Display,
+)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub(crate) enum ProgressMode {
+    /// Animate progress if stdout is a terminal, otherwise behave like `plain`
+    #[default]
+    #[display("auto")]
+    Auto,
+    /// Print each status update on its own line, without animation
+    #[display("plain")]
+    Plain,
+    /// Don't display progress
+    #[value(name = "none")]
+    #[serde(rename = "none")]
+    #[display("none")]
+    Hidden,
+}
+
+impl ProgressMode {
+    /// Resolves `Auto` to `Plain` if stdout is not a terminal
+    ///
+    /// After resolving, `Auto` means progress should be animated
+    pub fn resolve(self) -> Self {
+        match self {
+            ProgressMode::Auto if !stdout_is_terminal() => ProgressMode::Plain,
+            mode => mode,
+        }
+    }
+}
+
+/// Whether stdout is a terminal, used to pick defaults for interactive output
+fn stdout_is_terminal() -> bool {
+    std::io::stdout().is_terminal()
+}
+
+/// Global defaults, read from `config.toml` in the config directory
+///
+/// Values are resolved in the following order, from lowest to highest priority:
+/// built-in defaults, the config file, `MCDL_*` environment variables, CLI flags
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub(crate) struct Config {
+    /// When to use colored output
+    pub color: ColorChoice,
+    /// How to display progress for long-running operations
+    pub progress: ProgressMode,
+    /// The arguments to pass to the JVM for newly installed instances
+    pub java_args: Vec<String>,
+    /// The vendor to download JREs from
+    pub jre_vendor: String,
+    /// How long cached API responses are kept, in seconds
+    pub cache_ttl: u64,
+    /// The maximum number of versions to install at the same time
+    pub concurrency: usize,
+    /// Whether the Minecraft EULA has been accepted for all future installs
+    ///
+    /// See <https://aka.ms/MinecraftEULA>
+    pub accept_eula: bool,
+    /// A shell command to run in the instance directory after each successful install
+    pub after_install: Option<String>,
+    /// Whether to refuse versions that aren't releases, pre-releases or snapshots
+    ///
+    /// A safety gate for automation, e.g. against installing an April Fools version by accident
+    pub strict: bool,
+    /// A local copy of the version manifest to use instead of fetching it, for offline installs
+    pub manifest_file: Option<PathBuf>,
+    /// Where to fetch the version manifest from instead of Mojang, e.g. a private mirror
+    ///
+    /// Can't be set together with `manifest_file`
+    pub manifest_url: Option<String>,
+    /// Whether to neither read nor write cached responses. Only set with `--no-cache`
+    #[serde(skip)]
+    pub no_cache: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            color: ColorChoice::default(),
+            progress: ProgressMode::default(),
+            java_args: DEFAULT_JVM_ARGS.as_args(),
+            jre_vendor: "eclipse".to_string(),
+            cache_ttl: 60 * 10, // 10 minutes
+            concurrency: 4,
+            accept_eula: false,
+            after_install: None,
+            strict: false,
+            manifest_file: None,
+            manifest_url: None,
+            no_cache: false,
+        }
+    }
+}
+
+impl Config {
+    /// Parses a config from a TOML string, reporting the offending key on error
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        let config: Self = serde_path_to_error::deserialize(toml::Deserializer::new(contents))
+            .map_err(|e| match e.path().to_string().as_str() {
+                "." => eyre!("{}", e.inner()),
+                key => eyre!("Invalid value for `{key}`: {}", e.inner()),
+            })?;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    #[instrument(err)]
+    pub fn from_file<P: AsRef<Path> + Debug>(path: P) -> Result<Self> {
+        debug!("Reading config");
+
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .wrap_err(format!("Error reading config at {}", path.display()))?;
+
+        Self::from_toml(&contents).wrap_err(format!("Error parsing config at {}", path.display()))
+    }
+
+    /// Reads the config at `path` if it exists, then applies environment overrides
+    #[instrument(err)]
+    pub fn load<P: AsRef<Path> + Debug>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let mut config = if path.exists() {
+            Self::from_file(path)?
+        } else {
+            debug!("Config not found, using defaults");
+            Self::default()
+        };
+
+        config.apply_env()?;
+        debug!(?config, "Loaded config");
+        Ok(config)
+    }
+
+    #[instrument(err, ret(level = "debug"), skip(self))]
+    pub fn save<P: AsRef<Path> + Debug>(&self, path: P) -> Result<()> {
+        debug!("Saving config");
+
+        let path = path.as_ref();
+        std::fs::create_dir_all(path.parent().expect("infallible"))?;
+
+        let contents = format!(
+            "# mcdl global configuration\n# Settings here can be overridden by MCDL_* environment variables and CLI flags\n\n{}",
+            self.to_toml()?
+        );
+
+        std::fs::write(path, contents).wrap_err(format!(
+            "Error writing config to file at {}",
+            path.display()
+        ))?;
+
+        Ok(())
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        Ok(toml::to_string(self)?)
+    }
+
+    /// Overrides values with `MCDL_<KEY>` environment variables, if set
+    fn apply_env(&mut self) -> Result<()> {
+        fn var(key: &str) -> Option<(String, String)> {
+            let name = format!("MCDL_{}", key.to_ascii_uppercase());
+            std::env::var(&name).ok().map(|value| (name, value))
+        }
+
+        if let Some((name, value)) = var("color") {
+            self.color = ColorChoice::from_str(&value, true)
+                .map_err(|e| eyre!("Invalid value for {name}: {e}"))?;
+        }
+        if let Some((name, value)) = var("progress") {
+            self.progress = ProgressMode::from_str(&value, true)
+                .map_err(|e| eyre!("Invalid value for {name}: {e}"))?;
+        }
+        if let Some((_, value)) = var("java_args") {
+            self.java_args = value.split_whitespace().map(String::from).collect();
+        }
+        if let Some((_, value)) = var("jre_vendor") {
+            self.jre_vendor = value;
+        }
+        if let Some((name, value)) = var("cache_ttl") {
+            self.cache_ttl = value
+                .parse()
+                .wrap_err(format!("Invalid value for {name}"))?;
+        }
+        if let Some((name, value)) = var("concurrency") {
+            self.concurrency = value
+                .parse()
+                .wrap_err(format!("Invalid value for {name}"))?;
+        }
+        if let Some((name, value)) = var("accept_eula") {
+            self.accept_eula = value
+                .parse()
+                .wrap_err(format!("Invalid value for {name}"))?;
+        }
+
+        if let Some((_, value)) = var("after_install") {
+            self.after_install = Some(value).filter(|v| !v.is_empty());
+        }
+        if let Some((name, value)) = var("strict") {
+            self.strict = value
+                .parse()
+                .wrap_err(format!("Invalid value for {name}"))?;
+        }
+        if let Some((_, value)) = var("manifest_file") {
+            self.manifest_file = Some(PathBuf::from(value)).filter(|v| !v.as_os_str().is_empty());
+        }
+        if let Some((_, value)) = var("manifest_url") {
+            self.manifest_url = Some(value).filter(|v| !v.is_empty());
+        }
+
+        self.validate()
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.concurrency == 0 {
+            return Err(eyre!("Invalid value for `concurrency`: must be at least 1"));
+        }
+        if self.jre_vendor.is_empty() {
+            return Err(eyre!("Invalid value for `jre_vendor`: must not be empty"));
+        }
+        if let Some(url) = &self.manifest_url {
+            validate_manifest_url(url).wrap_err("Invalid value for `manifest_url`")?;
+        }
+        // otherwise one would be silently ignored
+        if self.manifest_file.is_some() && self.manifest_url.is_some() {
+            return Err(eyre!(
+                "`manifest_file` and `manifest_url` can't both be set; remove one of them"
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that `url` is an HTTP(S) URL the manifest can be fetched from
+pub(crate) fn validate_manifest_url(url: &str) -> Result<()> {
+    let parsed = reqwest::Url::parse(url).wrap_err(format!("`{url}` is not a valid URL"))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(eyre!(
+            "`{url}` must be an http or https URL; use `manifest_file` for local files"
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_partial_config() {
+        let config =
+            Config::from_toml("cache_ttl = 60\ncolor = \"never\"\nprogress = \"none\"").unwrap();
+        assert_eq!(config.cache_ttl, 60);
+        assert_eq!(config.color, ColorChoice::Never);
+        assert_eq!(config.progress, ProgressMode::Hidden);
+        assert_eq!(config.jre_vendor, Config::default().jre_vendor);
+        assert!(!config.accept_eula);
+    }
+
+    #[test]
+    fn parse_config_round_trip() {
+        let config = Config::from_toml(&Config::default().to_toml().unwrap()).unwrap();
+        assert_eq!(config.java_args, Config::default().java_args);
+    }
+
+    #[test]
+    fn parse_config_invalid_value() {
+        let err = Config::from_toml("concurrency = \"lots\"").unwrap_err();
+        assert!(err.to_string().contains("`concurrency`"), "{err}");
+
+        let err = Config::from_toml("concurrency = 0").unwrap_err();
+        assert!(err.to_string().contains("`concurrency`"), "{err}");
+
+        let err = Config::from_toml("manifest_url = \"file:///tmp/manifest.json\"").unwrap_err();
+        assert!(err.to_string().contains("`manifest_url`"), "{err}");
+        let config = Config::from_toml("manifest_url = \"https://mirror.example/manifest.json\"");
+        assert!(config.is_ok());
+        let err = Config::from_toml(
+            "manifest_url = \"https://mirror.example/manifest.json\"\nmanifest_file = \"manifest.json\"",
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("can't both be set"), "{err}");
+    }
+
+    #[test]
+    fn parse_config_unknown_key() {
+        let err = Config::from_toml("colour = \"never\"").unwrap_err();
+        assert!(err.to_string().contains("unknown field `colour`"), "{err}");
+    }
+}
@@ -0,0 +1,158 @@
+use std::fmt::Debug;
+use std::path::Path;
+use std::str::FromStr;
+
+use color_eyre::eyre::{eyre, Report, Result, WrapErr};
+use tracing::{debug, instrument};
+
+/// A dotenv-style file of `KEY=VALUE` lines
+///
+/// Blank lines and lines starting with `#` are ignored, and a leading `export` is allowed.
+/// Values can be single-quoted (taken literally) or double-quoted (with `\n`, `\t`, `\"` and
+/// `\\` escapes). Unquoted values end at a ` #` comment
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct EnvFile {
+    /// The variables, in the order they appear
+    pub vars: Vec<(String, String)>,
+}
+
+impl EnvFile {
+    #[instrument(err)]
+    pub fn from_file<P: AsRef<Path> + Debug>(path: P) -> Result<Self> {
+        debug!("Reading env file");
+
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .wrap_err(format!("Error reading env file at {}", path.display()))?;
+
+        contents
+            .parse()
+            .wrap_err(format!("Error parsing env file at {}", path.display()))
+    }
+}
+
+impl FromStr for EnvFile {
+    type Err = Report;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let vars = s
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| {
+                let line = line.trim();
+                !line.is_empty() && !line.starts_with('#')
+            })
+            .map(|(i, line)| parse_line(line).wrap_err(format!("Line {}", i + 1)))
+            .collect::<Result<_>>()?;
+
+        Ok(Self { vars })
+    }
+}
+
+fn parse_line(line: &str) -> Result<(String, String)> {
+    let line = line.trim();
+    let line = line.strip_prefix("export ").unwrap_or(line);
+    let (key, value) = line
+        .split_once('=')
+        .ok_or_else(|| eyre!("Expected KEY=VALUE, got `{line}`"))?;
+
+    let key = key.trim();
+    let valid_key = key.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_key {
+        return Err(eyre!("Invalid variable name `{key}`"));
+    }
+
+    let value = value.trim_start();
+    let (value, rest) = match value.chars().next() {
+        Some('\'') => {
+            let (value, rest) = value[1..]
+                .split_once('\'')
+                .ok_or_else(|| eyre!("Unterminated single quote"))?;
+            (value.to_string(), rest)
+        }
+        Some('"') => parse_double_quoted(&value[1..])?,
+        _ => {
+            let value = match value.find(" #") {
+                Some(comment) => &value[..comment],
+                None => value,
+            };
+            (value.trim_end().to_string(), "")
+        }
+    };
+
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(eyre!("Unexpected `{rest}` after closing quote"));
+    }
+
+    Ok((key.to_string(), value))
+}
+
+/// Parses a double-quoted value after its opening quote, returning it and what follows it
+fn parse_double_quoted(s: &str) -> Result<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((value, &s[i + 1..])),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, c @ ('"' | '\\'))) => value.push(c),
+                Some((_, c)) => return Err(eyre!("Unknown escape `\\{c}`")),
+                None => break,
+            },
+            c => value.push(c),
+        }
+    }
+
+    Err(eyre!("Unterminated double quote"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_env_file() {
+        let file: EnvFile = "\
+# secrets
+RCON_PASSWORD=hunter2
+export TZ = UTC # inline comment
+
+SINGLE='literal \\n # not a comment'
+DOUBLE=\"line\\none \\\"quoted\\\"\" # comment
+EMPTY=
+"
+        .parse()
+        .unwrap();
+
+        assert_eq!(
+            file.vars,
+            [
+                ("RCON_PASSWORD", "hunter2"),
+                ("TZ", "UTC"),
+                ("SINGLE", "literal \\n # not a comment"),
+                ("DOUBLE", "line\none \"quoted\""),
+                ("EMPTY", ""),
+            ]
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_env_file_invalid() {
+        for (contents, expected) in [
+            ("A=1\nnot a variable", "Line 2"),
+            ("1A=1", "Invalid variable name `1A`"),
+            ("A=\"unterminated", "Unterminated double quote"),
+            ("A='unterminated", "Unterminated single quote"),
+            ("A=\"a\" b", "Unexpected `b`"),
+            ("A=\"\\x\"", "Unknown escape `\\x`"),
+        ] {
+            let err = contents.parse::<EnvFile>().unwrap_err();
+            assert!(format!("{err:#}").contains(expected), "{err:#}");
+        }
+    }
+}
@@ -0,0 +1,232 @@
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use color_eyre::eyre::{eyre, Report, Result};
+use derive_more::derive::Display;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// The format to store server mappings in
+///
+/// Mojang publishes mappings in ProGuard format, which is always kept. Other formats are converted
+/// from it and written alongside
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Display)]
+pub(crate) enum MappingsFormat {
+    /// ProGuard, as published by Mojang (`server_mappings.txt`)
+    #[display("proguard")]
+    Proguard,
+    /// Tiny v2, as used by Fabric tooling (`server_mappings.tiny`)
+    #[display("tiny")]
+    Tiny,
+}
+
+/// A field or method, with its descriptor in terms of the named classes
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct MemberMapping {
+    pub named: String,
+    pub obfuscated: String,
+    pub descriptor: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct ClassMapping {
+    pub named: String,
+    pub obfuscated: String,
+    pub fields: Vec<MemberMapping>,
+    pub methods: Vec<MemberMapping>,
+}
+
+/// Mappings between Mojang's names and the obfuscated names in the server jar
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Mappings {
+    pub classes: Vec<ClassMapping>,
+}
+
+impl Mappings {
+    /// Converts to Tiny v2, with the `named` namespace first and `official` (obfuscated) second
+    pub fn to_tiny_v2(&self) -> String {
+        let mut out = String::from("tiny\t2\t0\tnamed\tofficial\n");
+
+        for class in &self.classes {
+            out.push_str(&format!(
+                "c\t{}\t{}\n",
+                internal_name(&class.named),
+                internal_name(&class.obfuscated)
+            ));
+            for field in &class.fields {
+                out.push_str(&format!(
+                    "\tf\t{}\t{}\t{}\n",
+                    field.descriptor, field.named, field.obfuscated
+                ));
+            }
+            for method in &class.methods {
+                out.push_str(&format!(
+                    "\tm\t{}\t{}\t{}\n",
+                    method.descriptor, method.named, method.obfuscated
+                ));
+            }
+        }
+
+        out
+    }
+}
+
+impl FromStr for Mappings {
+    type Err = Report;
+
+    /// Parses ProGuard mappings, e.g.
+    ///
+    /// ```text
+    /// net.minecraft.server.Main -> net.minecraft.server.Main:
+    ///     java.lang.String[] args -> a
+    ///     12:34:void main(java.lang.String[]) -> main
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        lazy_static! {
+            static ref CLASS: Regex = Regex::new(r"^(\S+) -> (\S+):$").unwrap();
+            static ref FIELD: Regex = Regex::new(r"^(\S+) (\S+) -> (\S+)$").unwrap();
+            // line numbers before the return type and after the arguments are optional
+            static ref METHOD: Regex = Regex::new(
+                r"^(?:\d+:\d+:)?(\S+) ([^\s(]+)\(([^)]*)\)(?::\d+(?::\d+)?)? -> (\S+)$"
+            )
+            .unwrap();
+        }
+
+        let mut classes: Vec<ClassMapping> = vec![];
+        for (i, line) in s.lines().enumerate() {
+            let err = || eyre!("Invalid mappings on line {}: `{line}`", i + 1);
+
+            if line.trim().is_empty() || line.trim_start().starts_with('#') {
+                continue;
+            }
+
+            if !line.starts_with(char::is_whitespace) {
+                let caps = CLASS.captures(line).ok_or_else(err)?;
+                classes.push(ClassMapping {
+                    named: caps[1].to_string(),
+                    obfuscated: caps[2].to_string(),
+                    fields: vec![],
+                    methods: vec![],
+                });
+                continue;
+            }
+
+            let class = classes.last_mut().ok_or_else(err)?;
+            let line = line.trim();
+            if let Some(caps) = METHOD.captures(line) {
+                let args = caps[3]
+                    .split(',')
+                    .filter(|a| !a.is_empty())
+                    .map(type_descriptor)
+                    .collect::<Option<String>>()
+                    .ok_or_else(err)?;
+                let method = MemberMapping {
+                    named: caps[2].to_string(),
+                    obfuscated: caps[4].to_string(),
+                    descriptor: format!("({args}){}", type_descriptor(&caps[1]).ok_or_else(err)?),
+                };
+
+                // methods are listed once per inlined line range
+                if !class.methods.contains(&method) {
+                    class.methods.push(method);
+                }
+            } else if let Some(caps) = FIELD.captures(line) {
+                class.fields.push(MemberMapping {
+                    named: caps[2].to_string(),
+                    obfuscated: caps[3].to_string(),
+                    descriptor: type_descriptor(&caps[1]).ok_or_else(err)?,
+                });
+            } else {
+                return Err(err());
+            }
+        }
+
+        if classes.is_empty() {
+            return Err(eyre!("Mappings contain no classes"));
+        }
+
+        Ok(Self { classes })
+    }
+}
+
+/// Converts a class name like `a.b.C` to its internal form, `a/b/C`
+fn internal_name(name: &str) -> String {
+    name.replace('.', "/")
+}
+
+/// Converts a Java type like `int[]` or `java.lang.String` to a descriptor like `[I` or
+/// `Ljava/lang/String;`
+fn type_descriptor(java_type: &str) -> Option<String> {
+    let element = java_type.trim_end_matches("[]");
+    let dimensions = (java_type.len() - element.len()) / 2;
+
+    let element = match element {
+        "" => return None,
+        "boolean" => "Z".to_string(),
+        "byte" => "B".to_string(),
+        "char" => "C".to_string(),
+        "short" => "S".to_string(),
+        "int" => "I".to_string(),
+        "long" => "J".to_string(),
+        "float" => "F".to_string(),
+        "double" => "D".to_string(),
+        "void" if dimensions == 0 => "V".to_string(),
+        "void" => return None,
+        class => format!("L{};", internal_name(class)),
+    };
+
+    Some("[".repeat(dimensions) + &element)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROGUARD: &str = "\
+# {\"fileName\":\"Main.java\",\"id\":\"sourceFile\"}
+net.minecraft.server.Main -> net.minecraft.server.Main:
+    org.slf4j.Logger LOGGER -> a
+    int[][] grid -> b
+    1:1:void <init>() -> <init>
+    12:34:void main(java.lang.String[]) -> main
+    40:41:void main(java.lang.String[]) -> main
+    boolean isOk(int,net.minecraft.world.Level):50:51 -> a
+net.minecraft.world.Level -> cmm:
+";
+
+    #[test]
+    fn parse_proguard() {
+        let mappings: Mappings = PROGUARD.parse().unwrap();
+        assert_eq!(mappings.classes.len(), 2);
+
+        let main = &mappings.classes[0];
+        assert_eq!(main.fields[0].descriptor, "Lorg/slf4j/Logger;");
+        assert_eq!(main.fields[1].descriptor, "[[I");
+        assert_eq!(main.methods.len(), 3, "{:?}", main.methods);
+        assert_eq!(main.methods[1].descriptor, "([Ljava/lang/String;)V");
+        assert_eq!(
+            main.methods[2].descriptor,
+            "(ILnet/minecraft/world/Level;)Z"
+        );
+        assert_eq!(mappings.classes[1].obfuscated, "cmm");
+    }
+
+    #[test]
+    fn parse_proguard_invalid() {
+        assert!("".parse::<Mappings>().is_err());
+        assert!("    int a -> b".parse::<Mappings>().is_err());
+        assert!("a.B -> c:\n    nonsense".parse::<Mappings>().is_err());
+        assert!("a.B -> c:\n    void[] a -> b".parse::<Mappings>().is_err());
+    }
+
+    #[test]
+    fn convert_to_tiny() {
+        let mappings: Mappings = PROGUARD.parse().unwrap();
+        let tiny = mappings.to_tiny_v2();
+
+        assert!(tiny.starts_with("tiny\t2\t0\tnamed\tofficial\nc\tnet/minecraft/server/Main\tnet/minecraft/server/Main\n"));
+        assert!(tiny.contains("\n\tf\tLorg/slf4j/Logger;\tLOGGER\ta\n"));
+        assert!(tiny.contains("\n\tm\t([Ljava/lang/String;)V\tmain\tmain\n"));
+        assert!(tiny.ends_with("\nc\tnet/minecraft/world/Level\tcmm\n"));
+    }
+}
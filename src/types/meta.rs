@@ -1,23 +1,35 @@
 use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt::Debug;
-use std::path::{Path, PathBuf};
-
-use color_eyre::eyre::{Result, WrapErr};
+use std::net::IpAddr;
+use std::ops::{Deref, DerefMut};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use fs2::FileExt;
 use itertools::Itertools;
 use lazy_static::lazy_static;
+use parking_lot::{Mutex, MutexGuard};
 use serde::{Deserialize, Serialize};
 use tokio::fs;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tracing::{debug, instrument};
+use tracing::{debug, info, instrument, warn};
 
-use crate::types::version::VersionNumber;
+use crate::common::{CONFIG, MCDL_VERSION};
+use crate::types::version::{VersionDownload, VersionNumber};
+use crate::utils::fs::temp_path;
 
 lazy_static! {
-    static ref DEFAULT_JVM_ARGS: Vec<String> = vec!["-Xms4G".to_string(), "-Xmx4G".to_string()];
+    pub(crate) static ref DEFAULT_JVM_ARGS: Vec<String> =
+        vec!["-Xms4G".to_string(), "-Xmx4G".to_string()];
     static ref DEFAULT_SERVER_ARGS: Vec<String> = vec!["--nogui".to_string()];
 }
 
+/// The JVM flag that lets an `ipv6` server accept IPv6 connections
+pub(crate) const PREFER_IPV6_ARG: &str = "-Djava.net.preferIPv4Stack=false";
+
 pub(crate) trait AsArgs {
     fn as_args(&self) -> Vec<String>;
 
@@ -39,31 +51,103 @@ where T: Clone + Into<Vec<String>>
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub(crate) struct InstanceJavaSettings {
     /// The major version of the JVM to use
     ///
     /// Will be either 8, 16, or 17 by default,
-    /// depending on the server version
+    /// depending on the server version.
+    /// With `system`, this is the minimum version the system Java must have
     pub version: u8,
     /// The arguments to pass to the JVM
     pub args: Vec<String>,
+    /// Whether the JRE is stored in the instance directory
+    /// instead of being shared with other instances
+    #[serde(default)]
+    pub bundled: bool,
+    /// Whether to use the Java installed on the system, found through `JAVA_HOME` or `PATH`,
+    /// instead of a JRE downloaded by mcdl
+    #[serde(default)]
+    pub system: bool,
 }
 
 impl InstanceJavaSettings {
     pub fn new(version: u8) -> Self {
         Self {
             version,
-            args: DEFAULT_JVM_ARGS.as_args(),
+            args: CONFIG.read().java_args.clone(),
+            bundled: false,
+            system: false,
         }
     }
+
+    /// Sets the initial and maximum heap size (`-Xms` and `-Xmx`), e.g. `4G`
+    pub fn set_memory(&mut self, memory: &str) {
+        self.args
+            .retain(|arg| !arg.starts_with("-Xms") && !arg.starts_with("-Xmx"));
+        self.args.insert(0, format!("-Xmx{memory}"));
+        self.args.insert(0, format!("-Xms{memory}"));
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub(crate) struct InstanceServerSettings {
     /// The path to the server jar file, relative to the instance directory
     pub jar: PathBuf,
     /// The arguments to pass to the server
     pub args: Vec<String>,
+    /// The address the server listens on, written to `server-ip` in `server.properties`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bind: Option<IpAddr>,
+    /// Whether the server accepts IPv6 connections as well as IPv4
+    ///
+    /// Java is started with [`PREFER_IPV6_ARG`], and without `bind`, `server-ip` is left empty
+    /// so the server listens on every address
+    #[serde(default)]
+    pub ipv6: bool,
+}
+
+impl InstanceServerSettings {
+    /// The `server-ip` these settings need in `server.properties`, if any
+    pub fn server_ip(&self) -> Option<String> {
+        match (self.bind, self.ipv6) {
+            (Some(bind), _) => Some(bind.to_string()),
+            (None, true) => Some(String::new()),
+            (None, false) => None,
+        }
+    }
+
+    /// Checks that `jar` is a relative path inside the instance directory
+    ///
+    /// Settings files can be copied from elsewhere, so they must not be able to make `run`
+    /// execute an arbitrary file
+    pub fn validate(&self) -> Result<()> {
+        let mut depth = 0usize;
+        let inside = self.jar.components().all(|component| match component {
+            Component::Normal(_) => {
+                depth += 1;
+                true
+            }
+            Component::CurDir => true,
+            Component::ParentDir if depth > 0 => {
+                depth -= 1;
+                true
+            }
+            // absolute, or escaping the instance directory through `..`
+            _ => false,
+        });
+
+        // `depth` is 0 for the instance directory itself
+        if !inside || depth == 0 {
+            return Err(eyre!(
+                "Server jar `{}` must be a relative path inside the instance directory",
+                self.jar.display()
+            ));
+        }
+
+        Ok(())
+    }
 }
 
 impl Default for InstanceServerSettings {
@@ -71,16 +155,24 @@ impl Default for InstanceServerSettings {
         Self {
             jar: PathBuf::from("server.jar"),
             args: DEFAULT_SERVER_ARGS.as_args(),
+            bind: None,
+            ipv6: false,
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub(crate) struct InstanceSettings {
     /// The settings for the JVM
     pub java: InstanceJavaSettings,
     /// The settings relating to the server
     pub server: InstanceServerSettings,
+    /// Environment variables to set for the server, on top of those mcdl was started with
+    ///
+    /// Variables given with `mcdl run --env` take precedence
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
 }
 
 impl InstanceSettings {
@@ -88,6 +180,7 @@ impl InstanceSettings {
         Self {
             java: InstanceJavaSettings::new(version),
             server: InstanceServerSettings::default(),
+            env: BTreeMap::new(),
         }
     }
 
@@ -107,6 +200,10 @@ impl InstanceSettings {
 
         let settings: Self = toml::from_str(&contents)
             .wrap_err(format!("Error parsing settings at {}", path.display()))?;
+        settings
+            .server
+            .validate()
+            .wrap_err(format!("Invalid settings at {}", path.display()))?;
 
         Ok(settings)
     }
@@ -135,13 +232,129 @@ impl InstanceSettings {
         debug!(settings = ?self, "Saved instance settings");
         Ok(())
     }
+
+    /// Points arguments and environment variables that mention the instance directory `from` at
+    /// `to` instead, e.g. `-Dlog4j.configurationFile=<dir>/log4j2.xml`
+    ///
+    /// Only whole paths are replaced, so a sibling like `<dir>-old` is left alone
+    pub fn replace_paths(&mut self, from: &Path, to: &Path) {
+        let (from, to) = (from.to_string_lossy(), to.to_string_lossy());
+        let args = self.java.args.iter_mut().chain(&mut self.server.args);
+        for value in args.chain(self.env.values_mut()) {
+            let mut replaced = String::with_capacity(value.len());
+            let mut rest = value.as_str();
+            while let Some(start) = rest.find(from.as_ref()) {
+                let end = start + from.len();
+                let whole =
+                    !rest[end..].starts_with(|c: char| c.is_alphanumeric() || "-_.".contains(c));
+                replaced.push_str(&rest[..start]);
+                replaced.push_str(if whole { &to } else { &from });
+                rest = &rest[end..];
+            }
+            replaced.push_str(rest);
+            *value = replaced;
+        }
+    }
+}
+
+/// A description of an instance, written to `instance.toml` in its directory at install
+///
+/// The central meta is the index of instances, but this file is the source of truth for the
+/// instance it's in, so the directory still describes itself after being copied elsewhere
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub(crate) struct InstanceManifest {
+    /// The Minecraft version of the server
+    pub version: VersionNumber,
+    /// The kind of version, e.g. `release` or `snapshot`
+    pub kind: String,
+    /// The major version of Java the server needs
+    pub java: u8,
+    pub created_at: DateTime<Utc>,
+    /// The version of mcdl that installed the instance
+    pub mcdl_version: String,
+}
+
+impl InstanceManifest {
+    pub const FILE_NAME: &'static str = "instance.toml";
+
+    pub fn new(version: VersionNumber, kind: &str, java: u8) -> Self {
+        Self {
+            version,
+            kind: kind.to_string(),
+            java,
+            created_at: Utc::now(),
+            mcdl_version: MCDL_VERSION.clone(),
+        }
+    }
+
+    /// Reads the manifest in `instance_dir`, if it has one
+    #[instrument(err)]
+    pub fn read<P: AsRef<Path> + Debug>(instance_dir: P) -> Result<Option<Self>> {
+        let path = instance_dir.as_ref().join(Self::FILE_NAME);
+        if !path.exists() {
+            debug!("No instance manifest");
+            return Ok(None);
+        }
+
+        let contents = std::fs::read_to_string(&path).wrap_err(format!(
+            "Error reading instance manifest at {}",
+            path.display()
+        ))?;
+        let manifest = toml::from_str(&contents).wrap_err(format!(
+            "Error parsing instance manifest at {}",
+            path.display()
+        ))?;
+
+        Ok(Some(manifest))
+    }
+
+    #[instrument(err, ret(level = "debug"), skip(self))]
+    pub fn save<P: AsRef<Path> + Debug>(&self, instance_dir: P) -> Result<()> {
+        debug!("Saving instance manifest");
+
+        let path = instance_dir.as_ref().join(Self::FILE_NAME);
+        let mut contents =
+            "# This file is automatically generated and describes the instance in this directory\n\n"
+                .to_string();
+        contents.push_str(&toml::to_string(self)?);
+
+        std::fs::write(&path, contents).wrap_err(format!(
+            "Error writing instance manifest to {}",
+            path.display()
+        ))?;
+
+        Ok(())
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct InstanceMeta {
     pub id: VersionNumber,
     pub files: Vec<PathBuf>,
     pub jre: u8, // String?
+    /// Where the server jar came from, if it wasn't downloaded from Mojang
+    ///
+    /// Instances with a custom jar must never have their jar replaced by the official one
+    #[serde(default)]
+    pub custom_jar: Option<String>,
+    /// The absolute path of the instance directory, if it isn't in the instance base directory
+    #[serde(default)]
+    pub location: Option<PathBuf>,
+    /// Freeform labels for organizing instances, e.g. `prod` or `creative`
+    #[serde(default)]
+    pub tags: BTreeSet<String>,
+    /// Whether the instance is a client rather than a server, so it can't be run by mcdl
+    #[serde(default)]
+    pub client: bool,
+    /// The official jar download the instance was installed from, so the jar can be audited and
+    /// verified without the manifest
+    ///
+    /// `None` for custom jars, and for instances installed before downloads were recorded
+    #[serde(default)]
+    pub download: Option<VersionDownload>,
+    /// When `run` last started the server
+    #[serde(default)]
+    pub last_started: Option<DateTime<Utc>>,
 }
 
 impl InstanceMeta {
@@ -150,6 +363,12 @@ impl InstanceMeta {
             id,
             files: Vec::new(),
             jre,
+            custom_jar: None,
+            location: None,
+            tags: BTreeSet::new(),
+            client: false,
+            download: None,
+            last_started: None,
         }
     }
 
@@ -166,20 +385,109 @@ impl InstanceMeta {
     }
 }
 
+/// An advisory lock on the meta, held while it's changed and released when dropped
+#[must_use]
+#[derive(Debug)]
+pub(crate) struct MetaLock(std::fs::File);
+
+impl MetaLock {
+    /// How long to wait for another process to release the lock
+    pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn acquire(meta_path: &Path) -> Result<Self> {
+        let mut path = meta_path.as_os_str().to_owned();
+        path.push(".lock");
+        let path = PathBuf::from(path);
+
+        std::fs::create_dir_all(path.parent().expect("infallible"))?;
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .wrap_err(format!("Failed to open meta lock {}", path.display()))?;
+
+        let start = Instant::now();
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => return Ok(Self(file)),
+                Err(e) if e.kind() != fs2::lock_contended_error().kind() => {
+                    return Err(eyre!(e).wrap_err(format!("Failed to lock {}", path.display())));
+                }
+                Err(_) if start.elapsed() >= Self::TIMEOUT => {
+                    return Err(eyre!(
+                        "Another mcdl instance is modifying state (waited {}s for {})",
+                        Self::TIMEOUT.as_secs(),
+                        path.display()
+                    ));
+                }
+                Err(_) => {
+                    debug!("Waiting for meta lock");
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+            }
+        }
+    }
+}
+
+/// A meta locked with [`AppMeta::begin_update`], which derefs to the meta itself
+#[must_use]
+pub(crate) struct MetaUpdate<'a> {
+    // dropped in order, so the meta is saved and unlocked in this process before other processes
+    meta: MutexGuard<'a, AppMeta>,
+    _lock: MetaLock,
+}
+
+impl Deref for MetaUpdate<'_> {
+    type Target = AppMeta;
+
+    fn deref(&self) -> &Self::Target {
+        &self.meta
+    }
+}
+
+impl DerefMut for MetaUpdate<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.meta
+    }
+}
+
+impl Drop for MetaLock {
+    fn drop(&mut self) {
+        // closing the file would release it as well, but this way failures get logged
+        if let Err(e) = FileExt::unlock(&self.0) {
+            warn!("Failed to unlock meta: {e}");
+        }
+    }
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub(crate) struct AppMeta {
     // keyed by id for now, possibly changed later to allow for multiple instances with the same version
     pub instances: HashMap<String, InstanceMeta>,
     pub installed_jres: HashSet<u8>, // String?
     _path: PathBuf,
+    /// The instance commands use when none is given, set with `mcdl use`
+    #[serde(default)]
+    pub current: Option<String>,
+    /// The version of the meta format, checked when reading so older metas can be migrated
+    ///
+    /// Metas from before this was added are version 0
+    #[serde(default)]
+    pub schema_version: u32,
 }
 
 impl AppMeta {
+    /// The current version of the meta format. Bump it and add a migration when the format changes
+    pub const SCHEMA_VERSION: u32 = 1;
+
     pub fn new(path: PathBuf) -> Self {
         Self {
             instances: HashMap::new(),
             installed_jres: HashSet::new(),
             _path: path,
+            current: None,
+            schema_version: Self::SCHEMA_VERSION,
         }
     }
 
@@ -191,12 +499,40 @@ impl AppMeta {
         let data =
             std::fs::read(path).wrap_err(format!("Error reading meta at {}", path.display()))?;
 
-        let meta: Self = rmp_serde::from_slice(&data)
+        let mut meta: Self = rmp_serde::from_slice(&data)
             .wrap_err(format!("Error parsing meta at {}", path.display()))?;
+        meta.migrate()
+            .wrap_err(format!("Unsupported meta at {}", path.display()))?;
 
         Ok(meta)
     }
 
+    /// Upgrades a meta read in an older format to the current one
+    ///
+    /// Fails for metas written by a newer mcdl, since saving them would drop what this one
+    /// doesn't understand
+    fn migrate(&mut self) -> Result<()> {
+        if self.schema_version > Self::SCHEMA_VERSION {
+            return Err(eyre!(
+                "Meta format {} was written by a newer mcdl than this one, which supports up to {}. Upgrade mcdl to use it",
+                self.schema_version,
+                Self::SCHEMA_VERSION
+            ));
+        }
+
+        if self.schema_version < Self::SCHEMA_VERSION {
+            info!(
+                from = self.schema_version,
+                to = Self::SCHEMA_VERSION,
+                "Migrating meta"
+            );
+            // 0 -> 1: only fields with defaults were added, which deserializing fills in
+            self.schema_version = Self::SCHEMA_VERSION;
+        }
+
+        Ok(())
+    }
+
     pub fn save(&self) -> Result<()> {
         self.save_at(&self._path)
     }
@@ -210,24 +546,98 @@ impl AppMeta {
         let data = rmp_serde::to_vec(self)
             .wrap_err(format!("Error serializing meta at {}", path.display()))?;
 
-        std::fs::write(path, data).wrap_err(format!("Error writing meta at {}", path.display()))?;
+        // replaced in one step, so other processes never read a partly written meta
+        let partial = temp_path(path);
+        let result = std::fs::write(&partial, data).and_then(|()| std::fs::rename(&partial, path));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&partial);
+        }
+        result.wrap_err(format!("Error writing meta at {}", path.display()))?;
 
         Ok(())
     }
 
-    #[instrument(skip(path))]
-    pub fn read_or_create<P: AsRef<Path> + Debug>(path: P) -> Self {
+    /// Locks the meta against other mcdl processes and reloads it, before changing and saving it
+    ///
+    /// Each process keeps the meta it read at startup, so without this, two processes installing
+    /// at the same time would each save over the other's changes. Other processes wait until the
+    /// returned update is dropped, or give up after [`MetaLock::TIMEOUT`]
+    #[instrument(err, skip(meta))]
+    pub fn begin_update(meta: &Mutex<Self>) -> Result<MetaUpdate<'_>> {
+        // waiting for the file lock can take a while, so the mutex isn't held meanwhile
+        let path = meta.lock()._path.clone();
+        let lock = MetaLock::acquire(&path)?;
+
+        let mut meta = meta.lock();
+        if path.exists() {
+            *meta = Self::from_file(&path)?;
+            meta._path = path;
+        }
+
+        Ok(MetaUpdate { meta, _lock: lock })
+    }
+
+    /// Reads the meta at `path`, or creates an empty one if there is none
+    ///
+    /// A meta that can't be decoded is moved aside and replaced, but one written by a newer mcdl
+    /// is an error, since replacing it would lose every instance it lists
+    #[instrument(err, skip(path))]
+    pub fn read_or_create<P: AsRef<Path> + Debug>(path: P) -> Result<Self> {
         let path = path.as_ref();
-        if let Ok(mut meta) = Self::from_file(path) {
-            debug!("Meta read successfully");
-            meta._path = path.to_path_buf(); // this shouldn't be necessary, but it's here just in case
-            meta
-        } else {
-            debug!("Meta not found, creating");
-            let meta = Self::new(path.to_path_buf());
-            meta.save().expect("Error saving meta"); // TODO: handle error
-            meta
+        if let Some(meta) = Self::read_existing(path)? {
+            return Ok(meta);
+        }
+
+        // another process may be creating or replacing it too
+        let _lock = MetaLock::acquire(path)?;
+        match std::fs::read(path) {
+            Ok(data) => match rmp_serde::from_slice::<Self>(&data) {
+                Ok(mut meta) => {
+                    meta.migrate()
+                        .wrap_err(format!("Unsupported meta at {}", path.display()))?;
+                    meta._path = path.to_path_buf(); // this shouldn't be necessary, but it's here just in case
+                    return Ok(meta);
+                }
+                Err(e) => {
+                    // kept, so the instances it lists can still be recovered by hand
+                    let mut backup = path.as_os_str().to_owned();
+                    backup.push(format!(".{}.bak", Utc::now().format("%Y%m%dT%H%M%S%.9f")));
+                    let backup = PathBuf::from(backup);
+                    warn!("Replacing unreadable meta: {e:?}");
+                    eprintln!(
+                        "Warning: Error parsing meta at {}: {e}. It was moved to {} and an empty meta was created",
+                        path.display(),
+                        backup.display()
+                    );
+                    std::fs::rename(path, &backup)
+                        .wrap_err(format!("Error backing up meta to {}", backup.display()))?;
+                }
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                debug!("Meta not found, creating")
+            }
+            Err(e) => return Err(e).wrap_err(format!("Error reading meta at {}", path.display())),
         }
+
+        let meta = Self::new(path.to_path_buf());
+        meta.save()?;
+        Ok(meta)
+    }
+
+    /// Reads the meta at `path` if it exists and can be decoded, without locking it
+    fn read_existing(path: &Path) -> Result<Option<Self>> {
+        let Ok(data) = std::fs::read(path) else {
+            return Ok(None);
+        };
+        let Ok(mut meta) = rmp_serde::from_slice::<Self>(&data) else {
+            return Ok(None);
+        };
+
+        debug!("Meta read successfully");
+        meta.migrate()
+            .wrap_err(format!("Unsupported meta at {}", path.display()))?;
+        meta._path = path.to_path_buf();
+        Ok(Some(meta))
     }
 
     #[instrument(skip(self, instance), fields(id = %instance.id))]
@@ -247,6 +657,13 @@ impl AppMeta {
         self.instances.contains_key(id)
     }
 
+    /// The current instance, if it's still installed
+    pub fn current_instance(&self) -> Option<&String> {
+        self.current
+            .as_ref()
+            .filter(|id| self.instances.contains_key(*id))
+    }
+
     #[instrument(skip(self))]
     pub fn add_jre(&mut self, jre: u8) -> bool {
         debug!("Adding JRE");
@@ -284,6 +701,7 @@ mod tests {
         settings.save(&path).await.unwrap();
         let settings = InstanceSettings::from_file(&path).await.unwrap();
         assert_eq!(settings.java.version, 8);
+        assert!(!settings.java.bundled);
         assert_eq!(
             settings.java.args,
             DEFAULT_JVM_ARGS
@@ -295,6 +713,35 @@ mod tests {
         assert_eq!(settings.server.args, vec!["--nogui".to_string()]);
     }
 
+    #[test]
+    fn read_write_instance_manifest() {
+        let suf: String = Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
+        let dir = PathBuf::from(format!("instance-{suf}"));
+        std::fs::create_dir(&dir).unwrap();
+
+        scopeguard::defer! {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+
+        assert_eq!(InstanceManifest::read(&dir).unwrap(), None);
+
+        let manifest = InstanceManifest::new("1.20.4".parse().unwrap(), "release", 17);
+        manifest.save(&dir).unwrap();
+        assert_eq!(InstanceManifest::read(&dir).unwrap(), Some(manifest));
+    }
+
+    #[test]
+    fn set_memory_replaces_heap_args() {
+        let mut settings = InstanceJavaSettings {
+            version: 17,
+            args: vec!["-Xmx2G".to_string(), "-XX:+UseG1GC".to_string()],
+            bundled: false,
+            system: false,
+        };
+        settings.set_memory("6G");
+        assert_eq!(settings.args, vec!["-Xms6G", "-Xmx6G", "-XX:+UseG1GC"]);
+    }
+
     #[tokio::test]
     #[should_panic = "Error reading settings at settings-"]
     async fn read_settings_nonexistent() {
@@ -317,4 +764,231 @@ mod tests {
         fs::write(&path, "invalid").await.unwrap();
         let _settings = InstanceSettings::from_file(&path).await.unwrap();
     }
+
+    #[tokio::test]
+    async fn read_settings_escaping_jar() {
+        let suf: String = Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
+        let path = PathBuf::from(format!("settings-{suf}.toml"));
+
+        scopeguard::defer! {
+            let _ = std::fs::remove_file(&path);
+        }
+
+        for jar in [
+            "../../etc/whatever",
+            "libs/../../server.jar",
+            "/usr/bin/java",
+            ".",
+        ] {
+            let mut settings = InstanceSettings::new(17);
+            settings.server.jar = PathBuf::from(jar);
+            settings.save(&path).await.unwrap();
+
+            let err = InstanceSettings::from_file(&path).await.unwrap_err();
+            assert!(
+                format!("{err:?}")
+                    .contains("must be a relative path inside the instance directory"),
+                "{jar}: {err:?}"
+            );
+        }
+
+        for jar in [
+            "server.jar",
+            "./libs/../server.jar",
+            "versions/1.20.1/server.jar",
+        ] {
+            let mut settings = InstanceSettings::new(17);
+            settings.server.jar = PathBuf::from(jar);
+            settings.save(&path).await.unwrap();
+            InstanceSettings::from_file(&path).await.unwrap();
+        }
+    }
+
+    #[test]
+    fn read_meta_without_custom_jar() {
+        // the layout of `InstanceMeta` before `custom_jar` was added
+        #[derive(Serialize)]
+        struct OldInstanceMeta {
+            id: VersionNumber,
+            files: Vec<PathBuf>,
+            jre: u8,
+        }
+
+        let data = rmp_serde::to_vec(&OldInstanceMeta {
+            id: "1.20.1".parse().unwrap(),
+            files: vec![],
+            jre: 17,
+        })
+        .unwrap();
+
+        let meta: InstanceMeta = rmp_serde::from_slice(&data).unwrap();
+        assert_eq!(meta.jre, 17);
+        assert_eq!(meta.custom_jar, None);
+        assert!(meta.tags.is_empty());
+        assert!(meta.download.is_none());
+    }
+
+    #[test]
+    fn read_meta_without_current() {
+        #[derive(Serialize)]
+        struct OldAppMeta {
+            instances: HashMap<String, InstanceMeta>,
+            installed_jres: HashSet<u8>,
+            _path: PathBuf,
+        }
+
+        let data = rmp_serde::to_vec(&OldAppMeta {
+            instances: HashMap::new(),
+            installed_jres: HashSet::from([17]),
+            _path: PathBuf::from("meta.mpk"),
+        })
+        .unwrap();
+
+        let meta: AppMeta = rmp_serde::from_slice(&data).unwrap();
+        assert!(meta.jre_installed(&17));
+        assert_eq!(meta.current, None);
+        assert_eq!(meta.schema_version, 0);
+    }
+
+    #[test]
+    fn migrate_meta_versions() {
+        use rand::distributions::{Alphanumeric, DistString};
+
+        #[derive(Serialize)]
+        struct MetaV0 {
+            instances: HashMap<String, InstanceMeta>,
+            installed_jres: HashSet<u8>,
+            _path: PathBuf,
+            current: Option<String>,
+        }
+
+        let suf: String = Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
+        let path = std::env::temp_dir().join(format!("mcdl-meta-{suf}.mpk"));
+        let backups = || {
+            std::fs::read_dir(std::env::temp_dir())
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .filter(|p| {
+                    let name = p.file_name().unwrap().to_string_lossy();
+                    name.starts_with(&format!("mcdl-meta-{suf}.mpk.")) && name.ends_with(".bak")
+                })
+                .collect_vec()
+        };
+        scopeguard::defer! {
+            let _ = std::fs::remove_file(&path);
+            for backup in backups() {
+                let _ = std::fs::remove_file(backup);
+            }
+        }
+
+        let v0 = MetaV0 {
+            instances: HashMap::from([(
+                "1.20.1".to_string(),
+                InstanceMeta::new("1.20.1".parse().unwrap(), 17),
+            )]),
+            installed_jres: HashSet::from([17]),
+            _path: path.clone(),
+            current: Some("1.20.1".to_string()),
+        };
+        std::fs::write(&path, rmp_serde::to_vec(&v0).unwrap()).unwrap();
+        let meta = AppMeta::from_file(&path).unwrap();
+        assert_eq!(meta.schema_version, AppMeta::SCHEMA_VERSION);
+        assert!(meta.instance_installed(&"1.20.1".to_string()));
+
+        let mut newer = AppMeta::new(path.clone());
+        newer.schema_version = AppMeta::SCHEMA_VERSION + 1;
+        newer.save().unwrap();
+        let err = AppMeta::from_file(&path).unwrap_err();
+        assert!(format!("{err:?}").contains("Upgrade mcdl"), "{err:?}");
+
+        // a newer meta is left alone, since resetting it would lose its instances
+        let err = AppMeta::read_or_create(&path).unwrap_err();
+        assert!(format!("{err:?}").contains("Upgrade mcdl"), "{err:?}");
+        assert_eq!(
+            AppMeta::from_file(&path).unwrap_err().to_string(),
+            err.to_string()
+        );
+        assert!(backups().is_empty());
+
+        // undecodable metas are set aside rather than overwritten, each under its own name
+        for _ in 0..2 {
+            std::fs::write(&path, b"not a meta").unwrap();
+            let meta = AppMeta::read_or_create(&path).unwrap();
+            assert!(meta.instances.is_empty());
+            assert_eq!(meta.schema_version, AppMeta::SCHEMA_VERSION);
+        }
+        assert_eq!(backups().len(), 2);
+    }
+
+    #[test]
+    fn concurrent_updates_keep_all_instances() {
+        let suf: String = Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
+        let path = std::env::temp_dir().join(format!("mcdl-meta-{suf}.mpk"));
+        let mut lock = path.as_os_str().to_owned();
+        lock.push(".lock");
+        let lock = PathBuf::from(lock);
+        scopeguard::defer! {
+            let _ = std::fs::remove_file(&path);
+            let _ = std::fs::remove_file(&lock);
+        }
+
+        // each writer has its own meta and lock file handle, like separate processes would
+        let writers = ["a", "b"].map(|writer| {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                let meta = Mutex::new(AppMeta::read_or_create(&path).unwrap());
+                for i in 0..20 {
+                    let mut meta = AppMeta::begin_update(&meta).unwrap();
+                    meta.instances.insert(
+                        format!("{writer}-{i}"),
+                        InstanceMeta::new("1.20.1".parse().unwrap(), 17),
+                    );
+                    meta.save().unwrap();
+                }
+            })
+        });
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let meta = AppMeta::from_file(&path).unwrap();
+        assert_eq!(meta.instances.len(), 40);
+    }
+
+    #[test]
+    fn replace_instance_paths() {
+        let mut settings = InstanceSettings::new(17);
+        settings
+            .java
+            .args
+            .push("-Dlog4j.configurationFile=/srv/mc/prod/log4j2.xml".to_string());
+        settings
+            .server
+            .args
+            .push("--universe=/srv/mc/production".to_string());
+        settings
+            .env
+            .insert("BACKUP_DIR".to_string(), "/srv/mc/prod/backups".to_string());
+
+        settings.replace_paths(Path::new("/srv/mc/prod"), Path::new("/srv/mc/test"));
+        assert_eq!(
+            settings.java.args.last().unwrap(),
+            "-Dlog4j.configurationFile=/srv/mc/test/log4j2.xml"
+        );
+        assert_eq!(
+            settings.server.args.last().unwrap(),
+            "--universe=/srv/mc/production"
+        );
+        assert_eq!(settings.env["BACKUP_DIR"], "/srv/mc/test/backups");
+    }
+
+    #[test]
+    fn current_instance_must_exist() {
+        let mut meta = AppMeta::new(PathBuf::from("meta.mpk"));
+        meta.current = Some("1.20.1".to_string());
+        assert_eq!(meta.current_instance(), None);
+
+        meta.add_instance(InstanceMeta::new("1.20.1".parse().unwrap(), 17));
+        assert_eq!(meta.current_instance().map(String::as_str), Some("1.20.1"));
+    }
 }
@@ -1,3 +1,11 @@
+pub(crate) mod adoptium;
+pub(crate) mod checksum;
+pub(crate) mod config;
+pub(crate) mod env_file;
+pub(crate) mod mappings;
 pub(crate) mod meta;
 pub(crate) mod net;
+pub(crate) mod properties;
+pub(crate) mod run_state;
+pub(crate) mod service;
 pub(crate) mod version;
@@ -1,18 +1,38 @@
 use std::path::Path;
 use std::time::SystemTime;
 
-use color_eyre::eyre::Result;
-use derive_more::Constructor;
+use color_eyre::eyre::{eyre, Result};
+use derive_more::derive::Display;
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use tokio::fs;
 
-#[derive(Serialize, Deserialize, Constructor)]
+#[derive(Serialize, Deserialize)]
 pub(crate) struct CachedResponse<T> {
     pub data: T,
     pub expires: SystemTime,
+    /// The version of the cache format. Entries in any other format are refetched
+    ///
+    /// Entries from before this was added are version 0
+    #[serde(default)]
+    schema_version: u32,
 }
 
 impl<T> CachedResponse<T> {
+    /// The current version of the cache format
+    ///
+    /// Bump it when a cached type changes, since old entries would otherwise be read with the new
+    /// fields left at their defaults until they expire
+    // 2: `JavaVersionInfo::assumed` was added to version metadata
+    const SCHEMA_VERSION: u32 = 2;
+
+    pub fn new(data: T, expires: SystemTime) -> Self {
+        Self {
+            data,
+            expires,
+            schema_version: Self::SCHEMA_VERSION,
+        }
+    }
     pub fn is_expired(&self) -> bool {
         SystemTime::now() > self.expires
     }
@@ -22,6 +42,13 @@ impl<T> CachedResponse<T> {
     where Self: for<'de> Deserialize<'de> {
         let data = fs::read(path).await?;
         let cached: CachedResponse<T> = rmp_serde::from_slice(&data)?;
+        if cached.schema_version != Self::SCHEMA_VERSION {
+            return Err(eyre!(
+                "Cached response is in format {}, expected {}",
+                cached.schema_version,
+                Self::SCHEMA_VERSION
+            ));
+        }
         Ok(cached)
     }
 
@@ -33,3 +60,51 @@ impl<T> CachedResponse<T> {
         Ok(())
     }
 }
+
+/// Why a service couldn't be reached, as reported by `doctor`
+#[derive(Debug, Display)]
+pub(crate) enum ProbeError {
+    #[display("DNS lookup failed: {_0}")]
+    Dns(String),
+    #[display("connection failed: {_0}")]
+    Connect(String),
+    #[display("TLS handshake failed: {_0}")]
+    Tls(String),
+    #[display("timed out")]
+    Timeout,
+    #[display("HTTP status {_0}")]
+    Status(StatusCode),
+    #[display("{_0}")]
+    Other(String),
+}
+
+impl From<reqwest::Error> for ProbeError {
+    /// Classifies a request error by its cause, which reqwest only exposes as messages
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            return ProbeError::Timeout;
+        }
+        if let Some(status) = e.status() {
+            return ProbeError::Status(status);
+        }
+
+        let mut causes = vec![];
+        let mut source = std::error::Error::source(&e);
+        while let Some(cause) = source {
+            causes.push(cause.to_string());
+            source = cause.source();
+        }
+        let root = causes.last().cloned().unwrap_or_else(|| e.to_string());
+        let causes = causes.join(": ").to_ascii_lowercase();
+
+        if causes.contains("dns error") {
+            ProbeError::Dns(root)
+        } else if causes.contains("certificate") || causes.contains("tls") {
+            ProbeError::Tls(root)
+        } else if e.is_connect() {
+            ProbeError::Connect(root)
+        } else {
+            ProbeError::Other(root)
+        }
+    }
+}
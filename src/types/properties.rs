@@ -0,0 +1,118 @@
+use std::fmt::Debug;
+use std::path::Path;
+
+use color_eyre::eyre::{Result, WrapErr};
+use tracing::{debug, instrument};
+
+/// A `server.properties` file
+///
+/// Only the keys that are set are changed; comments, ordering,
+/// and any other keys are preserved as-is
+#[derive(Debug, Default)]
+pub(crate) struct ServerProperties {
+    lines: Vec<String>,
+}
+
+impl ServerProperties {
+    pub fn parse(contents: &str) -> Self {
+        Self {
+            lines: contents.lines().map(String::from).collect(),
+        }
+    }
+
+    /// Reads the properties at `path`, or returns an empty set if the file does not exist
+    #[instrument(err)]
+    pub fn read_or_default<P: AsRef<Path> + Debug>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            debug!("Properties not found, using empty set");
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .wrap_err(format!("Error reading properties at {}", path.display()))?;
+        Ok(Self::parse(&contents))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| Self::split(line))
+            .find_map(|(k, v)| (k == key).then_some(v))
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        let entry = format!("{key}={value}");
+        match self
+            .lines
+            .iter_mut()
+            .find(|line| Self::split(line).is_some_and(|(k, _)| k == key))
+        {
+            Some(line) => *line = entry,
+            None => self.lines.push(entry),
+        }
+    }
+
+    #[instrument(err, ret(level = "debug"), skip(self))]
+    pub fn save<P: AsRef<Path> + Debug>(&self, path: P) -> Result<()> {
+        debug!("Saving properties");
+
+        let path = path.as_ref();
+        let mut contents = self.lines.join("\n");
+        contents.push('\n');
+
+        std::fs::write(path, contents)
+            .wrap_err(format!("Error writing properties at {}", path.display()))?;
+
+        Ok(())
+    }
+
+    fn split(line: &str) -> Option<(&str, &str)> {
+        let line = line.trim_start();
+        if line.starts_with('#') || line.starts_with('!') {
+            return None;
+        }
+
+        line.split_once('=').map(|(k, v)| (k.trim(), v.trim()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_preserves_other_lines() {
+        let mut props =
+            ServerProperties::parse("#Minecraft server properties\nlevel-name=old\nmotd=hi");
+        props.set("level-name", "world");
+        props.set("server-port", "25566");
+
+        assert_eq!(
+            props.lines,
+            vec![
+                "#Minecraft server properties",
+                "level-name=world",
+                "motd=hi",
+                "server-port=25566"
+            ]
+        );
+    }
+
+    #[test]
+    fn get_ignores_comments() {
+        let props = ServerProperties::parse("#level-name=commented\nlevel-name = world\n");
+        assert_eq!(props.get("level-name"), Some("world"));
+        assert_eq!(props.get("motd"), None);
+    }
+
+    #[test]
+    fn set_ignores_comments() {
+        let mut props = ServerProperties::parse("# level-name=commented");
+        props.set("level-name", "world");
+        assert_eq!(
+            props.lines,
+            vec!["# level-name=commented", "level-name=world"]
+        );
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use color_eyre::eyre::{Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument, warn};
+
+/// The server process of a running instance, written when `run` starts it
+///
+/// The file is removed when the server exits. If mcdl is killed before that, the file is left
+/// behind, so readers check that the process still exists
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub(crate) struct RunState {
+    /// The PID of the server process
+    pub pid: u32,
+    /// When the server was started
+    pub started: DateTime<Utc>,
+}
+
+impl RunState {
+    pub fn new(pid: u32) -> Self {
+        Self {
+            pid,
+            started: Utc::now(),
+        }
+    }
+
+    /// Reads the run state at `path`, or `None` if there is none
+    #[instrument(err)]
+    pub fn from_file<P: AsRef<Path> + Debug>(path: P) -> Result<Option<Self>> {
+        let path = path.as_ref();
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e).wrap_err(format!("Error reading run state at {}", path.display()))
+            }
+        };
+
+        toml::from_str(&contents)
+            .map(Some)
+            .wrap_err(format!("Error parsing run state at {}", path.display()))
+    }
+
+    /// Writes the run state to `path`, returning a guard that removes it again when dropped
+    #[instrument(err, skip(self))]
+    pub fn save<P: AsRef<Path> + Debug>(&self, path: P) -> Result<RunStateGuard> {
+        debug!(pid = self.pid, "Saving run state");
+
+        let path = path.as_ref();
+        std::fs::create_dir_all(path.parent().expect("infallible"))?;
+        std::fs::write(path, toml::to_string(self)?)
+            .wrap_err(format!("Error writing run state at {}", path.display()))?;
+
+        Ok(RunStateGuard(path.to_path_buf()))
+    }
+
+    /// Whether the recorded process still exists
+    ///
+    /// PIDs are reused, so this can be wrong after a reboot or if a stale file is very old
+    pub fn is_alive(&self) -> bool {
+        process_alive(self.pid)
+    }
+}
+
+/// Removes a run state file when the server it describes exits
+#[must_use]
+#[derive(Debug)]
+pub(crate) struct RunStateGuard(PathBuf);
+
+impl Drop for RunStateGuard {
+    fn drop(&mut self) {
+        debug!(path = ?self.0, "Clearing run state");
+        if let Err(e) = std::fs::remove_file(&self.0) {
+            warn!("Failed to remove run state {}: {e}", self.0.display());
+        }
+    }
+}
+
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+
+    // signal 0 only checks that the process exists and could be signalled
+    // SAFETY: `kill` has no memory safety requirements
+    let signalled = unsafe { libc::kill(pid, 0) } == 0;
+    // `EPERM` means it exists, but belongs to another user
+    signalled || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[cfg(windows)]
+fn process_alive(pid: u32) -> bool {
+    std::process::Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {pid}"), "/FO", "CSV", "/NH"])
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains(&format!("\"{pid}\"")))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::distributions::{Alphanumeric, DistString};
+
+    use super::*;
+
+    #[test]
+    fn run_state_lifecycle() {
+        let suf: String = Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
+        let path = std::env::temp_dir().join(format!("mcdl-run-{suf}.toml"));
+
+        assert_eq!(RunState::from_file(&path).unwrap(), None);
+
+        let state = RunState::new(std::process::id());
+        assert!(state.is_alive());
+        let guard = state.save(&path).unwrap();
+        assert_eq!(RunState::from_file(&path).unwrap(), Some(state));
+
+        drop(guard);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn exited_process_is_not_alive() {
+        let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+            .arg("--list")
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+
+        assert!(!RunState::new(pid).is_alive());
+    }
+}
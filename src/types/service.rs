@@ -0,0 +1,277 @@
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use derive_more::derive::Display;
+use itertools::Itertools;
+
+/// A service manager to generate a definition for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Display)]
+pub(crate) enum ServiceKind {
+    /// A systemd unit (Linux)
+    #[display("systemd")]
+    Systemd,
+    /// A launchd property list (macOS)
+    #[display("launchd")]
+    Launchd,
+    /// A script registering a Windows service with NSSM
+    #[display("windows")]
+    Windows,
+}
+
+impl ServiceKind {
+    /// The service manager used by the current OS
+    pub fn native() -> Self {
+        match std::env::consts::OS {
+            "macos" => ServiceKind::Launchd,
+            "windows" => ServiceKind::Windows,
+            _ => ServiceKind::Systemd,
+        }
+    }
+}
+
+/// A service that runs an instance with `mcdl run`
+#[derive(Debug)]
+pub(crate) struct ServiceDefinition {
+    /// The instance to run
+    pub instance: String,
+    /// The path to the `mcdl` executable
+    pub mcdl_path: PathBuf,
+    /// The instance directory, used as the working directory
+    pub working_dir: PathBuf,
+    /// The `java` executable the instance runs with, for reference
+    pub java_path: PathBuf,
+    /// The user to run the service as, if any
+    pub user: Option<String>,
+}
+
+impl ServiceDefinition {
+    /// The service name, containing only characters that are safe for every service manager
+    pub fn name(&self) -> String {
+        let id = self
+            .instance
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '.' {
+                    c
+                } else {
+                    '-'
+                }
+            })
+            .collect::<String>();
+
+        format!("mcdl-{id}")
+    }
+
+    /// The file name the definition should be installed as
+    pub fn file_name(&self, kind: ServiceKind) -> String {
+        match kind {
+            ServiceKind::Systemd => format!("{}.service", self.name()),
+            ServiceKind::Launchd => format!("com.github.paenis.{}.plist", self.name()),
+            ServiceKind::Windows => format!("install-{}.cmd", self.name()),
+        }
+    }
+
+    fn args(&self) -> Vec<String> {
+        vec![
+            "--color".to_string(),
+            "never".to_string(),
+            "--progress".to_string(),
+            "plain".to_string(),
+            "run".to_string(),
+            "--version".to_string(),
+            self.instance.clone(),
+        ]
+    }
+
+    pub fn render(&self, kind: ServiceKind) -> String {
+        match kind {
+            ServiceKind::Systemd => self.render_systemd(),
+            ServiceKind::Launchd => self.render_launchd(),
+            ServiceKind::Windows => self.render_windows(),
+        }
+    }
+
+    fn render_systemd(&self) -> String {
+        // `%` starts a specifier in any value, e.g. `%h` for the home directory
+        fn escape_specifiers(s: &str) -> String {
+            s.replace('%', "%%")
+        }
+        fn quote(s: &str) -> String {
+            format!(
+                "\"{}\"",
+                escape_specifiers(s)
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+            )
+        }
+
+        let exec = std::iter::once(self.mcdl_path.to_string_lossy().to_string())
+            .chain(self.args())
+            .map(|s| quote(&s))
+            .join(" ");
+
+        let mut unit = format!(
+            "# Generated by mcdl\n\
+             # Java: {java}\n\
+             \n\
+             [Unit]\n\
+             Description=Minecraft server {instance} (mcdl)\n\
+             After=network-online.target\n\
+             Wants=network-online.target\n\
+             \n\
+             [Service]\n\
+             Type=simple\n\
+             ExecStart={exec}\n\
+             WorkingDirectory={dir}\n\
+             Restart=on-failure\n\
+             RestartSec=10\n",
+            java = self.java_path.display(),
+            instance = self.instance,
+            // unlike `ExecStart`, this is taken literally, so quotes would become part of the path
+            dir = escape_specifiers(&self.working_dir.to_string_lossy()),
+        );
+
+        // system units run as a dedicated user, user units run as the current user
+        let target = match &self.user {
+            Some(user) => {
+                unit.push_str(&format!("User={user}\n"));
+                "multi-user.target"
+            }
+            None => "default.target",
+        };
+        unit.push_str(&format!("\n[Install]\nWantedBy={target}\n"));
+
+        unit
+    }
+
+    fn render_launchd(&self) -> String {
+        fn escape(s: &str) -> String {
+            s.replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;")
+                .replace('"', "&quot;")
+        }
+
+        let args = std::iter::once(self.mcdl_path.to_string_lossy().to_string())
+            .chain(self.args())
+            .map(|s| format!("        <string>{}</string>\n", escape(&s)))
+            .join("");
+        let user = self
+            .user
+            .as_ref()
+            .map(|user| {
+                format!(
+                    "    <key>UserName</key>\n    <string>{}</string>\n",
+                    escape(user)
+                )
+            })
+            .unwrap_or_default();
+
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<!-- Generated by mcdl. Java: {java} -->
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>com.github.paenis.{name}</string>
+    <key>ProgramArguments</key>
+    <array>
+{args}    </array>
+    <key>WorkingDirectory</key>
+    <string>{dir}</string>
+{user}    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+</dict>
+</plist>
+"#,
+            java = escape(&self.java_path.to_string_lossy()),
+            name = self.name(),
+            dir = escape(&self.working_dir.to_string_lossy()),
+        )
+    }
+
+    fn render_windows(&self) -> String {
+        let name = self.name();
+        let args = self.args().join(" ");
+        let user = self
+            .user
+            .as_ref()
+            .map(|user| format!("nssm set {name} ObjectName \"{user}\"\r\n"))
+            .unwrap_or_default();
+
+        format!(
+            "@echo off\r\n\
+             rem Generated by mcdl. Registers the instance as a service using NSSM (https://nssm.cc)\r\n\
+             rem Run from an elevated prompt. Java: {java}\r\n\
+             nssm install {name} \"{mcdl}\" {args}\r\n\
+             nssm set {name} AppDirectory \"{dir}\"\r\n\
+             nssm set {name} AppExit Default Restart\r\n\
+             nssm set {name} AppRestartDelay 10000\r\n\
+             nssm set {name} Description \"Minecraft server {instance} (mcdl)\"\r\n\
+             {user}",
+            java = self.java_path.display(),
+            mcdl = self.mcdl_path.display(),
+            dir = self.working_dir.display(),
+            instance = self.instance,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definition(user: Option<&str>) -> ServiceDefinition {
+        ServiceDefinition {
+            instance: "1.20.1".to_string(),
+            mcdl_path: PathBuf::from("/usr/bin/mcdl"),
+            working_dir: PathBuf::from("/srv/mc/1.20.1"),
+            java_path: PathBuf::from("/srv/jre/17/bin/java"),
+            user: user.map(String::from),
+        }
+    }
+
+    #[test]
+    fn render_systemd_unit() {
+        let unit = definition(Some("minecraft")).render(ServiceKind::Systemd);
+
+        assert!(unit.contains(
+            "ExecStart=\"/usr/bin/mcdl\" \"--color\" \"never\" \"--progress\" \"plain\" \"run\" \"--version\" \"1.20.1\"\n"
+        ));
+        assert!(unit.contains("WorkingDirectory=/srv/mc/1.20.1\n"));
+        assert!(unit.contains("Restart=on-failure\n"));
+        assert!(unit.contains("User=minecraft\n"));
+        assert!(unit.contains("WantedBy=multi-user.target\n"));
+
+        let unit = definition(None).render(ServiceKind::Systemd);
+        assert!(!unit.contains("User="));
+        assert!(unit.contains("WantedBy=default.target\n"));
+
+        let mut spaced = definition(None);
+        spaced.working_dir = PathBuf::from("/srv/mc/100% vanilla");
+        let unit = spaced.render(ServiceKind::Systemd);
+        assert!(unit.contains("WorkingDirectory=/srv/mc/100%% vanilla\n"));
+    }
+
+    #[test]
+    fn render_launchd_plist() {
+        let plist = definition(None).render(ServiceKind::Launchd);
+
+        assert!(plist.contains("<string>com.github.paenis.mcdl-1.20.1</string>"));
+        assert!(plist.contains("        <string>1.20.1</string>\n"));
+        assert!(!plist.contains("UserName"));
+    }
+
+    #[test]
+    fn service_name_is_sanitized() {
+        let mut definition = definition(None);
+        definition.instance = "3D Shareware v1.34".to_string();
+        assert_eq!(definition.name(), "mcdl-3D-Shareware-v1.34");
+    }
+}
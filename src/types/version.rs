@@ -1,22 +1,36 @@
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::Infallible;
 use std::fmt::Display;
+use std::path::PathBuf;
 use std::str::FromStr;
 
 use chrono::{DateTime, FixedOffset};
+use color_eyre::eyre::{eyre, Result};
 use derive_more::derive::{Constructor, IsVariant};
 use derive_more::Display as MoreDisplay;
+use itertools::Itertools;
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use serde_with::{DeserializeFromStr, SerializeDisplay};
 
+use crate::types::checksum::{Checksum, ChecksumAlgo};
 use crate::utils::macros::parse_variants;
 
 /// Version format for release versions
 /// in the form of `X.Y.Z`
 #[derive(
-    Clone, Debug, SerializeDisplay, DeserializeFromStr, PartialEq, Eq, PartialOrd, Ord, Constructor,
+    Clone,
+    Debug,
+    SerializeDisplay,
+    DeserializeFromStr,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Constructor,
 )]
 pub(crate) struct ReleaseVersion {
     major: u64,
@@ -63,7 +77,16 @@ impl FromStr for ReleaseVersion {
 /// Version format for pre-release versions
 /// in the form of `X.Y.Z-preN` or `X.Y.Z-rcN`
 #[derive(
-    Clone, Debug, SerializeDisplay, DeserializeFromStr, PartialEq, Eq, PartialOrd, Ord, Constructor,
+    Clone,
+    Debug,
+    SerializeDisplay,
+    DeserializeFromStr,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Constructor,
 )]
 pub(crate) struct PreReleaseVersion {
     major: u64,
@@ -118,7 +141,16 @@ impl FromStr for PreReleaseVersion {
 /// in the form of `XXwYYZ`, where `XX` is the year,
 /// `YY` is the week, and `Z` is the iteration (a, b, c, ...)
 #[derive(
-    Clone, Debug, SerializeDisplay, DeserializeFromStr, PartialEq, Eq, PartialOrd, Ord, Constructor,
+    Clone,
+    Debug,
+    SerializeDisplay,
+    DeserializeFromStr,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Constructor,
 )]
 pub(crate) struct SnapshotVersion {
     year: u8,          // 13-$currentyear
@@ -151,20 +183,81 @@ impl FromStr for SnapshotVersion {
     }
 }
 
+/// Version format for anything that isn't a release, pre-release or snapshot,
+/// e.g. `b1.7.3` or `3D Shareware v1.34`
+///
+/// April Fools versions like `22w13oneblockatatime` start like a snapshot, so the year and week
+/// are kept to order them among the snapshots
+#[derive(Clone, Debug, SerializeDisplay, DeserializeFromStr, PartialEq, Eq, Hash)]
+pub(crate) struct OtherVersion {
+    id: String,
+    /// The year and week of a leading `XXwYY`
+    week: Option<(u8, u8)>,
+}
+
+impl OtherVersion {
+    pub fn as_str(&self) -> &str {
+        &self.id
+    }
+}
+
+impl Display for OtherVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.id)
+    }
+}
+
+impl FromStr for OtherVersion {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r"^(\d{2})w(\d{2})").unwrap();
+        }
+
+        Ok(Self {
+            id: s.to_string(),
+            week: RE
+                .captures(s)
+                .map(|caps| (caps[1].parse().unwrap(), caps[2].parse().unwrap())),
+        })
+    }
+}
+
+impl Ord for OtherVersion {
+    /// Versions with a week are ordered by it, and come before the others
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.week, other.week) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+        .then_with(|| self.id.cmp(&other.id))
+    }
+}
+
+impl PartialOrd for OtherVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 /// A version number, which can be one of the following:
 /// - `Release`
 /// - `PreRelease`
 /// - `Snapshot`
 /// - `Other`
-#[derive(
-    Clone, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, MoreDisplay, IsVariant,
-)]
+///
+/// Versions are ordered by kind in that order, except that `Other` versions named after a
+/// snapshot week come right after that week's snapshots
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, MoreDisplay, IsVariant)]
 #[serde(untagged)]
 pub(crate) enum VersionNumber {
     Release(ReleaseVersion),
     PreRelease(PreReleaseVersion),
     Snapshot(SnapshotVersion),
-    Other(String), // fallback
+    Other(OtherVersion), // fallback
 }
 
 // implements FromStr for VersionNumber
@@ -172,13 +265,163 @@ parse_variants!(VersionNumber {
     Release as ReleaseVersion,
     PreRelease as PreReleaseVersion,
     Snapshot as SnapshotVersion,
-    Other as String,
+    Other as OtherVersion,
 });
 
+impl Ord for VersionNumber {
+    fn cmp(&self, other: &Self) -> Ordering {
+        use VersionNumber::*;
+
+        let rank = |v: &Self| match v {
+            Release(_) => 0,
+            PreRelease(_) => 1,
+            Snapshot(_) => 2,
+            Other(_) => 3,
+        };
+
+        match (self, other) {
+            (Release(a), Release(b)) => a.cmp(b),
+            (PreRelease(a), PreRelease(b)) => a.cmp(b),
+            (Snapshot(a), Snapshot(b)) => a.cmp(b),
+            (Other(a), Other(b)) => a.cmp(b),
+            (Snapshot(a), Other(b)) => match b.week {
+                Some(week) => (a.year, a.week).cmp(&week).then(Ordering::Less),
+                None => Ordering::Less,
+            },
+            (Other(_), Snapshot(_)) => other.cmp(self).reverse(),
+            _ => rank(self).cmp(&rank(other)),
+        }
+    }
+}
+
+impl PartialOrd for VersionNumber {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl VersionNumber {
+    /// The [`VersionSeries`] this version belongs to
+    pub fn series(&self) -> VersionSeries {
+        match self {
+            VersionNumber::Release(v) => VersionSeries::Minor(v.major, v.minor),
+            VersionNumber::PreRelease(v) => VersionSeries::Minor(v.major, v.minor),
+            VersionNumber::Snapshot(v) => VersionSeries::Snapshots(v.year),
+            VersionNumber::Other(v) => v.week.map_or(VersionSeries::Other, |(year, _)| {
+                VersionSeries::Snapshots(year)
+            }),
+        }
+    }
+
+    /// Whether this is an experimental snapshot, e.g. `1.18_experimental-snapshot-1`
+    ///
+    /// These don't follow any of the other formats, so they are parsed as `Other`
+    pub fn is_experimental(&self) -> bool {
+        match self {
+            VersionNumber::Other(v) => {
+                v.id.contains("_experimental") || v.id.contains("-experimental-snapshot-")
+            }
+            _ => false,
+        }
+    }
+
+    /// Finds this version in `manifest`
+    ///
+    /// Parsing a [`VersionNumber`] only checks its structure. The manifest is the source of truth
+    /// for which versions exist, so anything that needs an existing version should resolve it first.
+    /// If the version does not exist, the error includes suggestions for similar versions
+    pub fn resolve<'a>(&self, manifest: &'a GameVersionList) -> Result<&'a GameVersion> {
+        if let VersionNumber::Other(alias) = self {
+            if let Some(version) = manifest.latest(alias.as_str()) {
+                return version;
+            }
+        }
+
+        if let Some(version) = manifest.versions.iter().find(|v| v.id == *self) {
+            return Ok(version);
+        }
+
+        match manifest.suggest(&self.to_string()).as_slice() {
+            [] => Err(eyre!("Version `{self}` does not exist")),
+            suggestions => Err(eyre!(
+                "Version `{self}` does not exist; did you mean {}?",
+                suggestions.iter().join(", ")
+            )),
+        }
+    }
+}
+
+/// The line of versions a version belongs to, used to group versions for display
+///
+/// Releases and pre-releases are grouped by `major.minor`, snapshots by year
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, MoreDisplay)]
+pub(crate) enum VersionSeries {
+    #[display("{_0}.{_1}")]
+    Minor(u64, u64),
+    #[display("Snapshots 20{_0:02}")]
+    Snapshots(u8),
+    #[display("Other")]
+    Other,
+}
+
+/// A version in a [`VersionSeries`], along with the pre-releases leading up to it
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct VersionTreeNode<'a> {
+    pub version: &'a VersionNumber,
+    pub pre_releases: Vec<&'a VersionNumber>,
+}
+
+/// Groups `versions` by their [`VersionSeries`], in ascending order
+///
+/// Pre-releases are nested under their release if it is also in `versions`. Otherwise, e.g. for
+/// an upcoming release, they are listed on their own. The order of `versions` is kept within
+/// each series.
+pub(crate) fn group_versions<'a>(
+    versions: impl IntoIterator<Item = &'a VersionNumber>,
+) -> Vec<(VersionSeries, Vec<VersionTreeNode<'a>>)> {
+    let versions = versions.into_iter().collect_vec();
+
+    let releases: HashSet<&ReleaseVersion> = versions
+        .iter()
+        .filter_map(|v| match v {
+            VersionNumber::Release(r) => Some(r),
+            _ => None,
+        })
+        .collect();
+
+    let mut pre_releases: HashMap<ReleaseVersion, Vec<&VersionNumber>> = HashMap::new();
+    let mut series: BTreeMap<VersionSeries, Vec<VersionTreeNode>> = BTreeMap::new();
+    for version in versions {
+        if let VersionNumber::PreRelease(pre) = version {
+            let release = ReleaseVersion::new(pre.major, pre.minor, pre.patch);
+            if releases.contains(&release) {
+                pre_releases.entry(release).or_default().push(version);
+                continue;
+            }
+        }
+
+        series
+            .entry(version.series())
+            .or_default()
+            .push(VersionTreeNode {
+                version,
+                pre_releases: Vec::new(),
+            });
+    }
+
+    for node in series.values_mut().flatten() {
+        if let VersionNumber::Release(release) = node.version {
+            node.pre_releases = pre_releases.remove(release).unwrap_or_default();
+        }
+    }
+
+    series.into_iter().collect()
+}
+
 /// A version of the game
 ///
 /// Consists of an ID, a release type, the meta URL, and a release
-/// timestamp
+/// timestamp. The v2 manifest also includes the SHA-1 of the metadata at `url`
 #[derive(Debug, Serialize, Deserialize, Eq)]
 pub(crate) struct GameVersion {
     pub id: VersionNumber,
@@ -188,6 +431,29 @@ pub(crate) struct GameVersion {
     pub time: DateTime<FixedOffset>,
     #[serde(rename = "releaseTime")]
     pub release_time: DateTime<FixedOffset>,
+    #[serde(default)]
+    sha1: Option<String>,
+    #[serde(default, rename = "complianceLevel")]
+    pub compliance_level: Option<u8>,
+}
+
+impl GameVersion {
+    /// The release type for display, with experimental snapshots told apart from other snapshots
+    pub fn kind(&self) -> &str {
+        if self.id.is_experimental() {
+            "experimental snapshot"
+        } else {
+            &self.release_type
+        }
+    }
+
+    /// The checksum of the metadata at `url`, if the manifest has one
+    pub fn checksum(&self) -> Result<Option<Checksum>> {
+        self.sha1
+            .as_deref()
+            .map(|sha1| Checksum::new(ChecksumAlgo::Sha1, sha1))
+            .transpose()
+    }
 }
 
 impl PartialEq for GameVersion {
@@ -226,6 +492,76 @@ pub(crate) struct GameVersionList {
     pub versions: Vec<GameVersion>,
 }
 
+impl GameVersionList {
+    /// Resolves one of the aliases `latest`, `latest-snapshot`, `latest-prerelease` and
+    /// `latest-any`, or returns `None` for anything else
+    ///
+    /// `latest` and `latest-snapshot` follow the manifest's own pointers. The others pick the
+    /// newest matching version by release time
+    pub fn latest(&self, alias: &str) -> Option<Result<&GameVersion>> {
+        let find = |id: &VersionNumber| {
+            self.versions
+                .iter()
+                .find(|v| v.id == *id)
+                .ok_or_else(|| eyre!("The manifest's latest version `{id}` does not exist"))
+        };
+
+        Some(match alias {
+            "latest" => find(&self.latest.release),
+            "latest-snapshot" => find(&self.latest.snapshot),
+            "latest-prerelease" => self
+                .versions
+                .iter()
+                .filter(|v| v.id.is_pre_release())
+                .max_by_key(|v| v.release_time)
+                .ok_or_else(|| eyre!("There are no pre-releases or release candidates")),
+            "latest-any" => self
+                .versions
+                .iter()
+                .max_by_key(|v| v.release_time)
+                .ok_or_else(|| eyre!("There are no versions")),
+            _ => return None,
+        })
+    }
+
+    /// The newest release after `id`, if there is one
+    ///
+    /// Only releases in the same `major.minor` line count, unless `any_minor` is set. Snapshots,
+    /// pre-releases and other versions never have a newer release, since they aren't on a line
+    pub fn newer_release(&self, id: &VersionNumber, any_minor: bool) -> Option<&GameVersion> {
+        if !id.is_release() {
+            return None;
+        }
+
+        self.versions
+            .iter()
+            .filter(|v| v.id.is_release() && v.id > *id)
+            .filter(|v| any_minor || v.id.series() == id.series())
+            .max_by(|a, b| a.id.cmp(&b.id))
+    }
+
+    /// Finds up to five versions that are close to `input`, for use in error messages
+    ///
+    /// Versions starting with `input` are preferred, followed by the smallest edit distance.
+    /// Ties are broken by release time, oldest first
+    pub fn suggest(&self, input: &str) -> Vec<&VersionNumber> {
+        let threshold = (input.len() / 3).max(2);
+
+        self.versions
+            .iter()
+            .filter_map(|v| {
+                let id = v.id.to_string();
+                let prefix = id.starts_with(input);
+                let distance = strsim::levenshtein(input, &id);
+                (prefix || distance <= threshold).then_some((!prefix, distance, v))
+            })
+            .sorted_by_key(|&(not_prefix, distance, v)| (not_prefix, distance, v.release_time))
+            .take(5)
+            .map(|(_, _, v)| &v.id)
+            .collect()
+    }
+}
+
 impl Iterator for GameVersionList {
     type Item = GameVersion;
 
@@ -234,26 +570,191 @@ impl Iterator for GameVersionList {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct VersionDownload {
     sha1: String,
-    size: u64,
+    /// The size in bytes, or 0 if unknown
+    #[serde(default)]
+    pub size: u64,
     pub url: String,
 }
 
+impl VersionDownload {
+    /// The expected SHA-1 of the download, as a hex string
+    pub fn sha1(&self) -> &str {
+        &self.sha1
+    }
+
+    pub fn checksum(&self) -> Result<Checksum> {
+        Checksum::new(ChecksumAlgo::Sha1, &self.sha1)
+    }
+}
+
+/// A library the client needs on its classpath
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Library {
+    pub name: String,
+    #[serde(default)]
+    downloads: LibraryDownloads,
+    /// Which operating systems the library is for. Without rules, it's for all of them
+    #[serde(default)]
+    rules: Vec<LibraryRule>,
+}
+
+impl Library {
+    /// The library's jar, if there is one and it's needed on this OS
+    ///
+    /// Natives for old versions are published as classifiers instead, and aren't included
+    pub fn artifact(&self) -> Option<&LibraryArtifact> {
+        let os = match std::env::consts::OS {
+            "macos" => "osx",
+            os => os,
+        };
+
+        self.applies_to(os)
+            .then_some(self.downloads.artifact.as_ref())
+            .flatten()
+    }
+
+    /// Whether the library's rules allow it on `os`, using Mojang's OS names
+    fn applies_to(&self, os: &str) -> bool {
+        if self.rules.is_empty() {
+            return true;
+        }
+
+        // like the launcher, the last matching rule wins
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| match &rule.os {
+                Some(RuleOs { name: Some(name) }) => name == os,
+                _ => true,
+            })
+            .is_some_and(|rule| rule.action == RuleAction::Allow)
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LibraryDownloads {
+    artifact: Option<LibraryArtifact>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct LibraryArtifact {
+    /// Where the jar goes, relative to the libraries directory
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub download: VersionDownload,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LibraryRule {
+    action: RuleAction,
+    os: Option<RuleOs>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RuleAction {
+    Allow,
+    Disallow,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RuleOs {
+    name: Option<String>,
+}
+
+/// The index of the game's assets, which launchers use to download them
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct AssetIndex {
+    pub id: String,
+    /// The size in bytes of all the assets in the index, or 0 if unknown
+    #[serde(rename = "totalSize", default)]
+    pub total_size: u64,
+    #[serde(flatten)]
+    pub download: VersionDownload,
+}
+
+/// The contents of an asset index, i.e. the assets of a version by name
+#[derive(Debug, Deserialize)]
+pub(crate) struct AssetIndexContents {
+    pub objects: BTreeMap<String, AssetObject>,
+}
+
+/// A file in the asset store, which is addressed by its SHA-1
+#[derive(Debug, Deserialize)]
+pub(crate) struct AssetObject {
+    hash: String,
+    pub size: u64,
+}
+
+impl AssetObject {
+    const BASE_URL: &'static str = "https://resources.download.minecraft.net/";
+
+    /// The expected SHA-1 of the object, which is also its key in the store
+    pub fn checksum(&self) -> Result<Checksum> {
+        Checksum::new(ChecksumAlgo::Sha1, &self.hash)
+    }
+
+    /// Where the object is stored relative to the objects directory, e.g. `ab/abcdef...`
+    ///
+    /// Fails if the hash isn't valid, so an index can't place files outside the store
+    pub fn path(&self) -> Result<PathBuf> {
+        let hash = self.checksum()?.value;
+        Ok(PathBuf::from(&hash[..2]).join(&hash))
+    }
+
+    pub fn url(&self) -> Result<String> {
+        let hash = self.checksum()?.value;
+        Ok(format!("{}{}/{hash}", Self::BASE_URL, &hash[..2]))
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct JavaVersionInfo {
+    #[serde(default)]
     component: String,
     #[serde(rename = "majorVersion")]
     pub major_version: u8,
+    /// Whether the version doesn't specify a Java version, so this is the default
+    ///
+    /// Never in Mojang's metadata, but kept when the metadata is cached
+    #[serde(default)]
+    pub assumed: bool,
+}
+
+impl Default for JavaVersionInfo {
+    /// What the launcher assumes for versions that don't specify one
+    fn default() -> Self {
+        Self {
+            component: "jre-legacy".to_string(),
+            major_version: 8,
+            assumed: true,
+        }
+    }
 }
 
+/// The metadata of a single version
+///
+/// Only the fields that are used are deserialized. Anything that isn't present for every version
+/// (e.g. old alphas have no server download) must have a default, so new or old versions don't
+/// fail to parse
 #[derive(Debug, Serialize, Deserialize)]
 pub(crate) struct VersionMetadata {
+    #[serde(default)]
     pub downloads: HashMap<String, VersionDownload>, // client, server, windows_server (legacy) + mappings
     pub id: VersionNumber,
-    #[serde(rename = "javaVersion")]
+    #[serde(rename = "javaVersion", default)]
     pub java_version: JavaVersionInfo,
+    /// Only used when installing a client
+    #[serde(default)]
+    pub libraries: Vec<Library>,
+    #[serde(rename = "assetIndex", default)]
+    pub asset_index: Option<AssetIndex>,
+    /// The name launchers know the asset index by, usually the same as its ID
+    #[serde(default)]
+    assets: Option<String>,
     // the rest of the fields are not used
 
     // time: DateTime<FixedOffset>,
@@ -263,6 +764,36 @@ pub(crate) struct VersionMetadata {
     // release_type: String,
 }
 
+impl VersionMetadata {
+    /// The downloads and their kinds, largest first. Ties are ordered by kind
+    pub fn downloads_by_size(&self) -> Vec<(&String, &VersionDownload)> {
+        self.downloads
+            .iter()
+            .sorted_by(|(a_kind, a), (b_kind, b)| b.size.cmp(&a.size).then(a_kind.cmp(b_kind)))
+            .collect()
+    }
+
+    /// The name of the asset index file, without `.json`, if the version has one
+    pub fn assets_id(&self) -> Option<&str> {
+        self.assets
+            .as_deref()
+            .or(self.asset_index.as_ref().map(|index| index.id.as_str()))
+    }
+
+    /// Why there is no `kind` (`server` or `client`) jar to download, or `None` if there is one
+    ///
+    /// The oldest versions have no downloads at all, and some later ones only have a client
+    pub fn missing_jar_reason(&self, kind: &str) -> Option<String> {
+        if self.downloads.contains_key(kind) {
+            None
+        } else if self.downloads.is_empty() {
+            Some(format!("this version predates downloadable {kind} jars"))
+        } else {
+            Some(format!("no {kind} jar is published for this version"))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -396,6 +927,479 @@ mod tests {
         );
 
         let v: VersionNumber = serde_json::from_str(r#""3D Shareware v1.34""#).unwrap();
-        assert_eq!(v, VersionNumber::Other("3D Shareware v1.34".to_string()));
+        assert_eq!(
+            v,
+            VersionNumber::Other("3D Shareware v1.34".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn experimental_snapshots() {
+        for id in [
+            "1.18_experimental-snapshot-1",
+            "1.19_deep_dark_experimental_snapshot-1",
+            "1.20-experimental-snapshot-2",
+        ] {
+            let v: VersionNumber = id.parse().unwrap();
+            assert!(v.is_other() && v.is_experimental(), "{id}");
+        }
+
+        for id in ["1.18", "21w37a", "3D Shareware v1.34", "1.14_combat-212796"] {
+            let v: VersionNumber = id.parse().unwrap();
+            assert!(!v.is_experimental(), "{id}");
+        }
+    }
+
+    fn manifest() -> GameVersionList {
+        let versions = ["1.20.2", "1.20.1", "1.20-pre1", "1.19.4", "23w14a", "1.2.5"]
+            .iter()
+            .enumerate()
+            .map(|(i, id)| {
+                serde_json::from_value(serde_json::json!({
+                    "id": id,
+                    "type": "release",
+                    "url": "",
+                    "time": format!("2023-01-{:02}T00:00:00+00:00", 20 - i),
+                    "releaseTime": format!("2023-01-{:02}T00:00:00+00:00", 20 - i),
+                }))
+                .unwrap()
+            })
+            .collect();
+
+        GameVersionList {
+            latest: LatestVersions {
+                release: "1.20.2".parse().unwrap(),
+                snapshot: "23w14a".parse().unwrap(),
+            },
+            versions,
+        }
+    }
+
+    #[test]
+    fn suggest_close_versions() {
+        let list = manifest();
+
+        let suggestions = list
+            .suggest("1.20")
+            .iter()
+            .map(|v| v.to_string())
+            .collect_vec();
+        assert_eq!(suggestions, vec!["1.20.1", "1.20.2", "1.20-pre1", "1.2.5"]);
+
+        assert!(list.suggest("nonsense").is_empty());
+    }
+
+    #[test]
+    fn resolve_version_number() {
+        let list = manifest();
+
+        let version: VersionNumber = "1.19.4".parse().unwrap();
+        assert_eq!(version.resolve(&list).unwrap().id, version);
+
+        let version: VersionNumber = "1.20.3".parse().unwrap();
+        let err = version.resolve(&list).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Version `1.20.3` does not exist; did you mean 1.20.1, 1.20.2, 1.2.5?"
+        );
+    }
+
+    #[test]
+    fn resolve_latest_aliases() {
+        let mut list = manifest();
+
+        for (alias, expected) in [
+            ("latest", "1.20.2"),
+            ("latest-snapshot", "23w14a"),
+            ("latest-prerelease", "1.20-pre1"),
+            ("latest-any", "1.20.2"),
+        ] {
+            let alias: VersionNumber = alias.parse().unwrap();
+            assert_eq!(alias.resolve(&list).unwrap().id.to_string(), expected);
+        }
+
+        list.versions.retain(|v| !v.id.is_pre_release());
+        let alias: VersionNumber = "latest-prerelease".parse().unwrap();
+        assert_eq!(
+            alias.resolve(&list).unwrap_err().to_string(),
+            "There are no pre-releases or release candidates"
+        );
+    }
+
+    #[test]
+    fn find_newer_release() {
+        let list = manifest();
+        let newer = |id: &str, any_minor| {
+            list.newer_release(&id.parse().unwrap(), any_minor)
+                .map(|v| v.id.to_string())
+        };
+
+        assert_eq!(newer("1.20.1", false).as_deref(), Some("1.20.2"));
+        assert_eq!(newer("1.20.2", false), None);
+        assert_eq!(newer("1.19.4", false), None);
+        assert_eq!(newer("1.19.4", true).as_deref(), Some("1.20.2"));
+        assert_eq!(newer("1.2.5", true).as_deref(), Some("1.20.2"));
+        // not on a release line
+        assert_eq!(newer("1.20-pre1", true), None);
+        assert_eq!(newer("23w14a", true), None);
+    }
+
+    #[test]
+    fn resolve_equivalent_release() {
+        let mut list = manifest();
+        list.versions.push(
+            serde_json::from_value(serde_json::json!({
+                "id": "1.0",
+                "type": "release",
+                "url": "",
+                "time": "2011-11-18T00:00:00+00:00",
+                "releaseTime": "2011-11-18T00:00:00+00:00",
+            }))
+            .unwrap(),
+        );
+
+        let version: VersionNumber = "1.0.0".parse().unwrap();
+        assert_eq!(version.resolve(&list).unwrap().id.to_string(), "1.0");
+
+        let version: VersionNumber = "1.0".parse().unwrap();
+        assert_eq!(version, "1.0.0".parse().unwrap());
+        assert_eq!(version.resolve(&list).unwrap().id, version);
+    }
+
+    #[test]
+    fn deserialize_manifest_checksum() {
+        let version: GameVersion = serde_json::from_value(serde_json::json!({
+            "id": "1.19.4",
+            "type": "release",
+            "url": "https://piston-meta.mojang.com/v1/packages/5cf4a7d1/1.19.4.json",
+            "time": "2023-03-14T12:56:18+00:00",
+            "releaseTime": "2023-03-14T12:56:18+00:00",
+            "sha1": "5CF4A7D1E5E5B8E5A1F3C6C0B6B8D4A7E1A2B3C4",
+            "complianceLevel": 1,
+        }))
+        .unwrap();
+
+        assert_eq!(
+            version.checksum().unwrap().unwrap().to_string(),
+            "sha1:5cf4a7d1e5e5b8e5a1f3c6c0b6b8d4a7e1a2b3c4"
+        );
+        assert_eq!(version.compliance_level, Some(1));
+
+        let version: GameVersion = serde_json::from_value(serde_json::json!({
+            "id": "1.19.4",
+            "type": "release",
+            "url": "",
+            "time": "2023-03-14T12:56:18+00:00",
+            "releaseTime": "2023-03-14T12:56:18+00:00",
+        }))
+        .unwrap();
+        assert_eq!(version.checksum().unwrap(), None);
+    }
+
+    #[test]
+    fn deserialize_minimal_metadata() {
+        // an old alpha with extra fields, no server download and no Java version
+        let metadata: VersionMetadata = serde_json::from_value(serde_json::json!({
+            "id": "rd-132211",
+            "type": "old_alpha",
+            "complianceLevel": 0,
+            "downloads": {
+                "client": {
+                    "sha1": "393e8d4b4d708587e2accd7c5221db65365e1075",
+                    "url": "https://piston-data.mojang.com/v1/objects/393e8d4b4d708587e2accd7c5221db65365e1075/client.jar"
+                }
+            },
+            "someFutureField": {"nested": [1, 2, 3]},
+        }))
+        .unwrap();
+
+        assert!(metadata.id.is_other());
+        assert_eq!(metadata.java_version.major_version, 8);
+        assert!(metadata.java_version.assumed);
+        assert!(metadata.downloads.contains_key("client"));
+        assert!(!metadata.downloads.contains_key("server"));
+        assert_eq!(
+            metadata.missing_jar_reason("server").as_deref(),
+            Some("no server jar is published for this version")
+        );
+
+        let metadata: VersionMetadata =
+            serde_json::from_value(serde_json::json!({ "id": "1.0" })).unwrap();
+        assert!(metadata.downloads.is_empty());
+    }
+
+    #[test]
+    fn deserialize_metadata_without_downloads() {
+        // old alpha and beta packages have an empty map, or none at all
+        for metadata in [
+            serde_json::json!({ "id": "a1.0.4", "type": "old_alpha", "downloads": {} }),
+            serde_json::json!({ "id": "b1.7.3", "type": "old_beta" }),
+        ] {
+            let metadata: VersionMetadata = serde_json::from_value(metadata).unwrap();
+            assert!(metadata.downloads.is_empty());
+            assert!(metadata.downloads_by_size().is_empty());
+            assert_eq!(
+                metadata.missing_jar_reason("server").as_deref(),
+                Some("this version predates downloadable server jars")
+            );
+        }
+    }
+
+    #[test]
+    fn java_version_assumed() {
+        let metadata: VersionMetadata = serde_json::from_value(serde_json::json!({
+            "id": "1.20.1",
+            "javaVersion": { "component": "java-runtime-gamma", "majorVersion": 17 },
+        }))
+        .unwrap();
+        assert_eq!(metadata.java_version.major_version, 17);
+        assert!(!metadata.java_version.assumed);
+
+        let metadata: VersionMetadata =
+            serde_json::from_value(serde_json::json!({ "id": "1.0" })).unwrap();
+        let cached: VersionMetadata =
+            rmp_serde::from_slice(&rmp_serde::to_vec(&metadata).unwrap()).unwrap();
+        assert_eq!(cached.java_version.major_version, 8);
+        assert!(cached.java_version.assumed);
+    }
+
+    #[test]
+    fn library_rules() {
+        let library = |rules| -> Library {
+            serde_json::from_value(serde_json::json!({
+                "name": "org.lwjgl:lwjgl:3.3.1",
+                "downloads": {
+                    "artifact": {
+                        "path": "org/lwjgl/lwjgl/3.3.1/lwjgl-3.3.1.jar",
+                        "sha1": "ae58664f88e18a9bb2c77b063833ca7aaec484cb",
+                        "size": 724243,
+                        "url": "https://libraries.minecraft.net/org/lwjgl/lwjgl/3.3.1/lwjgl-3.3.1.jar"
+                    }
+                },
+                "rules": rules,
+            }))
+            .unwrap()
+        };
+
+        let everywhere = library(serde_json::json!([]));
+        assert!(everywhere.applies_to("linux"));
+        assert!(everywhere.artifact().is_some());
+
+        let only_osx = library(serde_json::json!([
+            { "action": "allow", "os": { "name": "osx" } }
+        ]));
+        assert!(only_osx.applies_to("osx"));
+        assert!(!only_osx.applies_to("linux"));
+
+        // the last matching rule wins
+        let not_osx = library(serde_json::json!([
+            { "action": "allow" },
+            { "action": "disallow", "os": { "name": "osx" } }
+        ]));
+        assert!(!not_osx.applies_to("osx"));
+        assert!(not_osx.applies_to("windows"));
+    }
+
+    #[test]
+    fn asset_object_location() {
+        let index: AssetIndexContents = serde_json::from_value(serde_json::json!({
+            "objects": {
+                "minecraft/sounds/ambient/cave/cave1.ogg": {
+                    "hash": "BDF48EF6B5D0D23BBB02E17D04865216179F510A",
+                    "size": 20042
+                },
+                "../escape": { "hash": "../../../../../../../../../../etc/passwd", "size": 1 }
+            }
+        }))
+        .unwrap();
+
+        let sound = &index.objects["minecraft/sounds/ambient/cave/cave1.ogg"];
+        assert_eq!(
+            sound.path().unwrap(),
+            PathBuf::from("bd").join("bdf48ef6b5d0d23bbb02e17d04865216179f510a")
+        );
+        assert_eq!(
+            sound.url().unwrap(),
+            "https://resources.download.minecraft.net/bd/bdf48ef6b5d0d23bbb02e17d04865216179f510a"
+        );
+
+        let escape = &index.objects["../escape"];
+        assert!(escape.path().is_err());
+        assert!(escape.url().is_err());
+    }
+
+    #[test]
+    fn cache_metadata_with_libraries() {
+        let metadata: VersionMetadata = serde_json::from_value(serde_json::json!({
+            "id": "1.20.1",
+            "libraries": [{
+                "name": "com.mojang:logging:1.1.1",
+                "downloads": {
+                    "artifact": {
+                        "path": "com/mojang/logging/1.1.1/logging-1.1.1.jar",
+                        "sha1": "832b8e6674a9b325a5175a3a6267dfaf34c85139",
+                        "size": 15343,
+                        "url": "https://libraries.minecraft.net/com/mojang/logging/1.1.1/logging-1.1.1.jar"
+                    }
+                }
+            }],
+            "assetIndex": {
+                "id": "5",
+                "sha1": "ec5a9d5e2c5e6b2b3cdd2dba3f5b4a5c0a7d1f0e",
+                "size": 414329,
+                "totalSize": 616012929,
+                "url": "https://piston-meta.mojang.com/v1/packages/ec5a9d5e2c5e6b2b3cdd2dba3f5b4a5c0a7d1f0e/5.json"
+            },
+        }))
+        .unwrap();
+
+        // flattened fields are stored as maps, so check they survive the cache
+        let cached: VersionMetadata =
+            rmp_serde::from_slice(&rmp_serde::to_vec(&metadata).unwrap()).unwrap();
+        let artifact = cached.libraries[0].artifact().unwrap();
+        assert_eq!(
+            artifact.path,
+            PathBuf::from("com/mojang/logging/1.1.1/logging-1.1.1.jar")
+        );
+        assert_eq!(artifact.download.size, 15343);
+        let index = cached.asset_index.unwrap();
+        assert_eq!(index.id, "5");
+        assert_eq!(index.download.size, 414_329);
+    }
+
+    #[test]
+    fn sort_downloads_by_size() {
+        let download = |size| {
+            serde_json::json!({
+                "sha1": "393e8d4b4d708587e2accd7c5221db65365e1075",
+                "size": size,
+                "url": "",
+            })
+        };
+        let metadata: VersionMetadata = serde_json::from_value(serde_json::json!({
+            "id": "1.19.4",
+            "downloads": {
+                "client_mappings": download(8_000_000),
+                "client": download(23_000_000),
+                "server_mappings": download(6_000_000),
+                "server": download(47_000_000),
+                "windows_server": download(6_000_000),
+            },
+        }))
+        .unwrap();
+
+        let kinds = metadata
+            .downloads_by_size()
+            .into_iter()
+            .map(|(kind, _)| kind.as_str())
+            .collect_vec();
+        assert_eq!(
+            kinds,
+            [
+                "server",
+                "client",
+                "client_mappings",
+                "server_mappings",
+                "windows_server"
+            ]
+        );
+    }
+
+    #[test]
+    fn group_versions_by_series() {
+        let versions: Vec<VersionNumber> = [
+            "1.19.4",
+            "23w14a",
+            "1.20-pre1",
+            "1.20",
+            "1.20.1-rc1",
+            "1.20.1",
+            "1.20.2-pre1",
+            "b1.8.1",
+        ]
+        .iter()
+        .map(|v| v.parse().unwrap())
+        .collect();
+        let v = |s: &str| versions.iter().find(|v| v.to_string() == s).unwrap();
+
+        let tree = group_versions(&versions);
+        assert_eq!(
+            tree.iter().map(|(s, _)| s.to_string()).collect_vec(),
+            vec!["1.19", "1.20", "Snapshots 2023", "Other"]
+        );
+
+        let (_, minor) = &tree[1];
+        assert_eq!(
+            minor,
+            &vec![
+                VersionTreeNode {
+                    version: v("1.20"),
+                    pre_releases: vec![v("1.20-pre1")],
+                },
+                VersionTreeNode {
+                    version: v("1.20.1"),
+                    pre_releases: vec![v("1.20.1-rc1")],
+                },
+                // no release yet, so not nested
+                VersionTreeNode {
+                    version: v("1.20.2-pre1"),
+                    pre_releases: vec![],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn april_fools_snapshot_order() {
+        let v: VersionNumber = "22w13oneblockatatime".parse().unwrap();
+        assert!(v.is_other());
+        assert_eq!(v.series(), VersionSeries::Snapshots(22));
+        assert_eq!(
+            serde_json::to_string(&v).unwrap(),
+            r#""22w13oneblockatatime""#
+        );
+
+        let sorted = [
+            "3D Shareware v1.34",
+            "22w14a",
+            "22w13oneblockatatime",
+            "1.19",
+            "22w13a",
+            "20w14infinite",
+            "22w12a",
+        ]
+        .iter()
+        .map(|v| v.parse::<VersionNumber>().unwrap())
+        .sorted()
+        .map(|v| v.to_string())
+        .collect_vec();
+        assert_eq!(
+            sorted,
+            [
+                "1.19",
+                "20w14infinite",
+                "22w12a",
+                "22w13a",
+                "22w13oneblockatatime",
+                "22w14a",
+                "3D Shareware v1.34"
+            ]
+        );
+    }
+
+    #[test]
+    fn version_number_hash_set() {
+        let versions: HashSet<VersionNumber> =
+            ["1.16.4", "1.16.4-pre1", "20w45a", "3D Shareware v1.34"]
+                .iter()
+                .map(|v| v.parse().unwrap())
+                .collect();
+        assert_eq!(versions.len(), 4);
+
+        assert!(versions.contains(&VersionNumber::Release(ReleaseVersion::new(1, 16, 4))));
+        assert!(versions.contains(&"1.16.4-pre1".parse().unwrap()));
+        assert!(versions.contains(&"20w45a".parse().unwrap()));
+        assert!(versions.contains(&VersionNumber::Other("3D Shareware v1.34".parse().unwrap())));
+        assert!(!versions.contains(&"1.16.5".parse().unwrap()));
     }
 }
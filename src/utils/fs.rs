@@ -0,0 +1,97 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use color_eyre::eyre::{Result, WrapErr};
+use tracing::{debug, instrument};
+
+/// Recursively copies the contents of `from` into `to`, creating `to` if necessary
+///
+/// Links to directories are copied as links, so e.g. a linked world stays shared
+pub(crate) fn copy_dir_all(from: &Path, to: &Path) -> Result<()> {
+    copy_dir_except(from, to, &[])
+}
+
+/// Like [`copy_dir_all`], but skips the entries directly in `from` named in `except`
+#[instrument(err, ret(level = "debug"))]
+pub(crate) fn copy_dir_except(from: &Path, to: &Path, except: &[&OsStr]) -> Result<()> {
+    std::fs::create_dir_all(to).wrap_err(format!("Failed to create directory {}", to.display()))?;
+
+    for entry in
+        std::fs::read_dir(from).wrap_err(format!("Failed to read directory {}", from.display()))?
+    {
+        let entry = entry?;
+        if except.contains(&entry.file_name().as_os_str()) {
+            debug!(path = ?entry.path(), "Skipping");
+            continue;
+        }
+        let target = to.join(entry.file_name());
+
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() && entry.path().is_dir() {
+            let original = std::fs::read_link(entry.path())
+                .wrap_err(format!("Failed to read link {}", entry.path().display()))?;
+            symlink_dir(&original, &target)?;
+        } else if file_type.is_dir() {
+            copy_dir_all(&entry.path(), &target)?;
+        } else {
+            debug!(from = ?entry.path(), to = ?target, "Copying file");
+            std::fs::copy(entry.path(), &target).wrap_err(format!(
+                "Failed to copy {} to {}",
+                entry.path().display(),
+                target.display()
+            ))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Creates a symbolic link at `link` pointing to the directory `original`
+#[instrument(err, ret(level = "debug"))]
+pub(crate) fn symlink_dir(original: &Path, link: &Path) -> Result<()> {
+    #[cfg(unix)]
+    let result = std::os::unix::fs::symlink(original, link);
+    #[cfg(windows)]
+    let result = std::os::windows::fs::symlink_dir(original, link);
+
+    result.wrap_err(format!(
+        "Failed to link {} to {}",
+        link.display(),
+        original.display()
+    ))
+}
+
+/// Returns the total size of the files under `path`, in bytes
+///
+/// Symbolic links are not followed, and anything that can't be read is skipped
+pub(crate) fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(t) if t.is_dir() => dir_size(&entry.path()),
+            Ok(t) if t.is_file() => entry.metadata().map(|m| m.len()).unwrap_or(0),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// A path next to `path` to write to before renaming it into place
+///
+/// Each call returns a new path, so processes and threads writing the same file at the same time
+/// don't write over each other's partial files
+pub(crate) fn temp_path(path: &Path) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(format!(
+        ".{}-{}.partial",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    PathBuf::from(temp)
+}
@@ -1,2 +1,4 @@
+pub(crate) mod fs;
 pub(crate) mod macros;
 pub(crate) mod net;
+pub(crate) mod progress;
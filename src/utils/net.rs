@@ -1,25 +1,36 @@
-use std::path::PathBuf;
-use std::time::{Duration, SystemTime};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
 
-use bytes::Bytes;
-use color_eyre::eyre::{eyre, Result};
+use bytes::{Bytes, BytesMut};
+use color_eyre::eyre::{eyre, Result, WrapErr};
 use lazy_static::lazy_static;
-use reqwest::StatusCode;
+use reqwest::header::{CONTENT_TYPE, LOCATION};
+use reqwest::{Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use tracing::{debug, instrument};
+use sha1::{Digest, Sha1};
+use tracing::field::Empty;
+use tracing::{debug, instrument, warn, Span};
 
-use crate::common::{PROJ_DIRS, REQWEST_CLIENT};
-use crate::types::net::CachedResponse;
+use crate::common::{CONFIG, PROJ_DIRS, REQWEST_CLIENT, REQWEST_CLIENT_NO_REDIRECT};
+use crate::types::adoptium::{Asset, AvailableReleases, Platform};
+use crate::types::checksum::{verify, Checksum, ChecksumAlgo};
+use crate::types::net::{CachedResponse, ProbeError};
 use crate::types::version::{GameVersion, GameVersionList, VersionMetadata};
+use crate::utils::progress::Progress;
 
 lazy_static! {
     static ref CACHE_BASE_DIR: PathBuf = PROJ_DIRS.cache_dir().to_path_buf();
 }
 
 const PISTON_API_URL: &str = "https://piston-meta.mojang.com/";
-const FABRIC_API_URL: &str = "https://meta.fabricmc.net/";
+const ADOPTIUM_API_URL: &str = "https://api.adoptium.net/";
 
-const CACHE_EXPIRATION_TIME: u64 = 60 * 10; // 10 minutes
+/// How much of an unexpected response body to include in errors
+const BODY_PREVIEW_LEN: usize = 256;
+
+/// How many redirects to follow before giving up
+const MAX_REDIRECTS: usize = 10;
 
 #[inline]
 fn api_path(path: &str) -> String {
@@ -27,28 +38,123 @@ fn api_path(path: &str) -> String {
 }
 
 #[inline]
-fn fabric_api_path(path: &str) -> String {
-    format!("{FABRIC_API_URL}{path}")
+fn adoptium_api_path(path: &str) -> String {
+    format!("{ADOPTIUM_API_URL}{path}")
+}
+
+/// The URL of the version manifest, which is Mojang's unless `manifest_url` is set
+fn manifest_url() -> String {
+    CONFIG
+        .read()
+        .manifest_url
+        .clone()
+        .unwrap_or_else(|| api_path("mc/game/version_manifest_v2.json"))
 }
 
 #[instrument(err)]
 pub(crate) async fn get_version_manifest() -> Result<GameVersionList> {
-    let cache_file = CACHE_BASE_DIR.join("manifest.mpk");
+    let manifest_file = CONFIG.read().manifest_file.clone();
+    if let Some(path) = manifest_file {
+        return read_version_manifest(&path).await;
+    }
+
+    // mirrors are cached apart from Mojang's manifest and each other
+    let cache_file = match &CONFIG.read().manifest_url {
+        Some(url) => CACHE_BASE_DIR.join(format!(
+            "manifest_{}.mpk",
+            &hex::encode(Sha1::digest(url))[..16]
+        )),
+        None => CACHE_BASE_DIR.join("manifest.mpk"),
+    };
+
+    get_maybe_cached(&manifest_url(), &cache_file, None).await
+}
+
+/// Reads a version manifest saved from the Mojang API, bypassing the cache
+#[instrument(err)]
+async fn read_version_manifest(path: &Path) -> Result<GameVersionList> {
+    let body = tokio::fs::read(path)
+        .await
+        .wrap_err(format!("Failed to read manifest file {}", path.display()))?;
 
-    get_maybe_cached(&api_path("mc/game/version_manifest.json"), &cache_file).await
+    parse_json(&body, None).wrap_err(format!("Invalid manifest file {}", path.display()))
 }
 
 #[instrument(err, skip(version), fields(version = %version.id))]
 pub(crate) async fn get_version_metadata(version: &GameVersion) -> Result<VersionMetadata> {
     let cache_file = CACHE_BASE_DIR.join(format!("{}.mpk", version.id));
 
-    get_maybe_cached(&version.url, &cache_file).await
+    // old cached manifests don't have checksums, in which case there is nothing to verify
+    let checksum = version.checksum()?;
+    get_maybe_cached(&version.url, &cache_file, checksum.as_ref()).await
+}
+
+#[instrument(err)]
+pub(crate) async fn get_available_releases() -> Result<AvailableReleases> {
+    let cache_file = CACHE_BASE_DIR.join("adoptium_releases.mpk");
+
+    get_maybe_cached(
+        &adoptium_api_path("v3/info/available_releases"),
+        &cache_file,
+        None,
+    )
+    .await
 }
 
-#[instrument(err)] // ret is huge
-pub(crate) async fn get_maybe_cached<T>(url: &str, cache_file: &PathBuf) -> Result<T>
-where T: Serialize + for<'de> Deserialize<'de> {
-    if let Ok(cached) = CachedResponse::<T>::from_file(&cache_file).await {
+/// Gets the latest builds of a Java version for every platform Adoptium serves it on
+#[instrument(err, skip_all, fields(major_version))]
+pub(crate) async fn get_jre_assets(major_version: u8) -> Result<Vec<Asset>> {
+    let vendor = CONFIG.read().jre_vendor.clone();
+    get_available_releases()
+        .await?
+        .check(major_version, &vendor)?;
+
+    let cache_file = CACHE_BASE_DIR.join(format!("adoptium_assets_{vendor}_{major_version}.mpk"));
+
+    get_maybe_cached(
+        &adoptium_api_path(&format!(
+            "v3/assets/latest/{major_version}/hotspot?vendor={vendor}"
+        )),
+        &cache_file,
+        None,
+    )
+    .await
+}
+
+/// Gets a JSON response from `url`, or from `cache_file` if it hasn't expired
+///
+/// If `checksum` is given, a fresh response is verified against it before being parsed or cached.
+/// With `--no-cache`, the cache is neither read nor written
+pub(crate) async fn get_maybe_cached<T>(
+    url: &str,
+    cache_file: &Path,
+    checksum: Option<&Checksum>,
+) -> Result<T>
+where
+    T: Serialize + for<'de> Deserialize<'de>,
+{
+    let cache_file = (!CONFIG.read().no_cache).then_some(cache_file);
+    get_json(url, cache_file, checksum).await
+}
+
+/// Gets a JSON response from `url`, reading and writing `cache_file` if one is given
+#[instrument(err, fields(cache_hit = Empty, status = Empty, elapsed_ms = Empty))] // ret is huge
+async fn get_json<T>(
+    url: &str,
+    cache_file: Option<&Path>,
+    checksum: Option<&Checksum>,
+) -> Result<T>
+where
+    T: Serialize + for<'de> Deserialize<'de>,
+{
+    let span = Span::current();
+    let start = Instant::now();
+
+    let cached = match cache_file {
+        Some(cache_file) => CachedResponse::<T>::from_file(cache_file).await.ok(),
+        None => None,
+    };
+    if let Some(cached) = cached {
         if !cached.is_expired() {
             let mut msg = "Using cached response".to_string();
             if let Ok(elapsed) = cached.expires.duration_since(SystemTime::now()) {
@@ -58,49 +164,282 @@ where T: Serialize + for<'de> Deserialize<'de> {
                     " expiring in {minutes:02}:{seconds:02}.{milis:03}"
                 ));
             }
+            span.record("cache_hit", true);
+            span.record("elapsed_ms", start.elapsed().as_millis());
             debug!("{msg}");
             return Ok(cached.data);
         }
     }
+    span.record("cache_hit", false);
 
     debug!("Downloading fresh data");
-    let response: T = REQWEST_CLIENT.get(url).send().await?.json().await?;
+    let response = REQWEST_CLIENT.get(url).send().await?;
+    span.record("status", response.status().as_u16());
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(String::from);
+    let body = response.bytes().await?;
+    span.record("elapsed_ms", start.elapsed().as_millis());
+    debug!(bytes = body.len(), "Downloaded response");
 
-    let cached_response = CachedResponse::new(
-        &response,
-        SystemTime::now() + Duration::from_secs(CACHE_EXPIRATION_TIME),
-    );
-    cached_response.save(&cache_file).await?;
-    debug!("Saved cached response");
+    if let Some(checksum) = checksum {
+        verify(&body, checksum).wrap_err(format!(
+            "Response from {url} is corrupted or has been tampered with"
+        ))?;
+    }
+
+    let response: T = parse_json(&body, content_type.as_deref())?;
+
+    if let Some(cache_file) = cache_file {
+        let cached_response = CachedResponse::new(
+            &response,
+            SystemTime::now() + Duration::from_secs(CONFIG.read().cache_ttl),
+        );
+        cached_response.save(cache_file).await?;
+        debug!("Saved cached response");
+    }
 
     Ok(response)
 }
 
+/// Deserializes a JSON response body
+///
+/// If the body is not what was expected (e.g. an HTML error page),
+/// the error includes the content type and the start of the body
+fn parse_json<T: DeserializeOwned>(body: &[u8], content_type: Option<&str>) -> Result<T> {
+    serde_json::from_slice(body).map_err(|e| {
+        let preview = String::from_utf8_lossy(&body[..body.len().min(BODY_PREVIEW_LEN)]);
+        let ellipsis = if body.len() > BODY_PREVIEW_LEN {
+            "..."
+        } else {
+            ""
+        };
+
+        eyre!(e).wrap_err(format!(
+            "Failed to deserialize response (content-type: {}, {} bytes): {preview:?}{ellipsis}",
+            content_type.unwrap_or("none"),
+            body.len(),
+        ))
+    })
+}
+
+/// Sends a GET request to `url`, returning how long the response took to start
+///
+/// Unlike downloads, the whole request is limited to `timeout`, and the body is never read
+#[instrument(ret(level = "debug"))]
+pub(crate) async fn probe(url: &str, timeout: Duration) -> Result<Duration, ProbeError> {
+    let start = Instant::now();
+    let response = REQWEST_CLIENT.get(url).timeout(timeout).send().await?;
+    let elapsed = start.elapsed();
+
+    match response.status() {
+        status if status.is_success() => Ok(elapsed),
+        status => Err(ProbeError::Status(status)),
+    }
+}
+
+/// Probes each service mcdl downloads from at the same time, returning their names, URLs and
+/// results
+pub(crate) async fn probe_services(
+    timeout: Duration,
+) -> Vec<(&'static str, String, Result<Duration, ProbeError>)> {
+    let mojang = manifest_url();
+    let adoptium = adoptium_api_path("v3/info/available_releases");
+
+    let (mojang_result, adoptium_result) =
+        tokio::join!(probe(&mojang, timeout), probe(&adoptium, timeout));
+
+    vec![
+        ("Mojang", mojang, mojang_result),
+        ("Adoptium", adoptium, adoptium_result),
+    ]
+}
+
+/// The proxy that requests to HTTPS URLs go through, if any, with its password hidden
+///
+/// This mirrors how reqwest picks a proxy from the environment
+pub(crate) fn https_proxy() -> Option<String> {
+    let proxy = ["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|v| !v.is_empty()))?;
+
+    match Url::parse(&proxy) {
+        Ok(mut url) if url.password().is_some() => {
+            let _ = url.set_password(Some("***"));
+            Some(url.to_string())
+        }
+        _ => Some(proxy),
+    }
+}
+
+/// Gets a SHA-256 checksum file in the `sha256sum` format (`<hex>  <file name>`)
 #[instrument(err)]
-pub(crate) async fn download_jre(major_version: &u8) -> Result<Bytes> {
-    let url = format!(
-        "https://api.adoptium.net/v3/binary/latest/{feature_version}/{release_type}/{os}/{arch}/{image_type}/{jvm_impl}/{heap_size}/{vendor}",
+async fn get_published_checksum(url: &str) -> Result<Checksum> {
+    let text = REQWEST_CLIENT
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let value = text
+        .split_whitespace()
+        .next()
+        .ok_or_else(|| eyre!("Checksum file is empty"))?;
+
+    Checksum::new(ChecksumAlgo::Sha256, value)
+}
+
+pub(crate) async fn download_jre(major_version: &u8, pb: &Progress) -> Result<Bytes> {
+    download_jre_for(major_version, &Platform::native(), pb).await
+}
+
+/// Downloads the JRE archive for `platform`, which is a zip on Windows and a tarball elsewhere
+///
+/// The download is shown on `pb` as it arrives
+#[instrument(err, skip(pb), fields(%platform, status = Empty, elapsed_ms = Empty))]
+pub(crate) async fn download_jre_for(
+    major_version: &u8,
+    platform: &Platform,
+    pb: &Progress,
+) -> Result<Bytes> {
+    let vendor = CONFIG.read().jre_vendor.clone();
+    get_available_releases()
+        .await?
+        .check(*major_version, &vendor)?;
+
+    let url = adoptium_api_path(&format!(
+        "v3/binary/latest/{feature_version}/{release_type}/{os}/{arch}/{image_type}/{jvm_impl}/{heap_size}/{vendor}",
         feature_version = major_version,
         release_type = "ga",
-        os = match std::env::consts::OS { "macos" => "mac", os => os },
-        arch = std::env::consts::ARCH,
+        os = platform.os,
+        arch = platform.arch,
         image_type = "jre",
         jvm_impl = "hotspot",
         heap_size = "normal",
-        vendor = "eclipse",
-    );
+    ));
+
+    // the API redirects to the binary, usually on GitHub. Where it redirects to is cached, so
+    // installing several instances with the same JRE resolves it only once
+    let cache_file =
+        CACHE_BASE_DIR.join(format!("jre_url_{vendor}_{platform}_{major_version}.mpk"));
+    let no_cache = CONFIG.read().no_cache;
+    let cached_url = match CachedResponse::<String>::from_file(&cache_file).await {
+        Ok(cached) if !cached.is_expired() && !no_cache => Some(cached.data),
+        _ => None,
+    };
 
-    debug!(url, "Downloading JRE");
-    let response = REQWEST_CLIENT.get(&url).send().await?;
+    let start = Instant::now();
+    let mut response = None;
+    if let Some(cached_url) = cached_url {
+        debug!(cached_url, "Downloading JRE from cached URL");
+        match get_following_redirects(&REQWEST_CLIENT_NO_REDIRECT, &cached_url).await {
+            Ok(r) if r.status() == StatusCode::OK => response = Some(r),
+            // e.g. the build was replaced, so ask the API again
+            Ok(r) => debug!(status = %r.status(), "Cached JRE URL failed"),
+            Err(e) => debug!("Cached JRE URL failed: {e}"),
+        }
+    }
+    let from_cache = response.is_some();
+    let response = match response {
+        Some(response) => response,
+        None => {
+            debug!(url, "Downloading JRE");
+            get_following_redirects(&REQWEST_CLIENT_NO_REDIRECT, &url).await?
+        }
+    };
+    let final_url = response.url().clone();
+    Span::current().record("status", response.status().as_u16());
 
     match response.status() {
-        StatusCode::TEMPORARY_REDIRECT | StatusCode::OK => Ok(response.bytes().await?),
+        StatusCode::OK => {
+            // only freshly resolved URLs are cached, so newer builds are picked up after the TTL
+            if !from_cache && !no_cache {
+                let cached_response = CachedResponse::new(
+                    final_url.to_string(),
+                    SystemTime::now() + Duration::from_secs(CONFIG.read().cache_ttl),
+                );
+                if let Err(e) = cached_response.save(&cache_file).await {
+                    warn!("Failed to cache JRE URL: {e}");
+                }
+            }
+
+            // the checksum is published next to the binary the API redirects to
+            let checksum_url = format!("{final_url}.sha256.txt");
+            let body = read_body(response, pb).await?;
+            Span::current().record("elapsed_ms", start.elapsed().as_millis());
+            debug!(bytes = body.len(), "Downloaded JRE");
+
+            match get_published_checksum(&checksum_url).await {
+                Ok(checksum) => verify(&body, &checksum).wrap_err("Failed to verify JRE")?,
+                Err(e) => warn!("Not verifying JRE, no checksum available: {e}"),
+            }
+
+            Ok(body)
+        }
+        status if final_url.as_str() != url => Err(eyre!(
+            "Failed to download JRE (status code {status}): {url} redirected to {final_url}"
+        )),
         StatusCode::BAD_REQUEST => Err(eyre!("Bad input parameter in URL: {url}")),
         StatusCode::NOT_FOUND => Err(eyre!("No binary found for the given parameters: {url}")),
         status => Err(eyre!("Unexpected error (status code {status}): {url}")),
     }
 }
 
+/// Reads a response body in chunks, showing how much has arrived on `pb`
+async fn read_body(mut response: Response, pb: &Progress) -> Result<Bytes> {
+    let len = response.content_length();
+    let mut body = BytesMut::with_capacity(len.unwrap_or(0) as usize);
+
+    pb.start_download(len);
+    while let Some(chunk) = response.chunk().await? {
+        pb.inc(chunk.len() as u64);
+        body.extend_from_slice(&chunk);
+    }
+    pb.end_download();
+
+    Ok(body.freeze())
+}
+
+/// Sends a GET request to `url` with a client that doesn't follow redirects, following them here
+///
+/// Returns the final response whatever its status, so [`Response::url`] is the last URL requested
+#[instrument(err, skip(client))]
+async fn get_following_redirects(client: &reqwest::Client, url: &str) -> Result<Response> {
+    let mut current = Url::parse(url)?;
+
+    for _ in 0..=MAX_REDIRECTS {
+        let response = client.get(current.clone()).send().await.wrap_err_with(|| {
+            if current.as_str() == url {
+                format!("Failed to request {url}")
+            } else {
+                format!("Failed to follow redirect from {url} to {current}")
+            }
+        })?;
+        if !response.status().is_redirection() {
+            return Ok(response);
+        }
+
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .ok_or_else(|| {
+                eyre!(
+                    "Redirect from {current} has no location (status code {})",
+                    response.status()
+                )
+            })?
+            .to_str()?;
+        let next = current.join(location)?;
+        debug!(from = %current, to = %next, status = response.status().as_u16(), "Following redirect");
+        current = next;
+    }
+
+    Err(eyre!("Too many redirects from {url}"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -114,9 +453,216 @@ mod tests {
     #[tokio::test]
     async fn test_get_version_metadata() {
         let manifest = get_version_manifest().await.unwrap();
-        let version = manifest.versions.get(0).unwrap();
+        let version = manifest.versions.first().unwrap();
         let metadata = get_version_metadata(version).await.unwrap();
-        assert!(metadata.downloads.get("server").is_some());
+        assert!(metadata.downloads.contains_key("server"));
+    }
+
+    #[test]
+    fn parse_json_not_json() {
+        let body = format!("<html>{}</html>", "a".repeat(1000));
+        let err = parse_json::<GameVersionList>(body.as_bytes(), Some("text/html")).unwrap_err();
+        let msg = err.to_string();
+
+        assert!(msg.contains("content-type: text/html"), "{msg}");
+        assert!(msg.contains("\"<html>aaa"), "{msg}");
+        assert!(msg.ends_with("...") && msg.len() < 400, "{msg}");
+    }
+
+    /// Serves `routes` (path, status, extra headers, body) on localhost, returning the base URL
+    async fn serve(routes: &'static [(&str, u16, &str, &str)]) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 4096];
+                let n = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]);
+                let path = request.split_whitespace().nth(1).unwrap_or_default();
+
+                let (status, headers, body) = routes
+                    .iter()
+                    .find(|(p, ..)| *p == path)
+                    .map_or((404, "", ""), |&(_, status, headers, body)| {
+                        (status, headers, body)
+                    });
+                let response = format!(
+                    "HTTP/1.1 {status} X\r\n{headers}Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        format!("http://{addr}")
+    }
+
+    #[tokio::test]
+    async fn follow_redirects() {
+        let base = serve(&[
+            ("/binary", 307, "Location: /jre.tar.gz\r\n", ""),
+            ("/jre.tar.gz", 200, "", "jre"),
+            ("/broken", 307, "Location: /missing\r\n", ""),
+            ("/no-location", 307, "", ""),
+            ("/loop", 307, "Location: /loop\r\n", ""),
+        ])
+        .await;
+        let client = &REQWEST_CLIENT_NO_REDIRECT;
+
+        let response = get_following_redirects(client, &format!("{base}/binary"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.url().as_str(), format!("{base}/jre.tar.gz"));
+        assert_eq!(response.bytes().await.unwrap(), "jre");
+
+        // the final status is returned, not checked
+        let response = get_following_redirects(client, &format!("{base}/broken"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.url().as_str(), format!("{base}/missing"));
+
+        let err = get_following_redirects(client, &format!("{base}/no-location"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("has no location"), "{err}");
+
+        let err = get_following_redirects(client, &format!("{base}/loop"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Too many redirects"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn verify_fresh_response() {
+        use rand::distributions::{Alphanumeric, DistString};
+
+        let base = serve(&[("/list.json", 200, "", "[1, 2, 3]")]).await;
+        let url = format!("{base}/list.json");
+
+        let suf: String = Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
+        let cache_file = std::env::temp_dir().join(format!("mcdl-cache-{suf}.mpk"));
+        scopeguard::defer! {
+            let _ = std::fs::remove_file(&cache_file);
+        }
+
+        let wrong: Checksum = format!("sha1:{}", "0".repeat(40)).parse().unwrap();
+        let err = get_maybe_cached::<Vec<u8>>(&url, &cache_file, Some(&wrong))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("tampered"), "{err}");
+        assert!(!cache_file.exists());
+
+        let right: Checksum = "sha1:a01eda32e4e0b1393274e91d1b3e9ecfc5eaba85"
+            .parse()
+            .unwrap();
+        let list = get_maybe_cached::<Vec<u8>>(&url, &cache_file, Some(&right))
+            .await
+            .unwrap();
+        assert_eq!(list, [1, 2, 3]);
+    }
+
+    #[tokio::test]
+    async fn get_json_without_cache() {
+        use rand::distributions::{Alphanumeric, DistString};
+
+        let base = serve(&[("/list.json", 200, "", "[1, 2, 3]")]).await;
+        let url = format!("{base}/list.json");
+
+        let suf: String = Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
+        let cache_file = std::env::temp_dir().join(format!("mcdl-cache-{suf}.mpk"));
+        scopeguard::defer! {
+            let _ = std::fs::remove_file(&cache_file);
+        }
+
+        // a stale entry that would otherwise be used
+        CachedResponse::new(vec![4u8], SystemTime::now() + Duration::from_secs(60))
+            .save(&cache_file)
+            .await
+            .unwrap();
+        let before = std::fs::read(&cache_file).unwrap();
+
+        let list = get_json::<Vec<u8>>(&url, None, None).await.unwrap();
+        assert_eq!(list, [1, 2, 3]);
+        assert_eq!(std::fs::read(&cache_file).unwrap(), before);
+
+        let list = get_json::<Vec<u8>>(&url, Some(&cache_file), None)
+            .await
+            .unwrap();
+        assert_eq!(list, [4]);
+    }
+
+    #[tokio::test]
+    async fn refetch_old_cache_format() {
+        use rand::distributions::{Alphanumeric, DistString};
+
+        #[derive(Serialize)]
+        struct CachedResponseV0 {
+            data: Vec<u8>,
+            expires: SystemTime,
+        }
+
+        let base = serve(&[("/list.json", 200, "", "[1, 2, 3]")]).await;
+        let url = format!("{base}/list.json");
+
+        let suf: String = Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
+        let cache_file = std::env::temp_dir().join(format!("mcdl-cache-{suf}.mpk"));
+        scopeguard::defer! {
+            let _ = std::fs::remove_file(&cache_file);
+        }
+
+        let old = CachedResponseV0 {
+            data: vec![4],
+            expires: SystemTime::now() + Duration::from_secs(60),
+        };
+        std::fs::write(&cache_file, rmp_serde::to_vec(&old).unwrap()).unwrap();
+
+        let list = get_json::<Vec<u8>>(&url, Some(&cache_file), None)
+            .await
+            .unwrap();
+        assert_eq!(list, [1, 2, 3]);
+
+        // rewritten in the current format, so it's used from now on
+        let list = get_json::<Vec<u8>>(&url, Some(&cache_file), None)
+            .await
+            .unwrap();
+        assert_eq!(list, [1, 2, 3]);
+        assert!(CachedResponse::<Vec<u8>>::from_file(&cache_file)
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn probe_failures() {
+        let base = serve(&[("/ok", 200, "", "ok"), ("/gone", 410, "", "")]).await;
+        let timeout = Duration::from_secs(5);
+
+        assert!(probe(&format!("{base}/ok"), timeout).await.is_ok());
+        assert!(matches!(
+            probe(&format!("{base}/gone"), timeout).await,
+            Err(ProbeError::Status(StatusCode::GONE))
+        ));
+
+        // accepts connections, but never responds
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        assert!(matches!(
+            probe(&url, Duration::from_millis(200)).await,
+            Err(ProbeError::Timeout)
+        ));
+
+        // nothing listening once the listener is dropped
+        drop(listener);
+        assert!(matches!(
+            probe(&url, timeout).await,
+            Err(ProbeError::Connect(_))
+        ));
     }
 
     #[tokio::test]
@@ -126,7 +672,7 @@ mod tests {
             _ => 8,
         };
 
-        let jre = download_jre(&version).await.unwrap();
+        let jre = download_jre(&version, &Progress::hidden()).await.unwrap();
         assert!(!jre.is_empty());
     }
 }
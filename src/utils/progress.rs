@@ -0,0 +1,128 @@
+use std::borrow::Cow;
+use std::time::Duration;
+
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use lazy_static::lazy_static;
+use tracing::debug;
+
+use crate::common::CONFIG;
+use crate::types::config::ProgressMode;
+
+lazy_static! {
+    static ref PB_STYLE: ProgressStyle = ProgressStyle::with_template(
+        "{prefix:.bold.blue.bright} {spinner:.green.bright} {wide_msg}",
+    )
+    .unwrap()
+    .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏-");
+    static ref PB_DOWNLOAD_STYLE: ProgressStyle = ProgressStyle::with_template(
+        "{prefix:.bold.blue.bright} {spinner:.green.bright} {msg} [{bar:30.green.bright}] {bytes}/{total_bytes} ({eta})",
+    )
+    .unwrap()
+    .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏-")
+    .progress_chars("=> ");
+    static ref PB_DOWNLOAD_UNKNOWN_STYLE: ProgressStyle = ProgressStyle::with_template(
+        "{prefix:.bold.blue.bright} {spinner:.green.bright} {msg} {bytes}",
+    )
+    .unwrap()
+    .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏-");
+}
+
+/// Creates a group for multiple [`Progress`] spinners, drawn according to the configured mode
+pub(crate) fn progress_group() -> MultiProgress {
+    match CONFIG.read().progress.resolve() {
+        ProgressMode::Auto => MultiProgress::new(),
+        _ => MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+    }
+}
+
+/// A status spinner that respects the configured [`ProgressMode`]
+///
+/// In plain mode, each message is printed to stderr on its own line instead of being animated
+#[derive(Clone)]
+pub(crate) struct Progress {
+    bar: ProgressBar,
+    prefix: String,
+    mode: ProgressMode,
+}
+
+impl Progress {
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self::with_bar(ProgressBar::new_spinner(), prefix.into())
+    }
+
+    pub fn in_group(group: &MultiProgress, prefix: impl Into<String>) -> Self {
+        Self::with_bar(group.add(ProgressBar::new_spinner()), prefix.into())
+    }
+
+    #[cfg(test)]
+    pub fn hidden() -> Self {
+        Self {
+            bar: ProgressBar::hidden(),
+            prefix: String::new(),
+            mode: ProgressMode::Hidden,
+        }
+    }
+
+    fn with_bar(bar: ProgressBar, prefix: String) -> Self {
+        let mode = CONFIG.read().progress.resolve();
+
+        if mode == ProgressMode::Auto {
+            bar.set_style(PB_STYLE.clone());
+            bar.set_prefix(prefix.clone());
+            bar.enable_steady_tick(Duration::from_millis(100));
+        } else {
+            bar.set_draw_target(ProgressDrawTarget::hidden());
+        }
+
+        Self { bar, prefix, mode }
+    }
+
+    pub fn set_message(&self, msg: impl Into<Cow<'static, str>>) {
+        let msg = msg.into();
+        self.report(&msg);
+        self.bar.set_message(msg);
+    }
+
+    pub fn finish_with_message(&self, msg: impl Into<Cow<'static, str>>) {
+        let msg = msg.into();
+        self.report(&msg);
+        self.bar.finish_with_message(msg);
+    }
+
+    /// Shows the progress of a download of `len` bytes, or just the bytes so far if unknown
+    ///
+    /// Advance it with [`Progress::inc`], and go back to a spinner with [`Progress::end_download`]
+    pub fn start_download(&self, len: Option<u64>) {
+        self.bar.set_position(0);
+        self.bar.set_length(len.unwrap_or(0));
+        if self.mode == ProgressMode::Auto {
+            self.bar.set_style(match len {
+                Some(_) => PB_DOWNLOAD_STYLE.clone(),
+                None => PB_DOWNLOAD_UNKNOWN_STYLE.clone(),
+            });
+        }
+    }
+
+    pub fn inc(&self, bytes: u64) {
+        self.bar.inc(bytes);
+    }
+
+    pub fn end_download(&self) {
+        debug!(
+            prefix = self.prefix,
+            bytes = self.bar.position(),
+            "Download finished"
+        );
+        if self.mode == ProgressMode::Auto {
+            self.bar.set_style(PB_STYLE.clone());
+        }
+    }
+
+    fn report(&self, msg: &str) {
+        debug!(prefix = self.prefix, "{msg}");
+
+        if self.mode == ProgressMode::Plain {
+            eprintln!("{}: {msg}", self.prefix);
+        }
+    }
+}
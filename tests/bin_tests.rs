@@ -1,5 +1,132 @@
+use std::ffi::OsStr;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
 use assert_cmd::Command;
 use predicates::prelude::*;
+use rand::distributions::{Alphanumeric, DistString};
+
+/// A directory under the temporary directory, removed with everything in it when dropped
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(prefix: &str) -> Self {
+        let suf: String = Alphanumeric.sample_string(&mut rand::thread_rng(), 5);
+        let dir = std::env::temp_dir().join(format!("{prefix}-{suf}"));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<OsStr> for TempDir {
+    fn as_ref(&self) -> &OsStr {
+        self.0.as_os_str()
+    }
+}
+
+/// An empty `MCDL_HOME`, so a test doesn't see or change the real instances
+fn temp_home() -> TempDir {
+    TempDir::new("mcdl-home")
+}
+
+/// A version manifest listing `versions` as `(id, type)`, newest first
+///
+/// The metadata URLs are empty, so anything that needs the version metadata fails
+fn manifest_json(versions: &[(&str, &str)]) -> String {
+    let latest = |kind: &str| {
+        versions
+            .iter()
+            .find(|(_, t)| kind == "snapshot" || *t == kind)
+            .map_or("", |(id, _)| id)
+    };
+    let entries = versions
+        .iter()
+        .enumerate()
+        .map(|(i, (id, kind))| {
+            // released a day apart, in the order given
+            let time = format!("2023-01-{:02}T12:00:00+00:00", versions.len() - i);
+            format!(
+                r#"{{"id": "{id}", "type": "{kind}", "url": "", "time": "{time}", "releaseTime": "{time}"}}"#
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",\n");
+
+    format!(
+        r#"{{"latest": {{"release": "{}", "snapshot": "{}"}}, "versions": [{entries}]}}"#,
+        latest("release"),
+        latest("snapshot")
+    )
+}
+
+/// Serves the files from `files`, by URL path, until the test exits, returning the base URL
+///
+/// `files` is given the base URL, so files can link to each other
+fn serve(files: impl FnOnce(&str) -> Vec<(String, Vec<u8>)>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let files = files(&base);
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let path = line
+                .split_whitespace()
+                .nth(1)
+                .unwrap_or_default()
+                .to_string();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+
+            match files.iter().find(|(p, _)| *p == path) {
+                Some((_, body)) => {
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        body.len()
+                    )
+                    .unwrap();
+                    stream.write_all(body).unwrap();
+                }
+                None => write!(
+                    stream,
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                )
+                .unwrap(),
+            }
+        }
+    });
+
+    base
+}
+
+/// Writes [`manifest_json`] to a file for `--manifest-file`, which is removed with the returned guard
+fn temp_manifest(versions: &[(&str, &str)]) -> (PathBuf, impl Drop) {
+    let dir = TempDir::new("mcdl-manifest");
+    let path = dir.join("manifest.json");
+    std::fs::write(&path, manifest_json(versions)).unwrap();
+    (path, dir)
+}
 
 #[test]
 fn test_help() {
@@ -17,6 +144,22 @@ fn test_version() {
     cmd.assert()
         .success()
         .stdout(predicate::str::contains(env!("CARGO_PKG_VERSION")));
+
+    // the short form is a single line, for scripts
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.arg("-V");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("commit:").not());
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.arg("--version");
+    cmd.assert().success().stdout(
+        predicate::str::is_match(r"\ncommit: \w+\n")
+            .unwrap()
+            .and(predicate::str::is_match(r"\nrustc channel: (stable|beta|nightly|dev)\n").unwrap())
+            .and(predicate::str::contains("\ntarget: ")),
+    );
 }
 
 #[test]
@@ -39,13 +182,108 @@ fn test_list_filter() {
     );
 }
 
+#[test]
+fn test_list_format() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.arg("list").arg("--format").arg("csv");
+    cmd.assert().success().stdout(
+        predicate::str::starts_with("id,type,release_time\n")
+            .and(predicate::str::contains("\n1.19.4,release,2023-03-14T")),
+    );
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.arg("list").arg("--format").arg("tsv");
+    cmd.assert().success().stdout(
+        predicate::str::starts_with("id\ttype\trelease_time\n")
+            .and(predicate::str::contains("\n1.19.4\trelease\t2023-03-14T")),
+    );
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.arg("list").arg("--format").arg("json");
+    cmd.assert().success().stdout(
+        predicate::str::starts_with("[").and(predicate::str::contains(
+            "\"id\": \"1.19.4\",\n    \"type\": \"release\"",
+        )),
+    );
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.arg("list")
+        .arg("--plain-ids")
+        .arg("--color")
+        .arg("always");
+    cmd.assert().success().stdout(
+        predicate::str::contains("\n1.19.4\n")
+            .and(predicate::str::is_match(r"^[^\s\x1b]+(\n[^\s\x1b]+)*\n$").unwrap()),
+    );
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.arg("list")
+        .arg("--plain-ids")
+        .arg("--format")
+        .arg("csv");
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_list_tree() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.arg("list").arg("--all").arg("--tree");
+    cmd.assert().success().stdout(
+        predicate::str::contains("\n1.19\n")
+            .and(predicate::str::contains("─ 1.19.4\n"))
+            .and(predicate::str::contains("─ 1.19.4-rc1\n"))
+            .and(predicate::str::contains("\nSnapshots 2023\n")),
+    );
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.arg("list").arg("--tree").arg("--installed");
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_list_detailed() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.arg("list").arg("--release").arg("--detailed");
+    cmd.assert().success().stdout(
+        predicate::str::contains("Release Date")
+            .and(predicate::str::is_match(r"\n 1\.19\.4 +release ").unwrap()),
+    );
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.arg("list").arg("--release");
+    cmd.assert().success().stdout(
+        predicate::str::contains("\n1.19.4\n").and(predicate::str::contains("release").not()),
+    );
+}
+
 #[test]
 fn test_info() {
     let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
     cmd.arg("info").arg("--version").arg("1.19.4");
     cmd.assert().success().stdout(
         predicate::str::contains("Version 1.19.4 (release)")
-            .and(predicate::str::contains("Released: 14 March 2023")),
+            .and(predicate::str::contains("Released: 14 March 2023"))
+            .and(predicate::str::is_match(r"\nDownloads:\n  server +\d+\.\d MiB\n").unwrap())
+            .and(predicate::str::contains("\n  total ")),
+    );
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.arg("info").arg("--version").arg("1.19.4").arg("--sha");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::is_match(r"\n  server +\d+\.\d MiB +sha1 [0-9a-f]{40}\n").unwrap());
+}
+
+#[test]
+fn test_info_compare() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.args(["info", "--version", "1.17.1", "--compare", "1.18"]);
+    cmd.assert().success().stdout(
+        predicate::str::contains("1.17.1")
+            .and(predicate::str::contains("server"))
+            .and(predicate::str::contains(
+                "The required Java version changes from 16 to 17",
+            )),
     );
 }
 
@@ -74,3 +312,739 @@ fn test_locate_config() {
         }
     }
 }
+
+#[test]
+fn test_jre_list() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.arg("jre").arg("list");
+    cmd.assert().success().stdout(
+        predicate::str::contains("No instances installed")
+            .or(predicate::str::contains("Installed")),
+    );
+}
+
+#[test]
+fn test_open_missing_instance() {
+    let home = temp_home();
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("open")
+        .arg("not-an-instance")
+        .arg("--shell");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "Instance `not-an-instance` does not exist",
+    ));
+}
+
+#[test]
+fn test_manifest_file() {
+    let home = temp_home();
+    let (path, _manifest) = temp_manifest(&[("23w13a", "snapshot"), ("1.19.4", "release")]);
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("list")
+        .arg("--all")
+        .arg("--manifest-file")
+        .arg(&path);
+    cmd.assert().success().stdout("1.19.4\n23w13a\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg(format!("--manifest-file={}", path.display()))
+        .arg("info")
+        .arg("--version")
+        .arg("1.19.4");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Version 1.19.4 (release)"));
+
+    std::fs::write(&path, "{}").unwrap();
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("list")
+        .arg("--manifest-file")
+        .arg(&path);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Invalid manifest file"));
+}
+
+#[test]
+fn test_manifest_url() {
+    let home = temp_home();
+
+    // serves the manifest once, so a second request would fail
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/manifest.json", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let body = manifest_json(&[("23w13a", "snapshot"), ("1.19.4", "release")]);
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = String::new();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        while reader.read_line(&mut request).unwrap() > 2 {
+            request.clear();
+        }
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+    });
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("list")
+        .arg("--all")
+        .arg("--manifest-url")
+        .arg(&url);
+    cmd.assert().success().stdout("1.19.4\n23w13a\n");
+    server.join().unwrap();
+
+    // cached for this URL, so the server isn't needed anymore
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .env("MCDL_MANIFEST_URL", &url)
+        .arg("list")
+        .arg("--all");
+    cmd.assert().success().stdout("1.19.4\n23w13a\n");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("list")
+        .arg("--manifest-url")
+        .arg("file:///tmp/manifest.json");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("must be an http or https URL"));
+
+    // from the config or environment, neither would be clearly the one used
+    let (path, _manifest) = temp_manifest(&[("1.19.4", "release")]);
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .env("MCDL_MANIFEST_URL", &url)
+        .env("MCDL_MANIFEST_FILE", &path)
+        .arg("list");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("can't both be set"));
+
+    // a flag replaces both
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .env("MCDL_MANIFEST_URL", &url)
+        .arg("list")
+        .arg("--manifest-file")
+        .arg(&path);
+    cmd.assert().success().stdout("1.19.4\n");
+}
+
+#[test]
+fn test_install_duplicate_versions() {
+    let home = temp_home();
+    // the metadata URL is invalid, so nothing is installed
+    let (path, _manifest) = temp_manifest(&[("1.19.4", "release")]);
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("--manifest-file")
+        .arg(&path)
+        .arg("install")
+        .arg("--accept-eula")
+        .arg("--version")
+        .arg("1.19.4,1.19.4");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Ignoring 1 duplicate version\n"));
+
+    // still a single version, so options for one instance are allowed
+    let output = TempDir::new("mcdl-output");
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("--manifest-file")
+        .arg(&path)
+        .arg("install")
+        .arg("--accept-eula")
+        .arg("--name")
+        .arg("x")
+        .arg("--output-dir")
+        .arg(&*output)
+        .arg("--version")
+        .arg("1.19.4,1.19.4");
+    cmd.assert().failure().stderr(
+        predicate::str::contains("Ignoring 1 duplicate version\n")
+            .and(predicate::str::contains("single version").not()),
+    );
+}
+
+#[test]
+fn test_install_keep_going_metadata() {
+    let home = temp_home();
+    // neither metadata URL is valid, so both versions fail before anything is downloaded
+    let (manifest, _manifest) = temp_manifest(&[("1.19.4", "release"), ("1.19.3", "release")]);
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("--manifest-file")
+        .arg(&manifest)
+        .arg("install")
+        .arg("--accept-eula")
+        .arg("--keep-going")
+        .arg("--version")
+        .arg("1.19.4,1.19.3");
+    cmd.assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "  1.19.4: Failed to install 1.19.4",
+        ))
+        .stdout(predicate::str::contains(
+            "  1.19.3: Failed to install 1.19.3",
+        ))
+        .stderr(predicate::str::contains("2 of 2 installs failed"));
+}
+
+#[test]
+fn test_install_output_dir_not_empty() {
+    let home = temp_home();
+    let (manifest, _manifest) = temp_manifest(&[("1.19.4", "release")]);
+    let output = TempDir::new("mcdl-output");
+    // uninstalling would remove the whole directory, along with files that aren't mcdl's
+    std::fs::write(output.join("notes.txt"), "mine").unwrap();
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("--manifest-file")
+        .arg(&manifest)
+        .arg("install")
+        .arg("--accept-eula")
+        .arg("--output-dir")
+        .arg(&*output)
+        .arg("--version")
+        .arg("1.19.4");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("is not empty"));
+    assert_eq!(
+        std::fs::read_to_string(output.join("notes.txt")).unwrap(),
+        "mine"
+    );
+}
+
+#[test]
+fn test_log_file() {
+    let home = temp_home();
+    let dir = TempDir::new("mcdl-logs");
+    let path = dir.join("mcdl.log");
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("--color")
+        .arg("always")
+        .arg("--log-file")
+        .arg(&path)
+        .arg("locate")
+        .arg("config");
+    cmd.assert().success();
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    assert!(log.contains("Args: "), "{log}");
+    assert!(!log.contains('\x1b'), "{log}");
+}
+
+#[test]
+fn test_log_file_rotation_requires_log_file() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.arg("--log-file-rotation")
+        .arg("daily")
+        .arg("locate")
+        .arg("config");
+    cmd.assert().failure();
+}
+
+#[test]
+fn test_use_missing_instance() {
+    let home = temp_home();
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("use")
+        .arg("not-an-instance");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "Instance `not-an-instance` does not exist",
+    ));
+}
+
+#[test]
+fn test_run_duplicate_instances() {
+    let home = temp_home();
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("run")
+        .arg("--version")
+        .arg("1.0,1.0.0");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "Instance `1.0` was given more than once",
+    ));
+}
+
+#[test]
+fn test_mcdl_home() {
+    let home = temp_home();
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home).arg("locate").arg("config");
+    cmd.assert().success().stdout(predicate::str::contains(
+        home.join("config").to_str().unwrap(),
+    ));
+}
+
+#[test]
+fn test_max_term_width() {
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.arg("--max-term-width")
+        .arg("60")
+        .arg("install")
+        .arg("--help");
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let help = String::from_utf8(output).unwrap();
+    assert!(
+        help.lines().all(|line| line.chars().count() <= 60),
+        "{help}"
+    );
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("COLUMNS", "60").arg("install").arg("--help");
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let help = String::from_utf8(output).unwrap();
+    assert!(
+        help.lines().all(|line| line.chars().count() <= 60),
+        "{help}"
+    );
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.arg("--max-term-width")
+        .arg("300")
+        .arg("install")
+        .arg("--help");
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let help = String::from_utf8(output).unwrap();
+    assert!(
+        help.lines().any(|line| line.chars().count() > 100),
+        "{help}"
+    );
+}
+
+#[test]
+fn test_version_surrounding_whitespace() {
+    let home = temp_home();
+    let (path, _manifest) = temp_manifest(&[("1.19.4", "release")]);
+
+    for version in [" 1.19.4", "1.19.4 ", "1.19.4\n", "\t1.19.4\r\n"] {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("MCDL_HOME", &home)
+            .arg("--manifest-file")
+            .arg(&path)
+            .arg("info")
+            .arg("--version")
+            .arg(version);
+        cmd.assert()
+            .success()
+            .stdout(predicate::str::contains("Version 1.19.4 (release)"));
+    }
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("use")
+        .arg(" not-an-instance\n");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "Instance `not-an-instance` does not exist",
+    ));
+}
+
+#[test]
+#[cfg(feature = "schema")]
+fn test_schema() {
+    for (what, field) in [("config", "accept_eula"), ("settings", "bundled")] {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.arg("schema").arg(what);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let schema: serde_json::Value = serde_json::from_slice(&output).unwrap();
+        assert!(schema.to_string().contains(field), "{schema}");
+    }
+}
+
+#[test]
+fn test_list_explain() {
+    let home = temp_home();
+    let (path, _manifest) = temp_manifest(&[("1.20-pre1", "snapshot"), ("1.19.4", "release")]);
+
+    for (filter, expected) in [
+        (None, "Result: `1.20-pre1` is not listed"),
+        (Some("--pre-release"), "Result: `1.20-pre1` is listed"),
+    ] {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("MCDL_HOME", &home)
+            .arg("--manifest-file")
+            .arg(&path)
+            .arg("list")
+            .arg("--explain")
+            .arg("1.20-pre1")
+            .args(filter);
+        cmd.assert().success().stdout(
+            predicate::str::contains("with type `snapshot`")
+                .and(predicate::str::contains("is a pre-release by its ID"))
+                .and(predicate::str::contains(expected)),
+        );
+    }
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("--manifest-file")
+        .arg(&path)
+        .arg("list")
+        .arg("--explain")
+        .arg("1.19.5");
+    cmd.assert().success().stdout(
+        predicate::str::contains("`1.19.5` is not in the version manifest")
+            .and(predicate::str::contains("Similar versions: 1.19.4")),
+    );
+}
+
+#[test]
+fn test_tags() {
+    let home = temp_home();
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("tag")
+        .arg("1.19.4")
+        .arg("add")
+        .arg("prod,test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Instance `1.19.4` does not exist"));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("tag")
+        .arg("1.19.4")
+        .arg("add")
+        .arg("two words");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Tag `two words` can only contain"));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("run")
+        .arg("--tag")
+        .arg("test");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("No instances are tagged `test`"));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("list")
+        .arg("--tag")
+        .arg("prod");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--installed"));
+}
+
+#[test]
+fn test_tags_installed() {
+    use sha1::{Digest, Sha1};
+
+    let home = temp_home();
+    // the server jar is never started, so it doesn't need to be real
+    let jar = b"not a server".to_vec();
+    let sha1 = hex::encode(Sha1::digest(&jar));
+    let mut manifest = String::new();
+    serve(|base| {
+        manifest = manifest_json(&[("1.19.4", "release")])
+            .replace(r#""url": """#, &format!(r#""url": "{base}/1.19.4.json""#));
+        let metadata = format!(
+            r#"{{"id": "1.19.4", "javaVersion": {{"majorVersion": 17}}, "downloads": {{"server": {{"sha1": "{sha1}", "size": {}, "url": "{base}/server.jar"}}}}}}"#,
+            jar.len()
+        );
+        vec![
+            ("/1.19.4.json".to_string(), metadata.into_bytes()),
+            ("/server.jar".to_string(), jar),
+        ]
+    });
+    let manifest_dir = TempDir::new("mcdl-manifest");
+    let manifest_path = manifest_dir.join("manifest.json");
+    std::fs::write(&manifest_path, manifest).unwrap();
+
+    let mcdl = |args: &[&str]| {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("MCDL_HOME", &home)
+            .arg("--manifest-file")
+            .arg(&manifest_path)
+            .args(args);
+        cmd.assert()
+    };
+    // sorted, since instances are listed in no particular order
+    let listed = |tags: &str| {
+        let output = mcdl(&["list", "--installed", "--plain-ids", "--tag", tags])
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        let mut names: Vec<_> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        names.sort();
+        names
+    };
+    let started = |tags: &str| {
+        let output = mcdl(&["run", "--tag", tags, "--print-command"])
+            .success()
+            .get_output()
+            .stdout
+            .clone();
+        String::from_utf8(output).unwrap().lines().count()
+    };
+
+    for name in ["a", "b"] {
+        mcdl(&[
+            "install",
+            "--accept-eula",
+            "--no-interactive",
+            "--skip-jre",
+            "--name",
+            name,
+            "--version",
+            "1.19.4",
+        ])
+        .success();
+    }
+
+    mcdl(&["tag", "a", "add", "prod,shared"])
+        .success()
+        .stdout("`a`: prod, shared\n");
+    mcdl(&["tag", "b", "add", "test,shared"]).success();
+    // read back by a new process, so the tags were saved
+    mcdl(&["tag", "a"]).success().stdout("`a`: prod, shared\n");
+
+    assert_eq!(listed("prod"), ["a"]);
+    assert_eq!(listed("test"), ["b"]);
+    assert_eq!(listed("shared"), ["a", "b"]);
+    assert_eq!(started("prod"), 1);
+    assert_eq!(started("prod,test"), 2);
+    mcdl(&[
+        "list",
+        "--installed",
+        "--tag",
+        "prod",
+        "--explain",
+        "1.19.4",
+    ])
+    .success()
+    .stdout(predicate::str::contains(
+        "--tag keeps the instances tagged `prod`: a",
+    ));
+
+    mcdl(&["tag", "a", "remove", "prod"])
+        .success()
+        .stdout("`a`: shared\n");
+    mcdl(&["tag", "a"]).success().stdout("`a`: shared\n");
+    assert!(listed("prod").is_empty());
+    mcdl(&["run", "--tag", "prod", "--print-command"])
+        .failure()
+        .stderr(predicate::str::contains("No instances are tagged `prod`"));
+    mcdl(&[
+        "list",
+        "--installed",
+        "--tag",
+        "prod",
+        "--explain",
+        "1.19.4",
+    ])
+    .success()
+    .stdout(predicate::str::contains(
+        "--tag hides it, as no instance using it is tagged `prod`",
+    ));
+}
+
+#[test]
+fn test_install_from_stdin() {
+    let home = temp_home();
+    let (path, _manifest) = temp_manifest(&[("1.19.4", "release")]);
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("--manifest-file")
+        .arg(&path)
+        .arg("install")
+        .arg("--accept-eula")
+        .arg("-")
+        .write_stdin("# versions to install\n1.19.4\n\n1.19.5 # typo\n");
+    cmd.assert().failure().stderr(
+        predicate::str::contains("Invalid versions on stdin")
+            .and(predicate::str::contains(
+                "line 4: Version `1.19.5` does not exist",
+            ))
+            .and(predicate::str::contains("line 1").not()),
+    );
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("--manifest-file")
+        .arg(&path)
+        .arg("install")
+        .arg("-")
+        .arg("--version")
+        .arg("1.19.4");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_outdated() {
+    let home = temp_home();
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home).arg("outdated").arg("--major");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("No instances installed"));
+}
+
+#[test]
+fn test_install_client_args() {
+    let home = temp_home();
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("install")
+        .arg("--client")
+        .arg("--accept-eula")
+        .arg("--no-interactive");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("install")
+        .arg("--client-libraries")
+        .arg("--no-interactive");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--client"));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("install")
+        .arg("--client")
+        .arg("--assets")
+        .arg("--no-interactive");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("--client-libraries"));
+}
+
+#[test]
+fn test_install_bind_address() {
+    let home = temp_home();
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("install")
+        .arg("--bind")
+        .arg("not-an-address")
+        .arg("--no-interactive");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("invalid value 'not-an-address'"));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("install")
+        .arg("--client")
+        .arg("--ipv6")
+        .arg("--no-interactive");
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot be used with"));
+}
+
+#[test]
+fn test_clone_missing_instance() {
+    let home = temp_home();
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("clone")
+        .arg("missing")
+        .arg("copy")
+        .arg("--no-world");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "Instance `missing` does not exist",
+    ));
+}
+
+#[test]
+fn test_status() {
+    let home = temp_home();
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home).arg("status");
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("No instances installed"));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home).arg("status").arg("--json");
+    cmd.assert().success().stdout(predicate::str::diff("[]\n"));
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home).arg("status").arg("missing");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "Instance `missing` does not exist",
+    ));
+}
+
+#[test]
+fn test_info_installed() {
+    let home = temp_home();
+
+    let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    cmd.env("MCDL_HOME", &home)
+        .arg("info")
+        .arg("--installed")
+        .arg("missing");
+    cmd.assert().failure().stderr(predicate::str::contains(
+        "Instance `missing` does not exist",
+    ));
+}
+
+#[test]
+fn test_list_channel() {
+    let home = temp_home();
+    let (path, _manifest) = temp_manifest(&[
+        ("1.20-pre1", "snapshot"),
+        ("23w18a", "snapshot"),
+        ("1.19.4", "release"),
+    ]);
+
+    for (args, expected) in [
+        (vec!["--channel", "snapshot"], "23w18a\n1.20-pre1\n"),
+        (vec!["--channel", "all"], "1.19.4\n23w18a\n1.20-pre1\n"),
+        // the type flags override the channel
+        (vec!["--channel", "snapshot", "-r"], "1.19.4\n"),
+    ] {
+        let mut cmd = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+        cmd.env("MCDL_HOME", &home)
+            .arg("--manifest-file")
+            .arg(&path)
+            .arg("list")
+            .arg("--plain-ids")
+            .args(&args);
+        cmd.assert().success().stdout(expected);
+    }
+}